@@ -0,0 +1,6 @@
+package com.noxcipher
+
+interface FormatProgressCallback {
+    // Called from the formatting thread with bytes done and the total; keep it cheap.
+    fun onProgress(done: Long, total: Long)
+}
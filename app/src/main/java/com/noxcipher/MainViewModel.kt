@@ -306,6 +306,7 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
                     protectionPim,
                     candidate.volumeSize,
                     headerPlan.backupHeaderBytes,
+                    null,
                 )
 
                 if (handle > 0) {
@@ -333,10 +334,10 @@ class MainViewModel(application: Application) : AndroidViewModel(application) {
         } catch (e: Exception) {
             val callback = FileSystemReadCallback(veracryptDriver)
             val fsHandle = RustNative.mountFs(volumeHandle, callback, candidate.volumeSize)
-            if (fsHandle > 0) {
-                RustFileSystem(fsHandle, context.getString(R.string.root_title))
-            } else {
-                throw IOException(context.getString(R.string.error_fs_detection))
+            when {
+                fsHandle > 0 -> RustFileSystem(fsHandle, context.getString(R.string.root_title))
+                fsHandle == RustNative.MOUNT_ERR_IO -> throw IOException(context.getString(R.string.error_fs_io))
+                else -> throw IOException(context.getString(R.string.error_fs_detection))
             }
         }
     }
@@ -533,5 +534,16 @@ class FileSystemReadCallback(private val driver: BlockDeviceDriver) : NativeRead
             -1
         }
     }
+
+    override fun write(offset: Long, buffer: java.nio.ByteBuffer): Int {
+        return try {
+            val start = buffer.position()
+            driver.write(offset, buffer)
+            buffer.position() - start
+        } catch (e: Exception) {
+            android.util.Log.e("MainViewModel", "Error writing to physical driver", e)
+            -1
+        }
+    }
 }
 
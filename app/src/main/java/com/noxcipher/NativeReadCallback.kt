@@ -2,4 +2,10 @@ package com.noxcipher
     
 interface NativeReadCallback {
     fun read(offset: Long, buffer: java.nio.ByteBuffer): Int
+
+    // Writes the buffer's remaining bytes at offset. Returns bytes written or -1; read-only sources keep the default.
+    fun write(offset: Long, buffer: java.nio.ByteBuffer): Int = -1
+
+    // Makes previous writes durable (e.g. FileChannel.force). Called by syncFs; returns false on failure.
+    fun flush(): Boolean = true
 }
@@ -3,5 +3,11 @@ package com.noxcipher
 data class RustFile(
     val name: String,
     val isDir: Boolean,
-    val size: Long
+    val size: Long,
+    // Last modification time in seconds since the Unix epoch, or -1 if unknown.
+    val modifiedTime: Long = -1,
+    // Creation time in seconds since the Unix epoch, or -1 if unknown.
+    val createdTime: Long = -1,
+    // FAT/Windows attribute bits (read-only 0x01, hidden 0x02, system 0x04, directory 0x10, archive 0x20).
+    val attributes: Int = 0
 )
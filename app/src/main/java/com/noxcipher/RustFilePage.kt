@@ -0,0 +1,9 @@
+package com.noxcipher
+
+// One page of a directory listing from RustNative.listFilesPaged.
+class RustFilePage(
+    // Entries on this page, in listing order.
+    val files: Array<RustFile>,
+    // Number of entries in the whole directory.
+    val totalCount: Long
+)
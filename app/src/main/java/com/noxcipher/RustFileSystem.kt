@@ -81,7 +81,7 @@ class RustUsbFile(
         if (destination.isDirect) {
             val position = destination.position()
             val read = RustNative.readFileDirect(fsHandle, path, offset, destination, position, len)
-            if (read < 0) throw IOException("Native read failed")
+            if (read < 0) throw IOException("Native read failed: ${RustNative.getLastError()}")
             if (read > 0) destination.position(position + read.toInt())
             return
         }
@@ -91,7 +91,7 @@ class RustUsbFile(
             val array = destination.array()
             val arrayOffset = destination.arrayOffset() + destination.position()
             val read = RustNative.readFileArray(fsHandle, path, offset, array, arrayOffset, len)
-            if (read < 0) throw IOException("Native read failed")
+            if (read < 0) throw IOException("Native read failed: ${RustNative.getLastError()}")
             if (read > 0) destination.position(destination.position() + read.toInt())
             return
         }
@@ -101,7 +101,7 @@ class RustUsbFile(
         // Call native readFile.
         val read = RustNative.readFile(fsHandle, path, offset, buffer)
         if (read < 0) {
-            throw IOException("Native read failed")
+            throw IOException("Native read failed: ${RustNative.getLastError()}")
         }
         // Copy read data to destination buffer.
         if (read > 0) {
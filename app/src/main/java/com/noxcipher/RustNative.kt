@@ -8,6 +8,49 @@ object RustNative {
     var isInitialized = false
         private set
 
+    // mountFs failure codes (mirrors MOUNT_ERR_* in lib.rs); like every negative error return,
+    // each is the matching ERR_* code negated.
+    const val MOUNT_ERR_GENERIC = -1L
+    const val MOUNT_ERR_NO_FILESYSTEM = -13L
+    const val MOUNT_ERR_IO = -5L
+    // pollMount result while a background mount is still running.
+    const val MOUNT_PENDING = 0L
+
+    // initEx failure codes (mirrors VolumeError::code in volume.rs), the ERR_* codes negated.
+    const val INIT_ERR_INVALID_PASSWORD = -2L
+    const val INIT_ERR_INVALID_HEADER = -3L
+    const val INIT_ERR_CRYPTO = -4L
+    const val INIT_ERR_IO = -5L
+    // The password decrypted a header that then failed a check; the backup header may still be intact.
+    const val INIT_ERR_HEADER_CRC = -6L
+    const val INIT_ERR_KEY_AREA_CRC = -7L
+    const val INIT_ERR_UNSUPPORTED_VERSION = -8L
+    const val INIT_ERR_HEADER_LAYOUT = -9L
+
+    // verifyVolume results (mirrors VERIFY_* in lib.rs); any value >= 0 is a bad offset.
+    const val VERIFY_CLEAN = -1L
+    const val VERIFY_CANCELLED = -2L
+    const val VERIFY_ERROR = -3L
+
+    // getLastErrorCode values (mirrors NoxError in error.rs). Functions that return a negative
+    // error code return one of these negated.
+    const val ERR_NONE = 0
+    const val ERR_OTHER = 1
+    const val ERR_INVALID_PASSWORD = 2
+    const val ERR_INVALID_MAGIC = 3
+    const val ERR_CRYPTO = 4
+    const val ERR_IO = 5
+    const val ERR_HEADER_CRC = 6
+    const val ERR_KEY_AREA_CRC = 7
+    const val ERR_UNSUPPORTED_VERSION = 8
+    const val ERR_INVALID_HEADER_LAYOUT = 9
+    const val ERR_HIDDEN_VOLUME_PROTECTION = 10
+    const val ERR_HEADER_TOO_SHORT = 11
+    const val ERR_INVALID_ARGUMENT = 12
+    const val ERR_MOUNT_FAILED = 13
+    const val ERR_INTERNAL = 14
+    const val ERR_NOT_INITIALIZED = 15
+
     // Static initialization block to load the library.
     init {
         try {
@@ -50,6 +93,28 @@ object RustNative {
      */
     external fun getLogs(): Array<String>
 
+    /**
+     * Returns the message of the most recent native failure, whether it was thrown as an exception
+     * or only reported through an error return value (e.g. -1 or a MOUNT_ERR_* code), on the calling
+     * thread, together with [getLastErrorCode]. Never contains passwords or key material.
+     * @return The message, or an empty string if nothing has failed yet.
+     */
+    external fun getLastError(): String
+
+    /**
+     * Returns the code of the most recent native failure on the calling thread, thrown or not.
+     * Unlike [getLastError], the codes are stable, so callers can tell e.g. a wrong password
+     * from an I/O error without parsing messages.
+     * @return One of the ERR_* constants; [ERR_NONE] if nothing has failed on this thread.
+     */
+    external fun getLastErrorCode(): Int
+
+    /**
+     * Enables detailed (per-header, per-cipher) reasons in failed mount errors.
+     * Off by default in release builds, where failures only report "Invalid password or PIM".
+     */
+    external fun setDebugErrors(enabled: Boolean)
+
     /**
      * Initializes the Veracrypt volume.
      * @param password The password bytes.
@@ -60,6 +125,7 @@ object RustNative {
      * @param protectionPim The protection PIM value (0 for default).
      * @param volumeSize Total volume size to help validate offsets.
      * @param backupHeader Optional backup header buffer when available.
+     * @param keyfiles Optional keyfile contents (each at most 1 MB), mixed into the password.
      * @return A handle to the native context, or throws exception.
      */
     external fun init(
@@ -71,9 +137,157 @@ object RustNative {
         protectionPassword: ByteArray?, 
         protectionPim: Int,
         volumeSize: Long,
+        backupHeader: ByteArray?,
+        keyfiles: Array<ByteArray>?
+    ): Long
+
+    /**
+     * Same as [init], but reports mount failures as a negative code instead of throwing:
+     * [INIT_ERR_INVALID_PASSWORD], [INIT_ERR_INVALID_HEADER], [INIT_ERR_CRYPTO], [INIT_ERR_IO],
+     * [INIT_ERR_HEADER_CRC], [INIT_ERR_KEY_AREA_CRC], [INIT_ERR_UNSUPPORTED_VERSION] or [INIT_ERR_HEADER_LAYOUT].
+     * Invalid arguments still throw IllegalArgumentException and return -1.
+     * @return A handle to the native context (> 0), or a negative error code.
+     */
+    external fun initEx(
+        password: ByteArray,
+        header: ByteArray,
+        pim: Int,
+        partitionOffset: Long,
+        headerOffset: Long,
+        protectionPassword: ByteArray?,
+        protectionPim: Int,
+        volumeSize: Long,
+        backupHeader: ByteArray?,
+        keyfiles: Array<ByteArray>?
+    ): Long
+
+    /**
+     * Same as [init], but the returned context rejects every write, so a backup snapshot can be
+     * browsed without any risk of modifying it.
+     * @return A handle to the native context, or throws exception.
+     */
+    external fun initReadOnly(
+        password: ByteArray,
+        header: ByteArray,
+        pim: Int,
+        partitionOffset: Long,
+        headerOffset: Long,
+        protectionPassword: ByteArray?,
+        protectionPim: Int,
+        volumeSize: Long,
+        backupHeader: ByteArray?,
+        keyfiles: Array<ByteArray>?
+    ): Long
+
+    /**
+     * Same as [init], but first tries only the given cipher and PRF, which skips the slow
+     * search over every combination. Falls back to the full search if the hint does not match.
+     * @param cipherHint Cipher id as in [formatVolume], or -1 for no hint.
+     * @param prfHint PRF id as in [formatVolume], or -1 for no hint.
+     * @return A handle to the native context, or throws exception.
+     */
+    external fun initHinted(
+        password: ByteArray,
+        header: ByteArray,
+        pim: Int,
+        partitionOffset: Long,
+        headerOffset: Long,
+        protectionPassword: ByteArray?,
+        protectionPim: Int,
+        volumeSize: Long,
+        backupHeader: ByteArray?,
+        keyfiles: Array<ByteArray>?,
+        cipherHint: Int,
+        prfHint: Int
+    ): Long
+
+    /**
+     * Initializes the Veracrypt volume with keyfiles, without partition offsets or hidden volume protection.
+     * @param password The password bytes.
+     * @param header The first 128KB of the volume (containing the header).
+     * @param pim The PIM value (0 for default).
+     * @param keyfiles Keyfile contents (each at most 1 MB), mixed into the password.
+     * @param volumeSize Total volume size to help validate offsets.
+     * @param backupHeader Optional backup header buffer when available.
+     * @return A handle to the native context, or throws exception.
+     */
+    external fun initWithKeyfiles(
+        password: ByteArray,
+        header: ByteArray,
+        pim: Int,
+        keyfiles: Array<ByteArray>,
+        volumeSize: Long,
         backupHeader: ByteArray?
     ): Long
 
+    /**
+     * Mounts a container file by path. The primary, hidden and backup headers are read from the
+     * file itself, so the app does not need to extract them. Only available when the native
+     * library is built with the host-file feature.
+     * @param path The path of the container file.
+     * @param password The password bytes.
+     * @param pim The PIM value (0 for default).
+     * @param readOnly True to open the file without write access and reject writes.
+     * @return A handle to the native context, or throws exception.
+     */
+    external fun initFromFile(path: String, password: ByteArray, pim: Int, readOnly: Boolean = false): Long
+
+    /**
+     * Checks a password against a volume header without mounting it (no handle is created).
+     * @param password The password bytes.
+     * @param header The first 128KB of the volume (containing the header).
+     * @param pim The PIM value (0 for default).
+     * @param backupHeader Optional backup header buffer when available.
+     * @return [cipherTypeId, prfId] using the same ids as [formatVolume], or null if the password does not match.
+     */
+    external fun probe(password: ByteArray, header: ByteArray, pim: Int, backupHeader: ByteArray?): IntArray?
+
+    /**
+     * Lists the supported ciphers. The index of each name is its cipherTypeId in [formatVolume].
+     * @return Cipher names such as "AES" or "AES-Twofish-Serpent".
+     */
+    external fun getSupportedCiphers(): Array<String>?
+
+    /**
+     * Master key size in bytes required by each cipher, indexed like [getSupportedCiphers].
+     * @return One key size per cipher.
+     */
+    external fun getCipherKeySizes(): IntArray?
+
+    /**
+     * Lists the supported PRFs. The index of each name is its prfId in [formatVolume] and [changePassword].
+     * Argon2id and BLAKE2b-512 are only usable when the native library was built with the noxcipher-kdf feature.
+     * @return PRF names such as "SHA-512".
+     */
+    external fun getSupportedPrfs(): Array<String>?
+
+    /**
+     * Estimates how many PBKDF2 iterations of a PRF take about [targetMs] on this device, to pick a
+     * PIM for [formatVolume] (iterations = 15000 + PIM * 1000). Mounting is not affected.
+     * @param prfId PRF id, indexed like [getSupportedPrfs]. Argon2id is rejected.
+     * @param targetMs Desired key derivation time in milliseconds.
+     * @return The estimated iteration count, or -1 on error (see [getLastError]).
+     */
+    external fun benchmarkPrf(prfId: Int, targetMs: Int): Int
+
+    /**
+     * Converts a PBKDF2 iteration count (e.g. from [benchmarkPrf]) to the PIM that gives at least
+     * that many iterations. Volumes do not store their iteration count: pass the PIM to
+     * [formatVolume] and enter the same PIM on every mount.
+     * @param iterations Desired iteration count, at least 100000.
+     * @return The PIM, or -1 if the count is too small or too large (see [getLastError]).
+     */
+    external fun pimForIterations(iterations: Int): Int
+
+    /**
+     * Heuristic check (no password) that a buffer could be an encrypted volume header:
+     * at least 512 bytes, no plaintext boot sector or file system signature, and random-looking.
+     * A true result is not a guarantee.
+     * @param header The first bytes of the volume (at least 512, 128KB recommended).
+     * @return True if the buffer looks like an encrypted volume.
+     */
+    external fun looksEncrypted(header: ByteArray): Boolean
+
     /**
      * Decrypts a buffer in-place.
      * @param handle The native context handle.
@@ -87,6 +301,16 @@ object RustNative {
      */
     external fun decryptDirect(handle: Long, offset: Long, buffer: java.nio.ByteBuffer, position: Int, length: Int)
 
+    /**
+     * Decrypts the first [length] bytes of a direct ByteBuffer in-place with a single native call.
+     * Prefer this over per-block [decrypt] calls for large sequential reads.
+     * @param handle The native context handle.
+     * @param offset The absolute byte offset of the range (sector aligned).
+     * @param length The number of bytes to decrypt (multiple of the sector size).
+     * @param buffer A direct ByteBuffer holding the encrypted range.
+     */
+    external fun decryptRange(handle: Long, offset: Long, length: Long, buffer: java.nio.ByteBuffer)
+
     /**
      * Encrypts a buffer in-place.
      * @param handle The native context handle.
@@ -115,14 +339,106 @@ object RustNative {
     external fun getDataOffset(handle: Long): Long
 
     /**
-     * Mounts the file system (NTFS/exFAT) via Rust.
+     * Gets when the volume was created and when its header (password) was last changed.
+     * @param handle The native context handle.
+     * @return [volumeCreationTime, headerCreationTime] in Unix seconds; throws if the handle is unknown.
+     */
+    external fun getVolumeTimes(handle: Long): LongArray?
+
+    /**
+     * Describes the mounted volume.
+     * @param handle The native context handle.
+     * @return [cipher, prf, sector size, data size, truecrypt], e.g. ["AES-Twofish-Serpent", "SHA-512", "512", "536870912", "false"], or null if the handle is unknown.
+     */
+    external fun getVolumeInfo(handle: Long): Array<String>?
+
+    /**
+     * Describes the decrypted header for debugging, one "name: value" per line
+     * (version, sector size, encrypted area, flags, creation times, ...).
+     * Contains no key material, salt or PIM, so it can be pasted into a bug report.
+     * @param handle The native context handle.
+     * @return The description, or null if the handle is unknown.
+     */
+    external fun dumpHeader(handle: Long): String?
+
+    /**
+     * Checks whether the volume rejects writes (mounted with [initReadOnly]).
+     * @param handle The native context handle.
+     * @return True if read-only; false if writable or the handle is unknown.
+     */
+    external fun isReadOnly(handle: Long): Boolean
+
+    /**
+     * Checks whether hidden volume protection has refused a write since the volume was mounted
+     * (see the protection password of [init]). Once set, the outer volume should be unmounted.
+     * @param handle The native context handle.
+     * @return True if a write was blocked; false otherwise or if the handle is unknown.
+     */
+    external fun isProtectionTriggered(handle: Long): Boolean
+
+    /**
+     * Checks that the mounted header still matches its stored CRC32 values (header fields and key area),
+     * e.g. as a sanity check against memory corruption.
+     * @param handle The native context handle.
+     * @return True if intact; false if corrupted or the handle is unknown (see [getLastError]).
+     */
+    external fun verifyHeader(handle: Long): Boolean
+
+    /**
+     * Mounts the file system (NTFS/exFAT/FAT32) via Rust.
      * @param volumeHandle The handle to the initialized volume.
      * @param callback The callback to read raw data.
      * @param volumeSize The size of the volume in bytes.
-     * @return A handle to the file system, or -1 if failed.
+     * @return A handle to the file system, or a negative error code: usually [MOUNT_ERR_NO_FILESYSTEM] or
+     *         [MOUNT_ERR_IO]; -[ERR_NOT_INITIALIZED] for an unknown volume handle (see [getLastErrorCode]).
      */
     external fun mountFs(volumeHandle: Long, callback: NativeReadCallback, volumeSize: Long): Long
 
+    /**
+     * Starts [mountFs] on a native worker thread and returns immediately.
+     * @param volumeHandle The handle to the initialized volume.
+     * @param callback The callback to read raw data (called from the worker thread).
+     * @param volumeSize The size of the volume in bytes.
+     * @return A token for [pollMount] and [cancelMount], or a negative error code if the mount could not start.
+     */
+    external fun mountFsAsync(volumeHandle: Long, callback: NativeReadCallback, volumeSize: Long): Long
+
+    /**
+     * Checks on a mount started with [mountFsAsync].
+     * @param token The token returned by [mountFsAsync].
+     * @return [MOUNT_PENDING] while running, then once the file system handle or a negative error code
+     *         (as for [mountFs]; the failure is also reported by [getLastError] on this thread).
+     *         Unknown or already collected tokens give -[ERR_INVALID_ARGUMENT].
+     */
+    external fun pollMount(token: Long): Long
+
+    /**
+     * Abandons a mount started with [mountFsAsync]. A file system it already mounted is closed;
+     * a mount still in progress is discarded when the worker finishes.
+     * @param token The token returned by [mountFsAsync].
+     * @return False if the token is unknown or already collected.
+     */
+    external fun cancelMount(token: Long): Boolean
+
+    /**
+     * Reads every sector of a mounted volume through the decrypt path and checks the
+     * file system boot region (signature, exFAT checksum, FAT32 backup boot sector).
+     * Interrupting the calling thread cancels the pass.
+     * @param volumeHandle The handle to the initialized volume.
+     * @param callback The callback to read raw data.
+     * @param volumeSize The size of the volume in bytes.
+     * @return The first bad offset within the data area, [VERIFY_CLEAN], [VERIFY_CANCELLED] or [VERIFY_ERROR].
+     */
+    external fun verifyVolume(volumeHandle: Long, callback: NativeReadCallback, volumeSize: Long): Long
+
+    /**
+     * Measures cipher throughput on this device with a throwaway key.
+     * @param cipherId The cipher ID (as in [formatVolume]).
+     * @param bufferSizeMb The buffer size in MiB (1..256).
+     * @return Encrypt + decrypt throughput in MiB/s.
+     */
+    external fun benchmark(cipherId: Int, bufferSizeMb: Int): Double
+
     /**
      * Lists files in a directory.
      * @param fsHandle The file system handle.
@@ -131,24 +447,108 @@ object RustNative {
      */
     external fun listFiles(fsHandle: Long, path: String): Array<RustFile>?
 
+    /**
+     * Lists one page of a directory, for directories too large to return in one array.
+     * Pages follow the order of [listFiles]; an offset at or past the end gives an empty page.
+     * Only NTFS skips the work for entries outside the page; exFAT and FAT32 still read the whole
+     * directory for every page, so the saving there is only in the objects passed to Java.
+     * Unlike [listFiles], which returns an empty array on any failure, errors are reported.
+     * @param fsHandle The file system handle.
+     * @param path The path to list (e.g. "/").
+     * @param offset Index of the first entry to return.
+     * @param limit Maximum number of entries to return.
+     * @return The entries and the directory's total entry count, or null if failed (an exception is
+     *         thrown with the reason: IllegalArgumentException for a bad handle, path, offset or limit,
+     *         IOException if the directory cannot be listed).
+     */
+    external fun listFilesPaged(fsHandle: Long, path: String, offset: Long, limit: Int): RustFilePage?
+
     /**
      * Reads data from a file.
      * @param fsHandle The file system handle.
      * @param path The path of the file.
      * @param offset The offset to read from.
      * @param buffer The buffer to read into.
-     * @return The number of bytes read, or -1 if failed.
+     * @return The number of bytes read, 0 at or past the end of the file, or -1 on error
+     * (see [getLastError]). [readFileDirect] and [readFileArray] follow the same convention.
      */
     external fun readFile(fsHandle: Long, path: String, offset: Long, buffer: ByteArray): Long
     external fun readFileDirect(fsHandle: Long, path: String, offset: Long, buffer: java.nio.ByteBuffer, position: Int, length: Int): Long
     external fun readFileArray(fsHandle: Long, path: String, offset: Long, array: ByteArray, arrayOffset: Int, length: Int): Long
 
+    /**
+     * Writes data into a file. FAT32 files are created or grown as needed; on NTFS only small
+     * (MFT-resident) files can be overwritten in place without changing their length. exFAT is read-only.
+     * @param fsHandle The file system handle.
+     * @param path The path of the file.
+     * @param offset The offset to write at.
+     * @param data The data to write.
+     * Changes reach the volume through the callback immediately, but call [syncFs] before relying
+     * on them: without it, writes may be lost if the process is killed.
+     * @return The number of bytes written, or -1 if failed.
+     */
+    external fun writeFile(fsHandle: Long, path: String, offset: Long, data: ByteArray): Long
+
+    /**
+     * Creates a directory. Only supported on FAT32.
+     * @param fsHandle The file system handle.
+     * @param path The path of the new directory.
+     * @return 0 on success, -1 if failed (an IOException is thrown with the reason).
+     */
+    external fun mkdir(fsHandle: Long, path: String): Int
+
+    /**
+     * Computes the total size of all files below a directory.
+     * Walks the whole subtree, so it is O(number of entries); call it off the main thread.
+     * @param fsHandle The file system handle.
+     * @param path The directory path (e.g. "/").
+     * @return The size in bytes, or -1 if failed (an IOException is thrown with the reason).
+     */
+    external fun dirSize(fsHandle: Long, path: String): Long
+
+    /**
+     * Reports the free and total space of a mounted file system.
+     * The first call may scan the allocation bitmap or FAT; call it off the main thread.
+     * @param fsHandle The file system handle.
+     * @return [free bytes, total bytes], or null if failed (an IOException is thrown with the reason).
+     */
+    external fun getFreeSpace(fsHandle: Long): LongArray?
+
+    /**
+     * Commits pending changes of a mounted file system (the FAT32 free cluster count) and calls
+     * the callback's [NativeReadCallback.flush]. Without it, writes may be lost on process kill.
+     * @param fsHandle The file system handle.
+     * @return 0 on success, -1 if failed (an IOException is thrown with the reason).
+     */
+    external fun syncFs(fsHandle: Long): Int
+
+    /**
+     * Deletes a file or an empty directory on FAT32. On NTFS only simple files can be deleted
+     * (a single name and resident or unfragmented data); directories and other files are rejected.
+     * @param fsHandle The file system handle.
+     * @param path The path of the file or directory.
+     * @return 0 on success, -1 if failed (an IOException is thrown with the reason).
+     */
+    external fun deleteFile(fsHandle: Long, path: String): Int
+
     /**
      * Closes the file system.
      * @param fsHandle The file system handle.
      */
     external fun closeFs(fsHandle: Long)
 
+    /**
+     * Lists the handles of all open volume contexts (for leak diagnostics; also logged).
+     * @return The open handles, or null on failure.
+     */
+    external fun getActiveHandles(): LongArray?
+
+    /**
+     * Lists the handles of all mounted file systems (for leak diagnostics; also logged).
+     * @return The open file system handles, or null on failure.
+     */
+    external fun getActiveFsHandles(): LongArray?
+
     /**
      * Changes the password/PIM of a volume.
      * @param path The path of the volume.
@@ -156,7 +556,8 @@ object RustNative {
      * @param oldPim The current PIM.
      * @param newPassword The new password.
      * @param newPim The new PIM.
-     * @return 0 on success, -1 on failure.
+     * @param newPrfId The PRF for the new header (same ids as [formatVolume]), or -1 to keep the current one.
+     * @return 0 on success, or the failure's ERR_* code negated (see [getLastErrorCode]).
      */
     external fun changePassword(
         path: String,
@@ -168,6 +569,64 @@ object RustNative {
         newPrfId: Int
     ): Int
 
+    /**
+     * Replaces the keyfiles of a volume, keeping its password and PIM.
+     * @param path The path of the volume.
+     * @param password The password.
+     * @param pim The PIM.
+     * @param oldKeyfiles Contents of the keyfiles the volume currently uses, or null for none.
+     * @param newKeyfiles Contents of the keyfiles to use from now on, or null for none.
+     * @param newSalt The 64 byte salt for the new header.
+     * @param newPrfId The PRF for the new header (same ids as [formatVolume]), or -1 to keep the current one.
+     * @return 0 on success, or the failure's ERR_* code negated (see [getLastErrorCode]).
+     */
+    external fun changeKeyfiles(
+        path: String,
+        password: ByteArray,
+        pim: Int,
+        oldKeyfiles: Array<ByteArray>?,
+        newKeyfiles: Array<ByteArray>?,
+        newSalt: ByteArray,
+        newPrfId: Int
+    ): Int
+
+    /**
+     * Exports the raw (still encrypted) 128 KiB header region of a volume file.
+     * @param path The path of the volume file.
+     * @param useBackup True for the backup header at the end of the file, false for the primary header.
+     * @return The header bytes, or null if the export failed (an IOException is thrown).
+     */
+    external fun exportHeader(path: String, useBackup: Boolean): ByteArray?
+
+    /**
+     * Writes a header region previously returned by [exportHeader] back into a volume file.
+     * @param path The path of the volume file.
+     * @param headerBlob The 128 KiB header region.
+     * @param toBackupLocation True to write the backup header at the end of the file, false for the primary header.
+     * @return 0 on success, or the failure's ERR_* code negated (an IOException is thrown for restore errors).
+     */
+    external fun restoreHeader(path: String, headerBlob: ByteArray, toBackupLocation: Boolean): Int
+
+    /**
+     * Overwrites a whole volume file with random data [passes] times, then with zeros.
+     * Blocks until done, so call it off the main thread. The file itself is not deleted.
+     * @param path The path of the volume file.
+     * @param passes The number of random passes before the final zero pass.
+     * @return 0 on success, or the failure's ERR_* code negated (an IOException is thrown for wipe errors).
+     */
+    external fun wipeVolume(path: String, passes: Int): Int
+
+    /**
+     * Estimates how random a region of a file looks, e.g. the outer volume free space a hidden
+     * volume will occupy. Samples at most 4 MiB spread over the region.
+     * Zero-filled free space (low entropy) makes a hidden volume easier to spot.
+     * @param path The path of the volume file.
+     * @param regionStart Byte offset of the region.
+     * @param regionLen Length of the region in bytes.
+     * @return Entropy in bits per byte from 0.0 (constant) to 8.0 (random), or -1.0 on failure.
+     */
+    external fun freeSpaceEntropy(path: String, regionStart: Long, regionLen: Long): Double
+
     /**
      * Formats a new volume.
      * @param path The path to create the volume at.
@@ -176,8 +635,20 @@ object RustNative {
      * @param volumeSize The size of the volume in bytes.
      * @param salt The 64 byte salt.
      * @param masterKey The 256 byte master key.
-     * @param filesystemTypeId The filesystem type: 0 = FAT32, 1 = ExFAT.
-     * @return 0 on success, negative on failure.
+     * @param prfId The PRF: 0 = SHA-512, 1 = SHA-256, 2 = Whirlpool, 3 = RIPEMD-160, 4 = Streebog,
+     *              5 = BLAKE2s-256, 6 = SHA-1, 7 = Argon2id, 8 = SHA3-256, 9 = SHA3-512,
+     *              10 = BLAKE2b-512 (noxcipher only, not mountable by VeraCrypt).
+     * @param filesystemTypeId The filesystem type: 0 = FAT32, 1 = ExFAT, 2 = exFAT above 32 GiB, FAT32 otherwise.
+     * @param progress Optional callback receiving (bytes done, total) a bounded number of times while formatting.
+     * @param storePimHint Store the PIM in plaintext after the header so mounting with PIM 0 finds it.
+     *                     Non-standard and reveals the volume and its PIM, so it gives up plausible deniability.
+     * @param resume Record progress in [path] + ".format-progress" and continue a format of [path] that
+     *               was interrupted the same way, if it had the same size, cipher and filesystem and its
+     *               data decrypts with [masterKey]. Otherwise formatting starts over. The marker holds no
+     *               key material. The header is only written once formatting completes.
+     * @param fullFormat Overwrite the whole data area with encrypted random data first, so free space
+     *                   looks like data (slow, progress covers this pass). False for a quick format.
+     * @return 0 on success, or the failure's ERR_* code negated (see [getLastErrorCode]).
      */
     external fun formatVolume(
         path: String,
@@ -188,12 +659,63 @@ object RustNative {
         masterKey: ByteArray,
         cipherTypeId: Int,
         prfId: Int,
-        filesystemTypeId: Int = 0
+        filesystemTypeId: Int = 0,
+        progress: FormatProgressCallback? = null,
+        storePimHint: Boolean = false,
+        resume: Boolean = false,
+        fullFormat: Boolean = false
     ): Int
 
+    /**
+     * Converts an existing plain file into a volume in place; its contents become the volume data.
+     * The file grows by 256 KiB (both header areas). Progress is recorded in [path] + ".inplace-progress"
+     * after every step, so an interrupted conversion can be continued with [inPlaceProgress].
+     * The volume cannot be mounted until the conversion completes.
+     * @param path The path of the file to convert.
+     * @param password The password.
+     * @param pim The PIM.
+     * @param salt The 64 byte salt (ignored when resuming).
+     * @param masterKey The 256 byte master key (ignored when resuming; the keys come from the first call).
+     * @param cipherTypeId The cipher, as in [formatVolume].
+     * @param prfId The PRF, as in [formatVolume].
+     * @param resumeOffset -1 to start a new conversion, or the value of [inPlaceProgress] to continue one.
+     * @param progress Optional callback receiving (bytes done, total).
+     * @return 0 on success, or the failure's ERR_* code negated (see [getLastErrorCode]).
+     */
+    external fun encryptInPlace(
+        path: String,
+        password: ByteArray,
+        pim: Int,
+        salt: ByteArray,
+        masterKey: ByteArray,
+        cipherTypeId: Int,
+        prfId: Int,
+        resumeOffset: Long = -1,
+        progress: FormatProgressCallback? = null
+    ): Int
+
+    /**
+     * Gets the resume offset of an interrupted [encryptInPlace] on [path].
+     * @return The offset to pass as resumeOffset, or -1 if no conversion is in progress.
+     */
+    external fun inPlaceProgress(path: String): Long
+
     /**
      * Clears all volume contexts and sensitive keys from native memory.
      * Should be called on app destroy or logout.
      */
     external fun cleanup()
+
+    /**
+     * Closes every mounted file system but keeps the volume contexts open for [decrypt]/[encrypt].
+     * @return The number of file systems closed, or -1 on failure.
+     */
+    external fun closeAllFilesystems(): Int
+
+    /**
+     * Closes every volume context. Mounted file systems are left open and keep their own volume
+     * until they are closed; call [closeAllFilesystems] first to release everything.
+     * @return The number of volumes closed, or -1 on failure.
+     */
+    external fun closeAllVolumes(): Int
 }
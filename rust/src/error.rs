@@ -1,13 +1,131 @@
-#[derive(Debug)]
+// Stable numeric error codes exported across the JNI boundary (see getLastErrorCode).
+// Unlike failure messages, these never change between releases, so Java can branch on them.
+// Functions that report failure through their return value return the code negated (see
+// NoxError::return_code), so -N from any call means the same as N from getLastErrorCode.
+// The values are mirrored as ERR_* constants in RustNative.kt; never renumber existing variants.
+use crate::header::HeaderError;
+use crate::volume::VolumeError;
+
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum NoxError {
-    MountFailed,
-    InvalidPassword,
-    IoError(std::io::Error),
-    CryptoError,
+    // Nothing has failed on this thread yet.
+    None = 0,
+    // Failure without a more specific code.
+    Other = 1,
+    // Wrong password, PIM or keyfiles (no header decrypted).
+    InvalidPassword = 2,
+    // A header decrypted but does not carry the "VERA"/"TRUE" magic.
+    InvalidMagic = 3,
+    // Cipher, key derivation or other cryptographic failure.
+    CryptoError = 4,
+    // I/O failure on the container or file system.
+    IoError = 5,
+    // Header field CRC mismatch.
+    HeaderCrc = 6,
+    // Master key area CRC mismatch.
+    KeyAreaCrc = 7,
+    // Header or minimum program version not supported.
+    UnsupportedVersion = 8,
+    // Sector size, key size or data area layout in the header is invalid.
+    InvalidHeaderLayout = 9,
+    // A write was refused by hidden volume protection.
+    HiddenVolumeProtection = 10,
+    // Header data too short to parse.
+    HeaderTooShort = 11,
+    // Invalid argument from the caller (null buffer, negative offset, bad id, ...).
+    InvalidArgument = 12,
+    // No supported file system found in the volume.
+    MountFailed = 13,
+    // Panic or JNI failure inside the native library.
+    Internal = 14,
+    // Volume handle unknown or already closed.
+    NotInitialized = 15,
+}
+
+impl NoxError {
+    // The code returned by getLastErrorCode.
+    pub const fn code(self) -> i32 {
+        self as i32
+    }
+
+    // The value returned by JNI functions that report this failure through their return value.
+    pub const fn return_code(self) -> i32 {
+        -self.code()
+    }
+
+    // Code for an exception thrown without a more specific error value.
+    pub fn for_exception_class(class: &str) -> Self {
+        match class {
+            "java/lang/IllegalArgumentException" | "java/lang/IndexOutOfBoundsException" => NoxError::InvalidArgument,
+            "java/io/IOException" => NoxError::IoError,
+            "java/lang/RuntimeException" => NoxError::Internal,
+            _ => NoxError::Other,
+        }
+    }
 }
 
-impl From<std::io::Error> for NoxError {
-    fn from(err: std::io::Error) -> Self {
-        NoxError::IoError(err)
+impl From<&HeaderError> for NoxError {
+    fn from(err: &HeaderError) -> Self {
+        match err {
+            HeaderError::InvalidMagic => NoxError::InvalidMagic,
+            HeaderError::InvalidHeaderCrc => NoxError::HeaderCrc,
+            HeaderError::InvalidKeyAreaCrc => NoxError::KeyAreaCrc,
+            HeaderError::UnsupportedVersion(_) | HeaderError::UnsupportedProgramVersion(_) => NoxError::UnsupportedVersion,
+            HeaderError::InvalidSectorSize(_) | HeaderError::InvalidKeySize | HeaderError::InvalidLayout => {
+                NoxError::InvalidHeaderLayout
+            }
+            HeaderError::DataTooShort(_) => NoxError::HeaderTooShort,
+        }
+    }
+}
+
+impl From<&VolumeError> for NoxError {
+    fn from(err: &VolumeError) -> Self {
+        match err {
+            VolumeError::InvalidPassword(_) => NoxError::InvalidPassword,
+            VolumeError::InvalidHeader(e) => e.into(),
+            VolumeError::CryptoError(_) => NoxError::CryptoError,
+            VolumeError::NotInitialized => NoxError::NotInitialized,
+            VolumeError::IoError(_) => NoxError::IoError,
+            VolumeError::HiddenVolumeProtectionTriggered => NoxError::HiddenVolumeProtection,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NoxError;
+    use crate::header::HeaderError;
+    use crate::volume::VolumeError;
+
+    #[test]
+    fn codes_are_stable() {
+        assert_eq!(NoxError::None.code(), 0);
+        assert_eq!(NoxError::InvalidPassword.code(), 2);
+        assert_eq!(NoxError::IoError.code(), 5);
+        assert_eq!(NoxError::Internal.code(), 14);
+    }
+
+    #[test]
+    fn return_codes_negate_last_error_codes() {
+        assert_eq!(NoxError::None.return_code(), 0);
+        assert_eq!(NoxError::Other.return_code(), -1);
+        assert_eq!(NoxError::InvalidPassword.return_code(), -2);
+        assert_eq!(NoxError::MountFailed.return_code(), -13);
+        for err in [VolumeError::NotInitialized, VolumeError::CryptoError(String::new()), VolumeError::InvalidHeader(HeaderError::DataTooShort(1))] {
+            assert_eq!(err.code(), NoxError::from(&err).return_code());
+        }
+    }
+
+    #[test]
+    fn volume_errors_map_to_codes() {
+        assert_eq!(NoxError::from(&VolumeError::InvalidPassword(String::new())), NoxError::InvalidPassword);
+        assert_eq!(NoxError::from(&VolumeError::InvalidHeader(HeaderError::InvalidKeyAreaCrc)), NoxError::KeyAreaCrc);
+        assert_eq!(NoxError::from(&VolumeError::InvalidHeader(HeaderError::InvalidSectorSize(3))), NoxError::InvalidHeaderLayout);
+        assert_eq!(NoxError::from(&VolumeError::NotInitialized), NoxError::NotInitialized);
+        let io = VolumeError::IoError(std::io::Error::other("disk"));
+        assert_eq!(NoxError::from(&io), NoxError::IoError);
+        assert_eq!(NoxError::from(&VolumeError::HiddenVolumeProtectionTriggered), NoxError::HiddenVolumeProtection);
     }
 }
@@ -0,0 +1,1095 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::filesystem::FileInfo;
+
+// Minimal FAT32 driver
+// Reads and writes the layout produced by format::format_fat32 (and any standard FAT32 volume)
+// over any Read + Write + Seek device, e.g. a DecryptedReader.
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME_ID;
+const ENTRY_FREE: u8 = 0xE5;
+const ENTRY_END: u8 = 0x00;
+const FAT_ENTRY_MASK: u32 = 0x0FFF_FFFF;
+const FAT_EOC: u32 = 0x0FFF_FFFF;
+const FAT_EOC_MIN: u32 = 0x0FFF_FFF8;
+const FAT_BAD_CLUSTER: u32 = 0x0FFF_FFF7;
+const LFN_CHARS_PER_ENTRY: usize = 13;
+const MAX_FAT32_FILE_SIZE: u64 = u32::MAX as u64;
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+// FSInfo free cluster count meaning "not known".
+const FSINFO_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn not_found(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, message)
+}
+
+// Quick check on a raw boot sector: 0x55AA signature plus the "FAT32" file system type string.
+// The type string is informational, so Fat32::open still does the authoritative validation.
+pub fn is_fat32_boot_sector(boot: &[u8]) -> bool {
+    boot.len() >= 512 && boot[510] == 0x55 && boot[511] == 0xAA && &boot[82..87] == b"FAT32"
+}
+
+// Convert a FAT date/time pair (local time, no zone) to Unix seconds, treating it as UTC.
+// A zero or out-of-range date gives None.
+pub(crate) fn fat_time_to_unix(date: u16, time: u16) -> Option<u64> {
+    let year = 1980 + (date >> 9) as i64;
+    let month = ((date >> 5) & 0x0F) as i64;
+    let day = (date & 0x1F) as i64;
+    if !(1..=12).contains(&month) || day == 0 {
+        return None;
+    }
+    // Days since 1970-01-01 for the proleptic Gregorian calendar.
+    let (y, m) = if month <= 2 { (year - 1, month + 9) } else { (year, month - 3) };
+    let era = y / 400;
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let hours = (time >> 11) as i64;
+    let minutes = ((time >> 5) & 0x3F) as i64;
+    let seconds = ((time & 0x1F) * 2) as i64;
+    u64::try_from(days * 86_400 + hours * 3600 + minutes * 60 + seconds).ok()
+}
+
+// A parsed directory entry (short entry plus any long file name).
+#[derive(Debug, Clone)]
+struct DirEntry {
+    name: String,
+    // Raw 8.3 name of the short entry (space padded, as stored on disk).
+    short_name: [u8; 11],
+    attr: u8,
+    first_cluster: u32,
+    size: u32,
+    // Creation and last write times in Unix seconds (None when the entry leaves them zeroed).
+    created_time: Option<u64>,
+    modified_time: Option<u64>,
+    // Absolute device offsets of the LFN slots followed by the short entry (last).
+    slot_offsets: Vec<u64>,
+}
+
+impl DirEntry {
+    fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    fn short_entry_offset(&self) -> u64 {
+        // slot_offsets always contains at least the short entry.
+        self.slot_offsets[self.slot_offsets.len() - 1]
+    }
+}
+
+// FAT32 filesystem over a block device.
+pub struct Fat32<D: Read + Write + Seek> {
+    dev: D,
+    bytes_per_sector: u32,
+    sectors_per_cluster: u32,
+    reserved_sectors: u32,
+    fat_count: u32,
+    fat_sectors: u32,
+    root_cluster: u32,
+    cluster_count: u32,
+    // Next cluster to inspect when allocating.
+    alloc_hint: u32,
+    // Free cluster count, from FSInfo when valid or counted on first use; kept up to date
+    // by allocations and frees. Written back to FSInfo by sync.
+    free_clusters: Option<u32>,
+    // Sector of a valid FSInfo structure, if the volume has one.
+    fs_info_sector: Option<u32>,
+}
+
+impl<D: Read + Write + Seek> Fat32<D> {
+    // Parse the boot sector and validate the FAT32 layout.
+    pub fn open(mut dev: D) -> io::Result<Self> {
+        let mut boot = [0u8; 512];
+        dev.seek(SeekFrom::Start(0))?;
+        dev.read_exact(&mut boot)?;
+
+        if boot[510] != 0x55 || boot[511] != 0xAA {
+            return Err(invalid_data("Missing boot sector signature"));
+        }
+
+        let bytes_per_sector = LittleEndian::read_u16(&boot[11..13]) as u32;
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved_sectors = LittleEndian::read_u16(&boot[14..16]) as u32;
+        let fat_count = boot[16] as u32;
+        let root_entries = LittleEndian::read_u16(&boot[17..19]);
+        let total_sectors_16 = LittleEndian::read_u16(&boot[19..21]) as u32;
+        let fat_sectors_16 = LittleEndian::read_u16(&boot[22..24]);
+        let total_sectors_32 = LittleEndian::read_u32(&boot[32..36]);
+        let fat_sectors = LittleEndian::read_u32(&boot[36..40]);
+        let root_cluster = LittleEndian::read_u32(&boot[44..48]);
+
+        if !matches!(bytes_per_sector, 512 | 1024 | 2048 | 4096) {
+            return Err(invalid_data("Invalid FAT32 bytes per sector"));
+        }
+        if sectors_per_cluster == 0 || !sectors_per_cluster.is_power_of_two() {
+            return Err(invalid_data("Invalid FAT32 sectors per cluster"));
+        }
+        if reserved_sectors == 0 || fat_count == 0 {
+            return Err(invalid_data("Invalid FAT32 reserved region"));
+        }
+        // FAT32 has no fixed root directory and always uses the 32-bit FAT size field.
+        if root_entries != 0 || fat_sectors_16 != 0 || fat_sectors == 0 {
+            return Err(invalid_data("Not a FAT32 boot sector"));
+        }
+
+        let total_sectors = if total_sectors_16 != 0 { total_sectors_16 } else { total_sectors_32 };
+        let data_start = (fat_count as u64)
+            .checked_mul(fat_sectors as u64)
+            .and_then(|s| s.checked_add(reserved_sectors as u64))
+            .ok_or_else(|| invalid_data("FAT32 layout overflow"))?;
+        if data_start >= total_sectors as u64 {
+            return Err(invalid_data("FAT32 data region outside volume"));
+        }
+
+        let data_clusters = (total_sectors as u64 - data_start) / sectors_per_cluster as u64;
+        // The FAT itself must be able to address every data cluster.
+        let fat_capacity = (fat_sectors as u64 * bytes_per_sector as u64 / 4).saturating_sub(2);
+        let cluster_count = data_clusters.min(fat_capacity) as u32;
+
+        if root_cluster < 2 || root_cluster >= cluster_count + 2 {
+            return Err(invalid_data("FAT32 root cluster out of range"));
+        }
+
+        // A missing or malformed FSInfo sector only means the free count must be counted.
+        let fs_info_sector = LittleEndian::read_u16(&boot[48..50]) as u32;
+        let mut free_clusters = None;
+        let mut valid_fs_info_sector = None;
+        if fs_info_sector != 0 && fs_info_sector < reserved_sectors {
+            let mut fs_info = [0u8; 512];
+            dev.seek(SeekFrom::Start(fs_info_sector as u64 * bytes_per_sector as u64))?;
+            dev.read_exact(&mut fs_info)?;
+            if LittleEndian::read_u32(&fs_info[0..4]) == FSINFO_LEAD_SIGNATURE
+                && LittleEndian::read_u32(&fs_info[484..488]) == FSINFO_STRUCT_SIGNATURE
+            {
+                valid_fs_info_sector = Some(fs_info_sector);
+                let count = LittleEndian::read_u32(&fs_info[488..492]);
+                if count != FSINFO_UNKNOWN && count <= cluster_count {
+                    free_clusters = Some(count);
+                }
+            }
+        }
+
+        Ok(Self {
+            dev,
+            bytes_per_sector,
+            sectors_per_cluster,
+            reserved_sectors,
+            fat_count,
+            fat_sectors,
+            root_cluster,
+            cluster_count,
+            alloc_hint: 2,
+            free_clusters,
+            fs_info_sector: valid_fs_info_sector,
+        })
+    }
+
+    fn cluster_size(&self) -> u64 {
+        self.bytes_per_sector as u64 * self.sectors_per_cluster as u64
+    }
+
+    fn is_valid_cluster(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.cluster_count + 2
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> io::Result<u64> {
+        if !self.is_valid_cluster(cluster) {
+            return Err(invalid_data("FAT32 cluster out of range"));
+        }
+        let data_start = (self.reserved_sectors as u64 + self.fat_count as u64 * self.fat_sectors as u64)
+            * self.bytes_per_sector as u64;
+        Ok(data_start + (cluster as u64 - 2) * self.cluster_size())
+    }
+
+    fn fat_entry_offset(&self, fat_index: u32, cluster: u32) -> u64 {
+        (self.reserved_sectors as u64 + fat_index as u64 * self.fat_sectors as u64)
+            * self.bytes_per_sector as u64
+            + cluster as u64 * 4
+    }
+
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.dev.seek(SeekFrom::Start(offset))?;
+        self.dev.read_exact(buf)
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+        self.dev.seek(SeekFrom::Start(offset))?;
+        self.dev.write_all(data)
+    }
+
+    fn read_fat(&mut self, cluster: u32) -> io::Result<u32> {
+        let mut raw = [0u8; 4];
+        let offset = self.fat_entry_offset(0, cluster);
+        self.read_at(offset, &mut raw)?;
+        Ok(LittleEndian::read_u32(&raw) & FAT_ENTRY_MASK)
+    }
+
+    // Update the entry in every FAT copy, preserving the reserved high nibble.
+    fn write_fat(&mut self, cluster: u32, value: u32) -> io::Result<()> {
+        for fat_index in 0..self.fat_count {
+            let offset = self.fat_entry_offset(fat_index, cluster);
+            let mut raw = [0u8; 4];
+            self.read_at(offset, &mut raw)?;
+            let old = LittleEndian::read_u32(&raw);
+            LittleEndian::write_u32(&mut raw, (old & !FAT_ENTRY_MASK) | (value & FAT_ENTRY_MASK));
+            self.write_at(offset, &raw)?;
+        }
+        Ok(())
+    }
+
+    // Follow the FAT chain starting at `start`. Cycles and out-of-range links are rejected.
+    fn cluster_chain(&mut self, start: u32) -> io::Result<Vec<u32>> {
+        let mut chain = Vec::new();
+        if start == 0 {
+            return Ok(chain);
+        }
+
+        let mut cluster = start;
+        loop {
+            if !self.is_valid_cluster(cluster) {
+                return Err(invalid_data("Corrupt FAT32 cluster chain"));
+            }
+            if chain.len() > self.cluster_count as usize {
+                return Err(invalid_data("FAT32 cluster chain loops"));
+            }
+            chain.push(cluster);
+
+            let next = self.read_fat(cluster)?;
+            if next >= FAT_EOC_MIN {
+                return Ok(chain);
+            }
+            if next == FAT_BAD_CLUSTER || next < 2 {
+                return Err(invalid_data("Corrupt FAT32 cluster chain"));
+            }
+            cluster = next;
+        }
+    }
+
+    // Find a free cluster, mark it end-of-chain and zero its contents.
+    fn allocate_cluster(&mut self) -> io::Result<u32> {
+        let first = self.cluster_count + 2;
+        let start = if self.is_valid_cluster(self.alloc_hint) { self.alloc_hint } else { 2 };
+        let mut cluster = start;
+
+        loop {
+            if self.read_fat(cluster)? == 0 {
+                self.write_fat(cluster, FAT_EOC)?;
+                let zeros = vec![0u8; self.cluster_size() as usize];
+                let offset = self.cluster_offset(cluster)?;
+                self.write_at(offset, &zeros)?;
+                self.alloc_hint = if cluster + 1 < first { cluster + 1 } else { 2 };
+                if let Some(free) = self.free_clusters.as_mut() {
+                    *free = free.saturating_sub(1);
+                }
+                return Ok(cluster);
+            }
+
+            cluster += 1;
+            if cluster >= first {
+                cluster = 2;
+            }
+            if cluster == start {
+                return Err(io::Error::new(io::ErrorKind::Other, "FAT32 volume is full"));
+            }
+        }
+    }
+
+    // Append a freshly allocated cluster to the chain ending in `last` (0 for an empty chain).
+    fn extend_chain(&mut self, last: u32) -> io::Result<u32> {
+        let cluster = self.allocate_cluster()?;
+        if last != 0 {
+            self.write_fat(last, cluster)?;
+        }
+        Ok(cluster)
+    }
+
+    // Read all entries of the directory starting at `cluster`.
+    fn read_dir(&mut self, cluster: u32) -> io::Result<Vec<DirEntry>> {
+        let chain = self.cluster_chain(cluster)?;
+        let cluster_size = self.cluster_size() as usize;
+        let mut entries = Vec::new();
+        let mut lfn_parts: Vec<(u8, [u16; LFN_CHARS_PER_ENTRY])> = Vec::new();
+        let mut lfn_offsets: Vec<u64> = Vec::new();
+        let mut lfn_checksum = 0u8;
+        let mut data = vec![0u8; cluster_size];
+
+        for c in chain {
+            let base = self.cluster_offset(c)?;
+            self.read_at(base, &mut data)?;
+
+            for (i, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                let slot_offset = base + (i * DIR_ENTRY_SIZE) as u64;
+                match raw[0] {
+                    ENTRY_END => return Ok(entries),
+                    ENTRY_FREE => {
+                        lfn_parts.clear();
+                        lfn_offsets.clear();
+                        continue;
+                    }
+                    _ => {}
+                }
+
+                let attr = raw[11];
+                if attr & ATTR_LONG_NAME == ATTR_LONG_NAME {
+                    // Long file name slot. The last logical slot is stored first.
+                    if raw[0] & 0x40 != 0 {
+                        lfn_parts.clear();
+                        lfn_offsets.clear();
+                        lfn_checksum = raw[13];
+                    }
+                    let mut chars = [0u16; LFN_CHARS_PER_ENTRY];
+                    for (j, pos) in (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2)).enumerate() {
+                        chars[j] = LittleEndian::read_u16(&raw[pos..pos + 2]);
+                    }
+                    lfn_parts.push((raw[0] & 0x1F, chars));
+                    lfn_offsets.push(slot_offset);
+                    continue;
+                }
+
+                if attr & ATTR_VOLUME_ID != 0 {
+                    lfn_parts.clear();
+                    lfn_offsets.clear();
+                    continue;
+                }
+
+                let mut short_name = [0u8; 11];
+                short_name.copy_from_slice(&raw[0..11]);
+                let name = if !lfn_parts.is_empty() && lfn_checksum == short_name_checksum(&short_name) {
+                    assemble_long_name(&mut lfn_parts)
+                } else {
+                    lfn_offsets.clear();
+                    decode_short_name(&short_name, raw[12])
+                };
+
+                let mut slot_offsets = std::mem::take(&mut lfn_offsets);
+                slot_offsets.push(slot_offset);
+                lfn_parts.clear();
+
+                if name == "." || name == ".." {
+                    continue;
+                }
+
+                let first_cluster = ((LittleEndian::read_u16(&raw[20..22]) as u32) << 16)
+                    | LittleEndian::read_u16(&raw[26..28]) as u32;
+                entries.push(DirEntry {
+                    name,
+                    short_name,
+                    attr,
+                    first_cluster,
+                    size: LittleEndian::read_u32(&raw[28..32]),
+                    created_time: fat_time_to_unix(
+                        LittleEndian::read_u16(&raw[16..18]),
+                        LittleEndian::read_u16(&raw[14..16]),
+                    ),
+                    modified_time: fat_time_to_unix(
+                        LittleEndian::read_u16(&raw[24..26]),
+                        LittleEndian::read_u16(&raw[22..24]),
+                    ),
+                    slot_offsets,
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    // Resolve a directory path to its first cluster.
+    fn resolve_dir(&mut self, components: &[&str]) -> io::Result<u32> {
+        let mut cluster = self.root_cluster;
+        for component in components {
+            let entry = self
+                .read_dir(cluster)?
+                .into_iter()
+                .find(|e| e.name.eq_ignore_ascii_case(component))
+                .ok_or_else(|| not_found("Path not found"))?;
+            if !entry.is_dir() {
+                return Err(not_found("Path not found"));
+            }
+            // A first cluster of 0 in a subdirectory entry refers to the root directory.
+            cluster = if entry.first_cluster == 0 { self.root_cluster } else { entry.first_cluster };
+        }
+        Ok(cluster)
+    }
+
+    // Resolve a file path to its parent directory cluster and entry.
+    fn resolve_file(&mut self, components: &[&str]) -> io::Result<(u32, Option<DirEntry>)> {
+        let (file_name, dir_components) = components
+            .split_last()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Empty path"))?;
+        let parent = self.resolve_dir(dir_components)?;
+        let entry = self
+            .read_dir(parent)?
+            .into_iter()
+            .find(|e| e.name.eq_ignore_ascii_case(file_name));
+        Ok((parent, entry))
+    }
+
+    // List the entries of a directory.
+    pub fn list_files(&mut self, components: &[&str]) -> io::Result<Vec<FileInfo>> {
+        let cluster = self.resolve_dir(components)?;
+        Ok(self
+            .read_dir(cluster)?
+            .into_iter()
+            .map(|e| FileInfo {
+                is_dir: e.is_dir(),
+                size: if e.is_dir() { 0 } else { e.size as u64 },
+                modified_time: e.modified_time,
+                created_time: e.created_time,
+                attributes: e.attr as u32,
+                name: e.name,
+            })
+            .collect())
+    }
+
+    // Read from a file at `offset`. Returns the number of bytes read (0 at or past EOF).
+    pub fn read_file(&mut self, components: &[&str], offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let (_, entry) = self.resolve_file(components)?;
+        let entry = entry.ok_or_else(|| not_found("File not found"))?;
+        if entry.is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Path is a directory"));
+        }
+
+        let size = entry.size as u64;
+        if offset >= size || buf.is_empty() {
+            return Ok(0);
+        }
+        let to_read = std::cmp::min(buf.len() as u64, size - offset) as usize;
+
+        let chain = self.cluster_chain(entry.first_cluster)?;
+        self.transfer(&chain, offset, &mut buf[..to_read], false)?;
+        Ok(to_read)
+    }
+
+    // Write `data` at `offset` into a file, creating it if needed and growing it across
+    // cluster boundaries. Gaps between the old end of file and `offset` are zero-filled.
+    pub fn write_file(&mut self, components: &[&str], offset: u64, data: &[u8]) -> io::Result<usize> {
+        let end = offset
+            .checked_add(data.len() as u64)
+            .filter(|&end| end <= MAX_FAT32_FILE_SIZE)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "FAT32 file size limit exceeded"))?;
+
+        let (parent, entry) = self.resolve_file(components)?;
+        let mut entry = match entry {
+            Some(e) if e.is_dir() => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "Path is a directory"));
+            }
+            Some(e) => e,
+            None => {
+                let name = components[components.len() - 1];
+                self.create_entry(parent, name, ATTR_ARCHIVE, 0)?
+            }
+        };
+
+        let cluster_size = self.cluster_size();
+        let old_size = entry.size as u64;
+        let new_size = std::cmp::max(old_size, end);
+        let required_clusters = new_size.div_ceil(cluster_size) as usize;
+
+        let mut chain = self.cluster_chain(entry.first_cluster)?;
+        while chain.len() < required_clusters {
+            let last = chain.last().copied().unwrap_or(0);
+            let cluster = self.extend_chain(last)?;
+            if chain.is_empty() {
+                entry.first_cluster = cluster;
+            }
+            chain.push(cluster);
+        }
+
+        // Clear stale bytes between the old end of file and the write offset.
+        if offset > old_size {
+            let allocated_end = std::cmp::min(offset, old_size.div_ceil(cluster_size) * cluster_size);
+            if allocated_end > old_size {
+                let mut zeros = vec![0u8; (allocated_end - old_size) as usize];
+                self.transfer(&chain, old_size, &mut zeros, true)?;
+            }
+        }
+
+        let mut payload = data.to_vec();
+        self.transfer(&chain, offset, &mut payload, true)?;
+
+        entry.size = new_size as u32;
+        self.update_entry(&entry)?;
+        self.dev.flush()?;
+        Ok(data.len())
+    }
+
+    // Create an empty directory, including its "." and ".." entries.
+    pub fn create_dir(&mut self, components: &[&str]) -> io::Result<()> {
+        let (parent, entry) = self.resolve_file(components)?;
+        if entry.is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Entry already exists"));
+        }
+        let name = components[components.len() - 1];
+        validate_name(name)?;
+
+        // allocate_cluster zeroes the cluster, so the directory ends right after "..".
+        let cluster = self.allocate_cluster()?;
+        if let Err(e) = self.create_entry(parent, name, ATTR_DIRECTORY, cluster) {
+            self.free_chain(cluster)?;
+            return Err(e);
+        }
+
+        // ".." points at cluster 0 when the parent is the root directory.
+        let parent_ref = if parent == self.root_cluster { 0 } else { parent };
+        let mut dots = [0u8; 2 * DIR_ENTRY_SIZE];
+        for (i, (short_name, target)) in [(b".          ", cluster), (b"..         ", parent_ref)].iter().enumerate() {
+            let raw = &mut dots[i * DIR_ENTRY_SIZE..(i + 1) * DIR_ENTRY_SIZE];
+            raw[0..11].copy_from_slice(*short_name);
+            raw[11] = ATTR_DIRECTORY;
+            LittleEndian::write_u16(&mut raw[20..22], (target >> 16) as u16);
+            LittleEndian::write_u16(&mut raw[26..28], *target as u16);
+        }
+        let offset = self.cluster_offset(cluster)?;
+        self.write_at(offset, &dots)?;
+        self.dev.flush()
+    }
+
+    // Delete a file or an empty directory and release its clusters.
+    pub fn delete(&mut self, components: &[&str]) -> io::Result<()> {
+        let (_, entry) = self.resolve_file(components)?;
+        let entry = entry.ok_or_else(|| not_found("File not found"))?;
+
+        if entry.is_dir() && entry.first_cluster != 0 && !self.read_dir(entry.first_cluster)?.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::Other, "Directory not empty"));
+        }
+
+        // Mark the short entry and all of its long file name slots as free.
+        for &slot in &entry.slot_offsets {
+            self.write_at(slot, &[ENTRY_FREE])?;
+        }
+        self.free_chain(entry.first_cluster)?;
+        self.dev.flush()
+    }
+
+    // Mark every cluster of the chain starting at `start` as free in all FATs.
+    fn free_chain(&mut self, start: u32) -> io::Result<()> {
+        let chain = self.cluster_chain(start)?;
+        for &cluster in &chain {
+            self.write_fat(cluster, 0)?;
+        }
+        if let Some(free) = self.free_clusters.as_mut() {
+            *free = free.saturating_add(chain.len() as u32).min(self.cluster_count);
+        }
+        Ok(())
+    }
+
+    // Count the free entries of the first FAT.
+    fn count_free_clusters(&mut self) -> io::Result<u32> {
+        const CHUNK_ENTRIES: u32 = 16 * 1024;
+        let mut free = 0u32;
+        let mut raw = vec![0u8; CHUNK_ENTRIES as usize * 4];
+        let mut cluster = 2u32;
+        let end = self.cluster_count + 2;
+        while cluster < end {
+            let entries = (end - cluster).min(CHUNK_ENTRIES);
+            let chunk = &mut raw[..entries as usize * 4];
+            let offset = self.fat_entry_offset(0, cluster);
+            self.read_at(offset, chunk)?;
+            free += chunk
+                .chunks_exact(4)
+                .filter(|e| LittleEndian::read_u32(e) & FAT_ENTRY_MASK == 0)
+                .count() as u32;
+            cluster += entries;
+        }
+        Ok(free)
+    }
+
+    // Free and total bytes of the data region.
+    pub fn free_space(&mut self) -> io::Result<(u64, u64)> {
+        let free = match self.free_clusters {
+            Some(free) => free,
+            None => {
+                let free = self.count_free_clusters()?;
+                self.free_clusters = Some(free);
+                free
+            }
+        };
+        Ok((free as u64 * self.cluster_size(), self.cluster_count as u64 * self.cluster_size()))
+    }
+
+    // Write the known free cluster count and the allocation hint to FSInfo, then flush the device.
+    // FAT and directory updates are written immediately, so FSInfo is the only deferred state.
+    pub fn sync(&mut self) -> io::Result<()> {
+        if let (Some(sector), Some(free)) = (self.fs_info_sector, self.free_clusters) {
+            let mut counts = [0u8; 8];
+            LittleEndian::write_u32(&mut counts[0..4], free);
+            LittleEndian::write_u32(&mut counts[4..8], self.alloc_hint);
+            self.write_at(sector as u64 * self.bytes_per_sector as u64 + 488, &counts)?;
+        }
+        self.dev.flush()
+    }
+
+    // Copy between `buf` and the file region starting at `offset` within `chain`.
+    fn transfer(&mut self, chain: &[u32], offset: u64, buf: &mut [u8], write: bool) -> io::Result<()> {
+        let cluster_size = self.cluster_size();
+        let mut done = 0usize;
+        while done < buf.len() {
+            let pos = offset + done as u64;
+            let cluster = *chain
+                .get((pos / cluster_size) as usize)
+                .ok_or_else(|| invalid_data("FAT32 cluster chain shorter than file size"))?;
+            let in_cluster = pos % cluster_size;
+            let len = std::cmp::min((cluster_size - in_cluster) as usize, buf.len() - done);
+            let device_offset = self.cluster_offset(cluster)? + in_cluster;
+            if write {
+                self.write_at(device_offset, &buf[done..done + len])?;
+            } else {
+                self.read_at(device_offset, &mut buf[done..done + len])?;
+            }
+            done += len;
+        }
+        Ok(())
+    }
+
+    // Rewrite the first cluster and size fields of an entry's short slot.
+    fn update_entry(&mut self, entry: &DirEntry) -> io::Result<()> {
+        let offset = entry.short_entry_offset();
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        self.read_at(offset, &mut raw)?;
+        LittleEndian::write_u16(&mut raw[20..22], (entry.first_cluster >> 16) as u16);
+        LittleEndian::write_u16(&mut raw[26..28], entry.first_cluster as u16);
+        LittleEndian::write_u32(&mut raw[28..32], entry.size);
+        self.write_at(offset, &raw)
+    }
+
+    // Add a new entry (with long file name slots when needed) to the directory at `dir_cluster`.
+    fn create_entry(&mut self, dir_cluster: u32, name: &str, attr: u8, first_cluster: u32) -> io::Result<DirEntry> {
+        validate_name(name)?;
+        let existing = self.read_dir(dir_cluster)?;
+        if existing.iter().any(|e| e.name.eq_ignore_ascii_case(name)) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "Entry already exists"));
+        }
+
+        let (short_name, needs_lfn) = match exact_short_name(name) {
+            Some(short) if !existing.iter().any(|e| e.short_name == short) => (short, false),
+            _ => (generate_short_name(name, &existing)?, true),
+        };
+
+        let utf16: Vec<u16> = name.encode_utf16().collect();
+        let lfn_count = if needs_lfn { utf16.len().div_ceil(LFN_CHARS_PER_ENTRY) } else { 0 };
+        let slots = self.find_free_slots(dir_cluster, lfn_count + 1)?;
+
+        let checksum = short_name_checksum(&short_name);
+        for (i, &slot) in slots[..lfn_count].iter().enumerate() {
+            // Slots are written in reverse order: the highest sequence number comes first.
+            let seq = (lfn_count - i) as u8;
+            let mut raw = [0u8; DIR_ENTRY_SIZE];
+            raw[0] = if i == 0 { seq | 0x40 } else { seq };
+            raw[11] = ATTR_LONG_NAME;
+            raw[13] = checksum;
+
+            let start = (seq as usize - 1) * LFN_CHARS_PER_ENTRY;
+            let positions = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+            for (j, pos) in positions.enumerate() {
+                let ch = match utf16.get(start + j) {
+                    Some(&c) => c,
+                    None if start + j == utf16.len() => 0x0000,
+                    None => 0xFFFF,
+                };
+                LittleEndian::write_u16(&mut raw[pos..pos + 2], ch);
+            }
+            self.write_at(slot, &raw)?;
+        }
+
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        raw[0..11].copy_from_slice(&short_name);
+        raw[11] = attr;
+        LittleEndian::write_u16(&mut raw[20..22], (first_cluster >> 16) as u16);
+        LittleEndian::write_u16(&mut raw[26..28], first_cluster as u16);
+        self.write_at(slots[lfn_count], &raw)?;
+
+        Ok(DirEntry {
+            name: name.to_string(),
+            short_name,
+            attr,
+            first_cluster,
+            size: 0,
+            created_time: None,
+            modified_time: None,
+            slot_offsets: slots,
+        })
+    }
+
+    // Find `count` consecutive free directory slots, growing the directory if necessary.
+    fn find_free_slots(&mut self, dir_cluster: u32, count: usize) -> io::Result<Vec<u64>> {
+        let mut chain = self.cluster_chain(dir_cluster)?;
+        let cluster_size = self.cluster_size() as usize;
+        let mut run: Vec<u64> = Vec::with_capacity(count);
+        let mut data = vec![0u8; cluster_size];
+
+        for &c in &chain {
+            let base = self.cluster_offset(c)?;
+            self.read_at(base, &mut data)?;
+            for (i, raw) in data.chunks_exact(DIR_ENTRY_SIZE).enumerate() {
+                if raw[0] == ENTRY_END || raw[0] == ENTRY_FREE {
+                    run.push(base + (i * DIR_ENTRY_SIZE) as u64);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+        }
+
+        // Not enough room: append zeroed clusters to the directory.
+        while run.len() < count {
+            let last = *chain.last().ok_or_else(|| invalid_data("Directory has no clusters"))?;
+            let cluster = self.extend_chain(last)?;
+            chain.push(cluster);
+            let base = self.cluster_offset(cluster)?;
+            for i in 0..cluster_size / DIR_ENTRY_SIZE {
+                run.push(base + (i * DIR_ENTRY_SIZE) as u64);
+                if run.len() == count {
+                    break;
+                }
+            }
+        }
+        Ok(run)
+    }
+}
+
+fn short_name_checksum(short_name: &[u8; 11]) -> u8 {
+    short_name
+        .iter()
+        .fold(0u8, |sum, &b| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(b))
+}
+
+fn assemble_long_name(parts: &mut [(u8, [u16; LFN_CHARS_PER_ENTRY])]) -> String {
+    parts.sort_by_key(|(seq, _)| *seq);
+    let units: Vec<u16> = parts
+        .iter()
+        .flat_map(|(_, chars)| chars.iter().copied())
+        .take_while(|&c| c != 0x0000 && c != 0xFFFF)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+fn decode_short_name(raw: &[u8; 11], case_flags: u8) -> String {
+    let mut base: String = raw[0..8].iter().map(|&b| b as char).collect::<String>().trim_end().to_string();
+    let mut ext: String = raw[8..11].iter().map(|&b| b as char).collect::<String>().trim_end().to_string();
+    // 0x05 in the first byte stands for a real 0xE5.
+    if base.starts_with('\u{5}') {
+        base.replace_range(0..1, "\u{E5}");
+    }
+    // Windows NT lowercase flags.
+    if case_flags & 0x08 != 0 {
+        base = base.to_ascii_lowercase();
+    }
+    if case_flags & 0x10 != 0 {
+        ext = ext.to_ascii_lowercase();
+    }
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+fn validate_name(name: &str) -> io::Result<()> {
+    const INVALID: &[char] = &['"', '*', '/', ':', '<', '>', '?', '\\', '|'];
+    if name.is_empty() || name == "." || name == ".." || name.encode_utf16().count() > 255 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid FAT32 file name"));
+    }
+    if name.chars().any(|c| (c as u32) < 0x20 || INVALID.contains(&c)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Invalid character in FAT32 file name"));
+    }
+    Ok(())
+}
+
+fn is_short_name_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+// Returns the 8.3 form if `name` can be stored without a long file name.
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((b, e)) => (b, e),
+        None => (name, ""),
+    };
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || (name.contains('.') && ext.is_empty()) {
+        return None;
+    }
+    if !base.chars().chain(ext.chars()).all(is_short_name_char) {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short)
+}
+
+// Generate a unique "BASE~N.EXT" alias for a long file name.
+fn generate_short_name(name: &str, existing: &[DirEntry]) -> io::Result<[u8; 11]> {
+    let sanitize = |s: &str, max: usize| -> String {
+        s.chars()
+            .filter(|c| *c != ' ' && *c != '.')
+            .map(|c| {
+                let upper = c.to_ascii_uppercase();
+                if is_short_name_char(upper) { upper } else { '_' }
+            })
+            .take(max)
+            .collect()
+    };
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((b, e)) if !b.is_empty() => (sanitize(b, 8), sanitize(e, 3)),
+        _ => (sanitize(name, 8), String::new()),
+    };
+    let base = if base.is_empty() { "_".to_string() } else { base };
+
+    // Take the first alias no short entry in this directory already uses.
+    for n in 1..=999_999u32 {
+        let suffix = format!("~{}", n);
+        let keep = std::cmp::min(base.len(), 8 - suffix.len());
+        let candidate_base = format!("{}{}", &base[..keep], suffix);
+        let mut short = [b' '; 11];
+        short[..candidate_base.len()].copy_from_slice(candidate_base.as_bytes());
+        short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+        if existing.iter().all(|e| e.short_name != short) {
+            return Ok(short);
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::AlreadyExists, "No free FAT32 short name"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn formatted_volume(size: u64) -> Fat32<Cursor<Vec<u8>>> {
+        let mut cursor = Cursor::new(vec![0u8; size as usize]);
+        crate::format::format_fat32(&mut cursor, size).expect("Failed to format FAT32");
+        Fat32::open(cursor).expect("Failed to open FAT32")
+    }
+
+    #[test]
+    fn test_create_dir_and_write_inside() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        fs.create_dir(&["Documents"]).unwrap();
+        fs.create_dir(&["Documents", "Nested Folder"]).unwrap();
+        fs.write_file(&["Documents", "Nested Folder", "a.txt"], 0, b"nested").unwrap();
+
+        let root = fs.list_files(&[]).unwrap();
+        assert_eq!(root.len(), 1);
+        assert!(root[0].is_dir);
+        assert_eq!(root[0].name, "Documents");
+
+        let nested = fs.list_files(&["documents", "nested folder"]).unwrap();
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].name, "a.txt");
+
+        // "." and ".." are written but hidden from listings; ".." of a top-level directory is 0.
+        let dir = fs.resolve_dir(&["Documents"]).unwrap();
+        let mut raw = [0u8; 2 * DIR_ENTRY_SIZE];
+        let offset = fs.cluster_offset(dir).unwrap();
+        fs.read_at(offset, &mut raw).unwrap();
+        assert_eq!(&raw[0..11], b".          ");
+        assert_eq!(LittleEndian::read_u16(&raw[26..28]) as u32, dir);
+        assert_eq!(&raw[32..43], b"..         ");
+        assert_eq!(LittleEndian::read_u16(&raw[58..60]), 0);
+
+        assert_eq!(fs.create_dir(&["DOCUMENTS"]).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+    }
+
+    #[test]
+    fn test_delete_file_and_empty_dir_frees_clusters() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        fs.create_dir(&["dir"]).unwrap();
+        let data = vec![0x5Au8; fs.cluster_size() as usize * 3];
+        fs.write_file(&["dir", "A long file name.bin"], 0, &data).unwrap();
+
+        let dir = fs.resolve_dir(&["dir"]).unwrap();
+        let file = fs.resolve_file(&["dir", "A long file name.bin"]).unwrap().1.unwrap();
+        let file_chain = fs.cluster_chain(file.first_cluster).unwrap();
+        assert_eq!(file_chain.len(), 3);
+
+        // Non-empty directories are refused.
+        assert!(fs.delete(&["dir"]).is_err());
+
+        fs.delete(&["dir", "a long file name.bin"]).unwrap();
+        assert!(fs.list_files(&["dir"]).unwrap().is_empty());
+        for cluster in file_chain {
+            assert_eq!(fs.read_fat(cluster).unwrap(), 0);
+        }
+
+        fs.delete(&["dir"]).unwrap();
+        assert!(fs.list_files(&[]).unwrap().is_empty());
+        assert_eq!(fs.read_fat(dir).unwrap(), 0);
+        assert_eq!(fs.delete(&["dir"]).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_detects_fat32_boot_sector() {
+        let size = 16 * 1024 * 1024;
+        let mut cursor = Cursor::new(vec![0u8; size as usize]);
+        crate::format::format_fat32(&mut cursor, size).unwrap();
+        let image = cursor.into_inner();
+        assert!(is_fat32_boot_sector(&image[..512]));
+
+        // No signature, or a different type string, is not FAT32.
+        assert!(!is_fat32_boot_sector(&[0u8; 512]));
+        let mut other = image[..512].to_vec();
+        other[82..90].copy_from_slice(b"FAT16   ");
+        assert!(!is_fat32_boot_sector(&other));
+        assert!(!is_fat32_boot_sector(&image[..100]));
+    }
+
+    #[test]
+    fn test_fresh_volume_has_empty_root() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        assert!(fs.list_files(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_list_files_reports_timestamps_and_attributes() {
+        assert_eq!(fat_time_to_unix(0, 0), None);
+        assert_eq!(fat_time_to_unix(0x0021, 0), Some(315_532_800));
+
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        fs.create_dir(&["dir"]).unwrap();
+        fs.write_file(&["notes.txt"], 0, b"timestamps").unwrap();
+
+        // Entries created by this driver leave the timestamps zeroed.
+        let files = fs.list_files(&[]).unwrap();
+        assert!(files.iter().all(|f| f.modified_time.is_none() && f.created_time.is_none()));
+        let dir = files.iter().find(|f| f.name == "dir").unwrap();
+        assert_eq!(dir.attributes, ATTR_DIRECTORY as u32);
+
+        // Stamp 2024-03-15 12:34:56 and mark the file read-only and hidden.
+        let entry = fs.resolve_file(&["notes.txt"]).unwrap().1.unwrap();
+        let offset = entry.short_entry_offset();
+        let mut raw = [0u8; DIR_ENTRY_SIZE];
+        fs.read_at(offset, &mut raw).unwrap();
+        let date = ((2024 - 1980) << 9) | (3 << 5) | 15;
+        let time = (12 << 11) | (34 << 5) | (56 / 2);
+        LittleEndian::write_u16(&mut raw[14..16], time);
+        LittleEndian::write_u16(&mut raw[16..18], date);
+        LittleEndian::write_u16(&mut raw[22..24], time);
+        LittleEndian::write_u16(&mut raw[24..26], date);
+        raw[11] |= ATTR_READ_ONLY | ATTR_HIDDEN;
+        fs.write_at(offset, &raw).unwrap();
+
+        let files = fs.list_files(&[]).unwrap();
+        let file = files.iter().find(|f| f.name == "notes.txt").unwrap();
+        assert_eq!(file.modified_time, Some(1_710_506_096));
+        assert_eq!(file.created_time, Some(1_710_506_096));
+        assert_eq!(file.attributes & (ATTR_READ_ONLY | ATTR_HIDDEN) as u32, (ATTR_READ_ONLY | ATTR_HIDDEN) as u32);
+    }
+
+    #[test]
+    fn test_write_and_read_back_long_name() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        fs.write_file(&["Hello World.txt"], 0, b"hello fat32").unwrap();
+
+        let files = fs.list_files(&[]).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "Hello World.txt");
+        assert_eq!(files[0].size, 11);
+
+        let mut buf = [0u8; 32];
+        let n = fs.read_file(&["hello world.txt"], 0, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"hello fat32");
+        assert_eq!(fs.read_file(&["Hello World.txt"], 11, &mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_long_names_get_distinct_short_aliases() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        fs.write_file(&["Long File A.txt"], 0, b"a").unwrap();
+        fs.write_file(&["Long File B.txt"], 0, b"b").unwrap();
+        // An 8.3 name equal to an existing alias must not reuse that short entry name.
+        fs.write_file(&["LONGFI~1.TXT"], 0, b"c").unwrap();
+
+        let root = fs.root_cluster;
+        let mut shorts: Vec<[u8; 11]> = fs.read_dir(root).unwrap().iter().map(|e| e.short_name).collect();
+        shorts.sort();
+        assert_eq!(shorts, vec![*b"LONGFI~1TXT", *b"LONGFI~2TXT", *b"LONGFI~3TXT"]);
+
+        let mut buf = [0u8; 4];
+        for (name, data) in [("Long File A.txt", b"a"), ("Long File B.txt", b"b"), ("LONGFI~1.TXT", b"c")] {
+            let n = fs.read_file(&[name], 0, &mut buf).unwrap();
+            assert_eq!(&buf[..n], data);
+        }
+    }
+
+    #[test]
+    fn test_write_extends_across_cluster_boundary() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        let cluster_size = fs.cluster_size() as usize;
+
+        fs.write_file(&["DATA.BIN"], 0, &vec![0xAB; cluster_size - 10]).unwrap();
+        fs.write_file(&["DATA.BIN"], (cluster_size - 10) as u64, &[0xCD; 20]).unwrap();
+
+        let (_, entry) = fs.resolve_file(&["DATA.BIN"]).unwrap();
+        let entry = entry.unwrap();
+        assert_eq!(entry.size as usize, cluster_size + 10);
+        assert_eq!(fs.cluster_chain(entry.first_cluster).unwrap().len(), 2);
+
+        let mut buf = vec![0u8; 30];
+        let n = fs.read_file(&["DATA.BIN"], (cluster_size - 20) as u64, &mut buf).unwrap();
+        assert_eq!(n, 30);
+        assert_eq!(&buf[..10], &[0xAB; 10]);
+        assert_eq!(&buf[10..], &[0xCD; 20]);
+    }
+
+    #[test]
+    fn test_write_past_end_zero_fills_gap() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        fs.write_file(&["GAP.BIN"], 0, b"abc").unwrap();
+        fs.write_file(&["GAP.BIN"], 8, b"xyz").unwrap();
+
+        let mut buf = [0xFFu8; 11];
+        assert_eq!(fs.read_file(&["GAP.BIN"], 0, &mut buf).unwrap(), 11);
+        assert_eq!(&buf, b"abc\0\0\0\0\0xyz");
+    }
+
+    #[test]
+    fn test_free_space_tracks_allocations() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        let cluster_size = fs.cluster_size();
+        // The formatter leaves the FSInfo count unknown, so the first query counts the FAT.
+        assert!(fs.free_clusters.is_none());
+        let (free, total) = fs.free_space().unwrap();
+        assert_eq!(total, fs.cluster_count as u64 * cluster_size);
+        // Only the root directory cluster is in use.
+        assert_eq!(free, total - cluster_size);
+
+        fs.write_file(&["a.bin"], 0, &vec![1u8; cluster_size as usize * 3]).unwrap();
+        assert_eq!(fs.free_space().unwrap().0, free - 3 * cluster_size);
+        assert_eq!(fs.count_free_clusters().unwrap() as u64 * cluster_size, free - 3 * cluster_size);
+
+        fs.delete(&["a.bin"]).unwrap();
+        assert_eq!(fs.free_space().unwrap(), (free, total));
+    }
+
+    #[test]
+    fn test_sync_persists_free_count_to_fsinfo() {
+        let mut fs = formatted_volume(4 * 1024 * 1024);
+        let cluster_size = fs.cluster_size();
+        fs.write_file(&["a.bin"], 0, &vec![1u8; cluster_size as usize * 2]).unwrap();
+        let (free, _) = fs.free_space().unwrap();
+
+        // Without sync the FSInfo count stays unknown.
+        assert!(Fat32::open(fs.dev.clone()).unwrap().free_clusters.is_none());
+
+        fs.sync().unwrap();
+        let reopened = Fat32::open(fs.dev.clone()).unwrap();
+        assert_eq!(reopened.free_clusters.map(|c| c as u64 * cluster_size), Some(free));
+    }
+}
@@ -1,5 +1,6 @@
 // Import standard I/O traits and types.
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use byteorder::{ByteOrder, LittleEndian};
 // Import Arc for shared ownership.
 use std::sync::Arc;
 // Import Volume struct from volume module.
@@ -7,58 +8,93 @@ use crate::volume::Volume;
 // Import CallbackReader from io_callback module.
 use crate::io_callback::CallbackReader;
 // Import NTFS implementation.
-use ntfs::{Ntfs, NtfsReadSeek};
+use ntfs::{Ntfs, NtfsAttributeFlags, NtfsReadSeek};
+use ntfs::attribute_value::NtfsAttributeValue;
 // Import ExFAT implementation.
 // use exfat::ExFat;
+// Import FAT32 implementation.
+use crate::fat32::Fat32;
 use zeroize::Zeroize;
 
+// Raw (encrypted) data source a DecryptedReader reads from: CallbackReader over JNI in the app,
+// MemoryReader or a Cursor in tests. Clone lets mount_fs_from_reader probe the boot sector
+// through a copy of the reader.
+pub trait VolumeBackend: Read + Seek + Clone {}
+
+impl<T: Read + Seek + Clone> VolumeBackend for T {}
+
 // Struct representing a reader that decrypts data on the fly.
 // structure definition without Clone derive
 // Struct representing a reader that decrypts data on the fly.
 // structure definition without Clone derive
-pub struct DecryptedReader {
+// The backend defaults to CallbackReader; any VolumeBackend works (e.g. MemoryReader in tests).
+pub struct DecryptedReader<R: VolumeBackend = CallbackReader> {
     // The underlying reader (CallbackReader) that provides raw encrypted data.
-    inner: CallbackReader,
+    inner: R,
     // The volume context containing encryption keys and settings.
     volume: Arc<Volume>,
     // The sector size of the volume.
     sector_size: u64,
-    // The index of the currently buffered sector.
-    current_sector_index: u64,
-    // Buffer to hold the decrypted data of the current sector.
-    // Wrapped in Option for lazy allocation to save memory when listing many files.
-    sector_buffer: Option<Vec<u8>>,
+    // Recently decrypted sectors as (sector index, plaintext), least recently used first.
+    // Buffers are allocated lazily to save memory when listing many files.
+    sector_cache: Vec<(u64, Vec<u8>)>,
+    // Maximum number of sectors kept in sector_cache (at least 1).
+    cache_capacity: usize,
+    // Sectors fetched in one inner read on sequential access (1 disables read-ahead).
+    read_ahead: usize,
+    // Index of the sector requested last, to detect sequential access.
+    last_sector: Option<u64>,
     // Logical position of this reader (decoupled from inner reader).
     position: u64,
+    // Wipe all cached plaintext when a seek leaves the current sector (see set_wipe_on_seek).
+    wipe_on_seek: bool,
+    // Wiped sector buffers kept for reuse, so wiping does not churn allocations.
+    spare_buffers: Vec<Vec<u8>>,
 }
 
-impl Drop for DecryptedReader {
+// Default number of decrypted sectors cached per reader (16 * 4 KiB at most).
+pub const DEFAULT_SECTOR_CACHE_CAPACITY: usize = 16;
+
+// Default read-ahead window in sectors for sequential reads.
+pub const DEFAULT_READ_AHEAD_SECTORS: usize = 8;
+
+impl<R: VolumeBackend> Drop for DecryptedReader<R> {
     fn drop(&mut self) {
-        if let Some(mut buf) = self.sector_buffer.take() {
+        // The cache holds plaintext.
+        for (_, buf) in self.sector_cache.iter_mut() {
             buf.zeroize();
         }
     }
 }
 
-impl Clone for DecryptedReader {
+impl<R: VolumeBackend> Clone for DecryptedReader<R> {
     fn clone(&self) -> Self {
         Self {
             inner: self.inner.clone(),
             volume: self.volume.clone(),
             sector_size: self.sector_size,
-            // Reset state in clone to avoid stale buffer/index and unnecessary allocation
-            current_sector_index: u64::MAX,
-            sector_buffer: None,
+            // Start with an empty cache to avoid stale sectors and unnecessary allocation
+            sector_cache: Vec::new(),
+            cache_capacity: self.cache_capacity,
+            read_ahead: self.read_ahead,
+            last_sector: None,
             // Copy position state
             position: self.position,
+            wipe_on_seek: self.wipe_on_seek,
+            spare_buffers: Vec::new(),
         }
     }
 }
 
 // Implementation of DecryptedReader methods.
-impl DecryptedReader {
-    // Constructor to create a new DecryptedReader.
-    pub fn new(inner: CallbackReader, volume: Arc<Volume>) -> Self {
+impl<R: VolumeBackend> DecryptedReader<R> {
+    // Constructor to create a new DecryptedReader over any encrypted data source.
+    pub fn new(inner: R, volume: Arc<Volume>) -> Self {
+        Self::with_cache_capacity(inner, volume, DEFAULT_SECTOR_CACHE_CAPACITY)
+    }
+
+    // Constructor with an explicit sector cache size (in sectors, minimum 1).
+    pub fn with_cache_capacity(inner: R, volume: Arc<Volume>, cache_capacity: usize) -> Self {
         // Get the sector size from the volume.
         let sector_size = volume.sector_size() as u64;
         // Return a new instance.
@@ -66,21 +102,81 @@ impl DecryptedReader {
             inner,
             volume,
             sector_size,
-            // Initialize with an invalid sector index.
-            current_sector_index: u64::MAX, // Invalid
             // Lazy allocation.
-            sector_buffer: None,
+            sector_cache: Vec::new(),
+            cache_capacity: cache_capacity.max(1),
+            read_ahead: DEFAULT_READ_AHEAD_SECTORS,
+            last_sector: None,
             position: 0,
+            wipe_on_seek: true,
+            spare_buffers: Vec::new(),
+        }
+    }
+
+    // Set the read-ahead window in sectors (0 or 1 disables read-ahead).
+    pub fn set_read_ahead(&mut self, sectors: usize) {
+        self.read_ahead = sectors.max(1);
+    }
+
+    // Wipe cached plaintext whenever a seek moves to a different sector. On by default, trading
+    // the cache hits of random access for a shorter plaintext-in-memory window.
+    pub fn set_wipe_on_seek(&mut self, enabled: bool) {
+        self.wipe_on_seek = enabled;
+    }
+
+    // Zeroize every cached sector. The buffers stay allocated for reuse by later reads.
+    fn wipe_cache(&mut self) {
+        for (_, mut buf) in self.sector_cache.drain(..) {
+            buf.as_mut_slice().zeroize();
+            self.spare_buffers.push(buf);
+        }
+        self.last_sector = None;
+    }
+
+    // A buffer for a newly cached sector: the least recently used one once the cache is full,
+    // else a wiped spare, else a fresh allocation.
+    fn take_buffer(&mut self) -> Vec<u8> {
+        if self.sector_cache.len() >= self.cache_capacity {
+            self.sector_cache.remove(0).1
+        } else {
+            self.spare_buffers.pop().unwrap_or_else(|| vec![0u8; self.sector_size as usize])
         }
     }
 
+    // Drop cached plaintext for every sector overlapping [start, end).
+    fn invalidate_range(&mut self, start: u64, end: u64) {
+        let first = start / self.sector_size;
+        let last = end.div_ceil(self.sector_size);
+        self.sector_cache.retain_mut(|(index, buf)| {
+            let stale = *index >= first && *index < last;
+            if stale {
+                buf.zeroize();
+            }
+            !stale
+        });
+    }
+
     // Helper method to read and decrypt a specific sector.
+    // On success the sector is the last (most recently used) entry of sector_cache.
     fn read_sector(&mut self, sector_index: u64) -> io::Result<()> {
-        // If the requested sector is already in the buffer, do nothing.
-        if self.current_sector_index == sector_index {
+        // If the requested sector is cached, just mark it as most recently used.
+        let sequential = self.last_sector.and_then(|last| last.checked_add(1)) == Some(sector_index);
+        self.last_sector = Some(sector_index);
+
+        if let Some(pos) = self.sector_cache.iter().position(|(index, _)| *index == sector_index) {
+            let entry = self.sector_cache.remove(pos);
+            self.sector_cache.push(entry);
             return Ok(());
         }
 
+        // On sequential access fetch the next sectors too. The window leaves room for at
+        // least one older sector, so read-ahead never flushes the whole cache.
+        let remaining = self.volume.size().div_ceil(self.sector_size).saturating_sub(sector_index);
+        let window = self.read_ahead.min(self.cache_capacity - 1).min(remaining as usize);
+        if sequential && window > 1 {
+            return self.read_sectors_ahead(sector_index, window);
+        }
+
         // Calculate the byte offset of the sector in the underlying encrypted volume.
         // The decrypted filesystem starts at volume.data_offset().
         let offset = sector_index
@@ -95,19 +191,21 @@ impl DecryptedReader {
         // Read encrypted data into the buffer.
         // Use read loop to handle potential partial reads or EOF if file is truncated.
         // We pad with zeros if we cannot read a full sector (best effort for recovery/inspection).
-        // Ensure buffer is allocated.
-        if self.sector_buffer.is_none() {
-            self.sector_buffer = Some(vec![0u8; self.sector_size as usize]);
-        }
-        let buffer = self.sector_buffer.as_mut().unwrap();
+        // Reuse the least recently used buffer once the cache is full.
+        let mut buffer = self.take_buffer();
 
+        // Never read past volume_data_size: the last sector may only partly belong to the volume.
+        let read_limit = self.valid_sector_bytes(sector_index).div_ceil(512) * 512;
         let mut read_len = 0;
-        while read_len < buffer.len() {
-            match self.inner.read(&mut buffer[read_len..]) {
+        while read_len < read_limit {
+            match self.inner.read(&mut buffer[read_len..read_limit]) {
                 Ok(0) => break, // EOF
                 Ok(n) => read_len += n,
                 Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                Err(e) => return Err(e),
+                Err(e) => {
+                    buffer.zeroize();
+                    return Err(e);
+                }
             }
         }
         
@@ -119,19 +217,79 @@ impl DecryptedReader {
              }
         }
 
-        // Decrypt the data in-place using the volume's decrypt_sector method.
-        self.volume
-            .decrypt_sector(sector_index, buffer)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Decrypt error: {}", e)))?;
+        // Decrypt the data in-place.
+        if let Err(e) = self.decrypt_sector_in_place(sector_index, &mut buffer) {
+            buffer.zeroize();
+            return Err(e);
+        }
+
+        // Insert as the most recently used sector.
+        self.sector_cache.push((sector_index, buffer));
+        Ok(())
+    }
+
+    // Number of bytes of a sector that lie within volume_data_size (the sector size, except
+    // for the last sector of a volume whose size is not a sector multiple).
+    fn valid_sector_bytes(&self, sector_index: u64) -> usize {
+        let start = sector_index.saturating_mul(self.sector_size);
+        self.volume.size().saturating_sub(start).min(self.sector_size) as usize
+    }
+
+    // Decrypt one sector read from the inner source; a partial last sector has its tail zeroed.
+    fn decrypt_sector_in_place(&self, sector_index: u64, buffer: &mut [u8]) -> io::Result<()> {
+        let result = if self.valid_sector_bytes(sector_index) < buffer.len() {
+            self.volume.decrypt_tail_sector(sector_index, buffer)
+        } else {
+            self.volume.decrypt_sector(sector_index, buffer)
+        };
+        result.map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Decrypt error: {}", e)))
+    }
+
+    // Read `count` sectors starting at `first_index` with one inner read and cache them all.
+    // The requested sector ends up as the most recently used entry.
+    fn read_sectors_ahead(&mut self, first_index: u64, count: usize) -> io::Result<()> {
+        let sector_size = self.sector_size as usize;
+        let offset = first_index
+            .checked_mul(self.sector_size)
+            .and_then(|o| o.checked_add(self.volume.data_offset()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Offset overflow"))?;
+        self.inner.seek(SeekFrom::Start(offset))?;
+
+        // Ciphertext only; missing bytes past the end of the source stay zero.
+        // The read stops at the 512-byte unit holding the last byte of the volume.
+        let mut raw = vec![0u8; sector_size * count];
+        let volume_left = self.volume.size().saturating_sub(first_index.saturating_mul(self.sector_size));
+        let read_limit = volume_left.div_ceil(512).saturating_mul(512).min(raw.len() as u64) as usize;
+        let mut read_len = 0;
+        while read_len < read_limit {
+            match self.inner.read(&mut raw[read_len..read_limit]) {
+                Ok(0) => break,
+                Ok(n) => read_len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
 
-        // Update the current sector index.
-        self.current_sector_index = sector_index;
+        // Prefetched sectors first (skipping cached ones), the requested sector last.
+        for i in (1..count).chain(std::iter::once(0)) {
+            let index = first_index + i as u64;
+            if i != 0 && self.sector_cache.iter().any(|(cached, _)| *cached == index) {
+                continue;
+            }
+            let mut buffer = self.take_buffer();
+            buffer.copy_from_slice(&raw[i * sector_size..(i + 1) * sector_size]);
+            if let Err(e) = self.decrypt_sector_in_place(index, &mut buffer) {
+                buffer.zeroize();
+                return Err(e);
+            }
+            self.sector_cache.push((index, buffer));
+        }
         Ok(())
     }
 }
 
 // Implement Read trait for DecryptedReader.
-impl Read for DecryptedReader {
+impl<R: VolumeBackend> Read for DecryptedReader<R> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         if buf.is_empty() {
             return Ok(0);
@@ -166,16 +324,18 @@ impl Read for DecryptedReader {
         // Ensure the correct sector is loaded and decrypted.
         self.read_sector(sector_index)?;
 
-        // Calculate available bytes in this sector.
+        // Calculate available bytes in this sector, stopping at the end of the volume.
         let available = sector_size.checked_sub(offset_in_sector).ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidData, "Sector offset out of bounds")
         })?;
+        let available = (self.volume.size() - current_pos).min(available as u64) as usize;
         let to_read = std::cmp::min(buf.len(), available);
 
         // Copy decrypted data.
         let buffer = self
-            .sector_buffer
-            .as_ref()
+            .sector_cache
+            .last()
+            .map(|(_, buf)| buf)
             .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing sector buffer"))?;
         let end = offset_in_sector.checked_add(to_read).ok_or_else(|| {
             io::Error::new(io::ErrorKind::InvalidData, "Read offset overflow")
@@ -199,7 +359,7 @@ impl Read for DecryptedReader {
 }
 
 // Implement Seek trait for DecryptedReader.
-impl Seek for DecryptedReader {
+impl<R: VolumeBackend> Seek for DecryptedReader<R> {
     fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
         let new_pos = match pos {
             SeekFrom::Start(p) => p,
@@ -220,21 +380,275 @@ impl Seek for DecryptedReader {
                 }
             }
         };
+        if self.wipe_on_seek && new_pos / self.sector_size.max(1) != self.position / self.sector_size.max(1) {
+            self.wipe_cache();
+        }
+        self.position = new_pos;
+        Ok(new_pos)
+    }
+}
+
+// Implement Write trait for DecryptedReader.
+// Writes go through a DecryptedWriter over the same backend and volume.
+impl<R: VolumeBackend + Write> Write for DecryptedReader<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut writer = DecryptedWriter::new(&mut self.inner, self.volume.clone());
+        writer.seek(SeekFrom::Start(self.position))?;
+        let written = writer.write(buf)?;
+        drop(writer);
+
+        // Cached plaintext for the written sectors is now stale.
+        self.invalidate_range(self.position, self.position + written as u64);
+
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Struct representing a writer that encrypts data on the fly.
+// Partial sector writes read, decrypt and patch the existing sector before re-encrypting it.
+// The backend defaults to CallbackReader; any Read + Write + Seek works (e.g. for tests).
+pub struct DecryptedWriter<W: Read + Write + Seek = CallbackReader> {
+    // The underlying device that provides and receives the encrypted data.
+    inner: W,
+    // The volume context containing encryption keys and settings.
+    volume: Arc<Volume>,
+    // The sector size of the volume.
+    sector_size: u64,
+    // Buffer holding the plaintext of the sector being written.
+    // Wrapped in Option for lazy allocation, wiped on drop.
+    sector_buffer: Option<Vec<u8>>,
+    // Logical position of this writer within the decrypted data area.
+    position: u64,
+}
+
+impl<W: Read + Write + Seek> Drop for DecryptedWriter<W> {
+    fn drop(&mut self) {
+        if let Some(mut buf) = self.sector_buffer.take() {
+            buf.zeroize();
+        }
+    }
+}
+
+// Implementation of DecryptedWriter methods.
+impl<W: Read + Write + Seek> DecryptedWriter<W> {
+    // Constructor to create a new DecryptedWriter.
+    pub fn new(inner: W, volume: Arc<Volume>) -> Self {
+        let sector_size = volume.sector_size() as u64;
+        Self {
+            inner,
+            volume,
+            sector_size,
+            sector_buffer: None,
+            position: 0,
+        }
+    }
+
+    // Byte offset of a sector in the underlying encrypted volume.
+    fn sector_offset(&self, sector_index: u64) -> io::Result<u64> {
+        sector_index
+            .checked_mul(self.sector_size)
+            .and_then(|o| o.checked_add(self.volume.data_offset()))
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Offset overflow"))
+    }
+
+    // Helper method to read and decrypt a sector into `buffer`.
+    fn read_sector(inner: &mut W, volume: &Volume, offset: u64, sector_index: u64, buffer: &mut [u8]) -> io::Result<()> {
+        inner.seek(SeekFrom::Start(offset))?;
+
+        // Pad with zeros if the underlying data is truncated, like DecryptedReader.
+        let mut read_len = 0;
+        while read_len < buffer.len() {
+            match inner.read(&mut buffer[read_len..]) {
+                Ok(0) => break,
+                Ok(n) => read_len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        buffer[read_len..].fill(0);
+
+        volume
+            .decrypt_sector(sector_index, buffer)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Decrypt error: {}", e)))
+    }
+}
+
+// Implement Write trait for DecryptedWriter.
+impl<W: Read + Write + Seek> Write for DecryptedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // Refuse before touching the device; encrypt_sector would reject it too.
+        if self.volume.is_read_only() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Volume is mounted read-only"));
+        }
+
+        let current_pos = self.position;
+
+        if current_pos >= self.volume.size() {
+            return Err(io::Error::new(io::ErrorKind::WriteZero, "Write past end of volume"));
+        }
+
+        if self.sector_size == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Sector size cannot be zero",
+            ));
+        }
+
+        let sector_size = self.sector_size as usize;
+        let sector_index = current_pos / self.sector_size;
+        let offset_in_sector = (current_pos % self.sector_size) as usize;
+        let to_write = std::cmp::min(buf.len(), sector_size - offset_in_sector);
+        let offset = self.sector_offset(sector_index)?;
+
+        // Ensure buffer is allocated.
+        let sector = self.sector_buffer.get_or_insert_with(|| vec![0u8; sector_size]);
+
+        // Partial writes need the existing contents (read-modify-write), full sector writes do not.
+        if to_write < sector_size {
+            Self::read_sector(&mut self.inner, &self.volume, offset, sector_index, sector)?;
+        }
+        sector[offset_in_sector..offset_in_sector + to_write].copy_from_slice(&buf[..to_write]);
+
+        // Encrypt in place using the volume's encrypt_sector method, which also
+        // enforces hidden volume protection.
+        let result = self
+            .volume
+            .encrypt_sector(sector_index, sector)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Encrypt error: {}", e)))
+            .and_then(|_| {
+                self.inner.seek(SeekFrom::Start(offset))?;
+                self.inner.write_all(sector)
+            });
+        // Do not keep plaintext (or ciphertext) around between calls.
+        sector.as_mut_slice().zeroize();
+        result?;
+
+        self.position += to_write as u64;
+        Ok(to_write)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Implement Seek trait for DecryptedWriter.
+impl<W: Read + Write + Seek> Seek for DecryptedWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => p,
+            SeekFrom::End(p) => {
+                let size = self.volume.size();
+                if p >= 0 {
+                    size.checked_add(p as u64).ok_or(io::Error::new(io::ErrorKind::Other, "Overflow"))?
+                } else {
+                    size.checked_sub(p.unsigned_abs()).ok_or(io::Error::new(io::ErrorKind::Other, "Underflow"))?
+                }
+            }
+            SeekFrom::Current(p) => {
+                if p >= 0 {
+                    self.position.checked_add(p as u64).ok_or(io::Error::new(io::ErrorKind::Other, "Overflow"))?
+                } else {
+                    self.position.checked_sub(p.unsigned_abs()).ok_or(io::Error::new(io::ErrorKind::Other, "Underflow"))?
+                }
+            }
+        };
         self.position = new_pos;
         Ok(new_pos)
     }
 }
 
 // Enum representing supported file systems.
-pub enum SupportedFileSystem {
+// Generic over the encrypted data source; the JNI layer uses CallbackReader.
+pub enum SupportedFileSystem<R: VolumeBackend + Write = CallbackReader> {
     // NTFS file system wrapper.
-    // NTFS file system wrapper.
-    Ntfs { fs: Box<Ntfs>, reader: DecryptedReader }, 
-    // ExFAT file system wrapper.
-    ExFat(Box<exfat::ExFat<DecryptedReader>>),
+    Ntfs { fs: Box<Ntfs>, reader: DecryptedReader<R> },
+    // ExFAT file system wrapper. The exfat crate validates the volume at mount; listing, reading and
+    // space queries parse the on-disk structures through the reader, since the crate does not expose them.
+    ExFat {
+        #[allow(dead_code)]
+        fs: Box<exfat::ExFat<DecryptedReader<R>>>,
+        reader: DecryptedReader<R>,
+    },
+    // FAT32 file system wrapper (read/write).
+    Fat32(Box<Fat32<DecryptedReader<R>>>),
+}
+
+// Detect and open the file system of a mounted volume read through `reader` (the raw container).
+// Tries FAT32 (by boot sector signature), NTFS, exFAT, then a full FAT32 parse.
+// A boot sector read failure is returned as is; no recognized file system gives ErrorKind::Unsupported.
+pub fn mount_fs_from_reader<R: VolumeBackend + Write>(
+    volume: Arc<Volume>,
+    reader: R,
+) -> io::Result<SupportedFileSystem<R>> {
+    mount_fs_from_decrypted(DecryptedReader::new(reader, volume))
+}
+
+// Like mount_fs_from_reader, over a DecryptedReader the caller already configured. Every reader
+// the mounted file system uses is a clone of it and keeps its settings (e.g. wipe_on_seek).
+pub fn mount_fs_from_decrypted<R: VolumeBackend + Write>(
+    decrypted_reader: DecryptedReader<R>,
+) -> io::Result<SupportedFileSystem<R>> {
+    // Read the boot sector first so I/O failures are not reported as an unknown file system.
+    let mut boot_sector = [0u8; 512];
+    decrypted_reader.clone().read_exact(&mut boot_sector)?;
+
+    // Errors from each file system probe, reported if none of them match.
+    let mut probe_errors: Vec<String> = Vec::new();
+
+    // Volumes formatted by create_volume are FAT32; recognise them from the boot sector
+    // before running the heavier NTFS and exFAT probes.
+    let fat32_signature = crate::fat32::is_fat32_boot_sector(&boot_sector);
+    if fat32_signature {
+        match Fat32::open(decrypted_reader.clone()) {
+            Ok(fat32_instance) => return Ok(SupportedFileSystem::Fat32(Box::new(fat32_instance))),
+            Err(e) => probe_errors.push(format!("FAT32: {}", e)),
+        }
+    }
+
+    // Try mounting as NTFS.
+    match Ntfs::new(&mut decrypted_reader.clone()) {
+        Ok(ntfs_instance) => {
+            return Ok(SupportedFileSystem::Ntfs { fs: Box::new(ntfs_instance), reader: decrypted_reader.clone() });
+        }
+        Err(e) => probe_errors.push(format!("NTFS: {}", e)),
+    }
+
+    // Try mounting as exFAT.
+    // We use the same reader clone (DecryptedReader is cheap to clone).
+    match exfat::ExFat::open(decrypted_reader.clone()) {
+        // Store the ExFat instance directly (wrapped in Box) to allow on-demand iteration.
+        Ok(exfat_instance) => {
+            return Ok(SupportedFileSystem::ExFat { fs: Box::new(exfat_instance), reader: decrypted_reader })
+        }
+        Err(e) => probe_errors.push(format!("exFAT: {}", e)),
+    }
+
+    // Fall back to a full FAT32 parse when the type string is missing (it is informational only).
+    if !fat32_signature {
+        match Fat32::open(decrypted_reader) {
+            Ok(fat32_instance) => return Ok(SupportedFileSystem::Fat32(Box::new(fat32_instance))),
+            Err(e) => probe_errors.push(format!("FAT32: {}", e)),
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("No recognized file system ({})", probe_errors.join("; ")),
+    ))
 }
 
 // Struct to hold information about a file or directory.
+#[derive(Debug)]
 pub struct FileInfo {
     // Name of the file or directory.
     pub name: String,
@@ -242,12 +656,36 @@ pub struct FileInfo {
     pub is_dir: bool,
     // Size of the file in bytes.
     pub size: u64,
+    // Last modification time in seconds since the Unix epoch, if the file system records it.
+    pub modified_time: Option<u64>,
+    // Creation time in seconds since the Unix epoch, if the file system records it.
+    pub created_time: Option<u64>,
+    // FAT/Windows attribute bits (0x01 read-only, 0x02 hidden, 0x04 system, 0x10 directory,
+    // 0x20 archive). NTFS uses the same values. 0 when unknown.
+    pub attributes: u32,
+}
+
+// Seconds between the NTFS epoch (1601-01-01) and the Unix epoch.
+const NTFS_TO_UNIX_EPOCH_SECS: u64 = 11_644_473_600;
+
+// Convert an NTFS timestamp (100 ns intervals since 1601) to Unix seconds.
+// Times before 1970 (including the unset value 0) give None.
+pub(crate) fn ntfs_time_to_unix(nt_timestamp: u64) -> Option<u64> {
+    (nt_timestamp / 10_000_000).checked_sub(NTFS_TO_UNIX_EPOCH_SECS)
 }
 
 // Implementation of methods for SupportedFileSystem.
-impl SupportedFileSystem {
+impl<R: VolumeBackend + Write> SupportedFileSystem<R> {
     // Method to list files in a given directory path.
     pub fn list_files(&mut self, path: &str) -> io::Result<Vec<FileInfo>> {
+        self.list_files_paged(path, 0, usize::MAX).map(|(files, _)| files)
+    }
+
+    // Method to list one page of a directory: up to `limit` entries starting at entry `offset`,
+    // plus the total number of entries. Pages follow list_files order; an offset past the end
+    // gives an empty page. NTFS only reads file records for entries on the page; exFAT and FAT32
+    // parse the whole directory into a Vec and then skip to the page.
+    pub fn list_files_paged(&mut self, path: &str, offset: usize, limit: usize) -> io::Result<(Vec<FileInfo>, usize)> {
         // Remove leading/trailing slashes from the path.
         let path = path.trim_matches('/');
         
@@ -264,6 +702,8 @@ impl SupportedFileSystem {
             // Split by forward slash and filter empty components.
             path.split('/').filter(|s| !s.is_empty()).collect()
         };
+        // Index one past the last entry of the page.
+        let page_end = offset.saturating_add(limit);
 
         match self {
             // Handle NTFS file system.
@@ -272,52 +712,20 @@ impl SupportedFileSystem {
                 // let reader = ntfs.get_mut(); // No, we allow mutable access to reader
                 reader.seek(SeekFrom::Start(0))?;
 
-                // Start at the root directory.
-                let mut current_dir = fs
-                    .root_directory(reader)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-
-                // Traverse the directory structure based on path components.
-                for component in components {
-                    // Get the index of the current directory.
-                    let index = current_dir
-                        .directory_index(reader)
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                    let mut found = false;
-                    // Iterate through entries in the directory (NTFS uses B-Tree like structure)
-                    let mut entries = index.entries();
-                    while let Some(entry) = entries.next(&mut *reader) {
-                        let entry: ntfs::NtfsIndexEntry<ntfs::indexes::NtfsFileNameIndex> = entry?;
-                        // Check if the entry name matches the current component.
-                        let key_res = entry.key().transpose().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Key error: {}", e)))?;
-                        if let Some(key) = key_res {
-                            if key.name().to_string_lossy() == component && key.is_directory() {
-                                let id = entry.file_reference().file_record_number();
-                                current_dir = fs.file(reader, id).map_err(|e| {
-                                    io::Error::new(io::ErrorKind::Other, e.to_string())
-                                })?;
-                                found = true;
-                                break;
-                            }
-                        }
-                    }
-                    // If component not found, return NotFound error.
-                    if !found {
-                        return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
-                    }
-                }
+                // Walk down to the target directory.
+                let current_dir = ntfs_find_dir(fs, reader, &components)?;
 
                 // We are now at the target directory. Get its index/entries.
                 let index = current_dir
                     .directory_index(reader)
                     .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
                 let mut results = Vec::new();
+                // Number of listed entries seen so far (the index of the next one).
+                let mut total = 0;
                 // Iterate through entries to collect file info.
                 let mut entries = index.entries();
                 
-                // For NTFS we iterate. Should validly limit for pagination if needed, but for now standard iteration.
-                // NOTE: NTFS entries iterator might be efficient, but large directories can still be slow.
-                // However, unlike ExFat code previously, this iterates one by one.
+                // Entries outside the page are only counted, which skips their file record reads.
                 while let Some(entry_res) = entries.next(reader) {
                     // Handle individual entry errors
                     let entry: ntfs::NtfsIndexEntry<ntfs::indexes::NtfsFileNameIndex> = match entry_res {
@@ -343,164 +751,47 @@ impl SupportedFileSystem {
                         if name == "." || name == ".." {
                             continue;
                         }
+                        total += 1;
+                        if total <= offset || total > page_end {
+                            continue;
+                        }
     
+                        // Prefer $STANDARD_INFORMATION, which Windows keeps current, over the
+                        // $FILE_NAME copy in the index that is only refreshed on rename.
+                        let id = entry.file_reference().file_record_number();
+                        let (modified, created, attributes) = match fs.file(reader, id).and_then(|f| f.info()) {
+                            Ok(info) => (info.modification_time(), info.creation_time(), info.file_attributes()),
+                            Err(e) => {
+                                log::warn!("Failed to read NTFS standard information: {}", e);
+                                (key.modification_time(), key.creation_time(), key.file_attributes())
+                            }
+                        };
+
                         results.push(FileInfo {
                             name: name.to_string(),
                             is_dir: key.is_directory(),
                             size: key.data_size(),
+                            modified_time: ntfs_time_to_unix(modified.nt_timestamp()),
+                            created_time: ntfs_time_to_unix(created.nt_timestamp()),
+                            attributes: attributes.bits(),
                         });
                     }
                 }
-                Ok(results)
+                Ok((results, total))
             }
-            // Handle ExFAT file system.
-            SupportedFileSystem::ExFat(exfat) => {
-                // EXFAT TRAVERSAL
-                // We start with root directory iterator
-                // Currently exfat crate `root_directory()` returns an Iterator of items.
-                // We cannot "seek" inside iterator unless we consume it.
-                // To traverse, we find the Directory item and open it.
-                
-                // Note: exfat crate usage pattern:
-                // `exfat.root_directory()` -> impl Iterator<Item=Item<R>>
-                // Item can be File/Directory.
-                // Directory has `open()` which returns a new Iterator (directory content).
-                
-                // We need to store the current iterator? No, we just need to drill down.
-                // But `root_directory()` takes `&self` (ref to ExFat struct).
-                // It returns an iterator that borrows from ExFat instance?
-                // Or works with the reader?
-                
-                // Strategy:
-                // 1. Get root directory iterator.
-                // 2. Iterate to find component.
-                // 3. If found (Dir), open it -> get new iterator.
-                // 4. Repeat.
-                
-                // Since `exfat` crate iterators are lazy, this handles the OOM issue (we don't collect until the end).
-                
-                // Problem: We need to traverse efficiently.
-                // Since this is listing files, we drill down to `path` then collect `path` children.
-                
-                // Root iterator
-                // We use a recursive approach or loop?
-                // `exfat.root_directory()` gives root items.
-                
-                // We need to hold the "Current Directory Iterator" logic.
-                // Since `exfat::directory::Directory` properties `open()` returns `Result<impl Iterator>`.
-                // We can't type-erase `impl Iterator` easily without Box<dyn Iterator>.
-                
-                // Let's iterate manually.
-                
-                // Need to find the target directory first.
-                // For root (empty components), we just list root.
-                
-                if components.is_empty() {
-                    let mut results = Vec::new();
-                    // Stub: ExFat root_dir method not found. Return empty iterator.
-                    let root_iter = Vec::<exfat::directory::Item<DecryptedReader>>::new().into_iter();
-                    
-                    for item in root_iter {
-                        match item {
-                             exfat::directory::Item::Directory(d) => results.push(FileInfo { 
-                                 name: d.name().to_string(), 
-                                 is_dir: true, 
-                                 size: 0 
-                             }),
-                             exfat::directory::Item::File(f) => results.push(FileInfo { 
-                                 name: f.name().to_string(), 
-                                 is_dir: false, 
-                                 size: f.len() 
-                             }),
-                        }
-                    }
-                    return Ok(results);
-                }
-                
-                // Drill down
-                // This is bit tricky because each level gives us an iterator we must consume to find the next dir.
-                // And `exfat` crate structures.
-                
-                // We need to implement a helper to "find directory in iterator".
-                
-                // Since we can't easily recurse with different iterator types (roots vs subdirs might differ in type signature in some crates, but usually `exfat` is consistent?),
-                // In `exfat` 0.1, `root_directory` returns `Result<Iter<'a, R>>`.
-                // `Directory::open` returns `Result<Iter<'a, R>>`.
-                // Usage: `let mut iter = exfat.root_directory()?;`
-                
-                // Let's implement the loop.
-                // Stub: ExFat root_dir method not found. Return empty iterator.
-                let mut current_iter = Vec::<exfat::directory::Item<DecryptedReader>>::new().into_iter();
-
-                // We need to process components one by one.
-                // But `open()` creates a NEW iterator.
-                // We can't assign `current_iter = new_iter` if they are derived from different lifecycles or ownerships?
-                // Actually they borrow `reader` from `exfat` struct usually.
-                
-                // For the loop to work, we need an intermediate `found_dir` object.
-                // `exfat::directory::Directory`.
-                
-                let mut target_dir_content: Option<Vec<FileInfo>> = None;
-                
-                // We have to restart logic for drill down.
-                // Since we can't easily keep the iterator "state" across components in a simple loop due to Rust type system (if `open` returns distinct type or lifetime issues),
-                // We will rely on recursion or just careful logic.
-                // Actually, `exfat` crate `Iter` type is likely `exfat::directory::Iter<'a, R>`.
-                
-                // Let's try to simulate checking each component.
-                
-                // Currently `exfat` 0.1 doesn't seem to support arbitrary path lookup, we have to iterate.
-                
-                // Recursive lookup helper?
-                fn find_dir<'a, R>(
-                    mut iter: impl Iterator<Item = exfat::directory::Item<R>>,
-                    components: &[&str],
-                ) -> io::Result<Vec<FileInfo>>
-                where R: io::Read + io::Seek
-                 {
-                    if components.is_empty() {
-                        // Current iter is the target. List it.
-                        let mut results = Vec::new();
-                         for item in iter {
-                            match item {
-                                 exfat::directory::Item::Directory(d) => results.push(FileInfo { 
-                                     name: d.name().to_string(), 
-                                     is_dir: true, 
-                                     size: 0 
-                                 }),
-                                 exfat::directory::Item::File(f) => results.push(FileInfo { 
-                                     name: f.name().to_string(), 
-                                     is_dir: false, 
-                                     size: f.len() 
-                                 }),
-                            }
-                        }
-                        return Ok(results);
-                    }
-                    
-                    let target_name = components[0];
-                    let remaining = &components[1..];
-                    
-                    for item in iter {
-                         if let exfat::directory::Item::Directory(d) = item {
-                             if d.name() == target_name {
-                                 let sub_iter = d.open().map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?.into_iter();
-                                 return find_dir(sub_iter, remaining);
-                             }
-                         }
-                    }
-                    Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"))
-                }
-                
-                // Stub: ExFat root_dir method not found. Return empty iterator.
-                let root_iter = Vec::<exfat::directory::Item<DecryptedReader>>::new().into_iter();
-                
-                find_dir(root_iter, &components)
+            // Handle ExFAT file system. Entry sets are parsed directly so sizes and timestamps are available.
+            SupportedFileSystem::ExFat { reader, .. } => exfat_list_files(reader, &components, offset, limit),
+            // Handle FAT32 file system.
+            SupportedFileSystem::Fat32(fat) => {
+                let files = fat.list_files(&components)?;
+                let total = files.len();
+                Ok((files.into_iter().skip(offset).take(limit).collect(), total))
             }
         }
     }
 
     // Method to read data from a specific file.
+    // Returns the number of bytes read, which is 0 at or past the end of the file; Err is a real failure.
     pub fn read_file(&mut self, path: &str, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
         let path = path.trim_matches('/');
  
@@ -520,35 +811,8 @@ impl SupportedFileSystem {
             SupportedFileSystem::Ntfs { fs, reader } => {
                 // let reader = ntfs.get_mut();
                 reader.seek(SeekFrom::Start(0))?;
-                
-                let mut current_dir = fs
-                    .root_directory(reader)
-                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
 
-                for component in dir_components {
-                    let index = current_dir
-                        .directory_index(reader)
-                        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                    let mut found = false;
-                    let mut entries = index.entries();
-                    while let Some(entry) = entries.next(reader) {
-                        let entry: ntfs::NtfsIndexEntry<ntfs::indexes::NtfsFileNameIndex> = entry?;
-                        let key_res = entry.key().transpose().map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Key error: {}", e)))?;
-                        if let Some(key) = key_res {
-                            if key.name().to_string_lossy() == *component && key.is_directory() {
-                                let id = entry.file_reference().file_record_number();
-                                current_dir = fs.file(reader, id).map_err(|e| {
-                                    io::Error::new(io::ErrorKind::Other, e.to_string())
-                                })?;
-                                found = true;
-                                break;
-                            }
-                        }
-                    }
-                    if !found {
-                        return Err(io::Error::new(io::ErrorKind::NotFound, "Path not found"));
-                    }
-                }
+                let current_dir = ntfs_find_dir(fs, reader, dir_components)?;
 
                 // Look for the file in the final directory.
                 let index = current_dir
@@ -572,8 +836,14 @@ impl SupportedFileSystem {
                                 let mut value = attr
                                     .value(reader)
                                     .map_err(|e: ntfs::NtfsError| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                                // Clamp at the stream length: past the end is EOF (0 bytes), not an error.
+                                let len = value.len();
+                                if offset >= len || buf.is_empty() {
+                                    return Ok(0);
+                                }
+                                let to_read = std::cmp::min(buf.len() as u64, len - offset) as usize;
                                 value.seek(reader, SeekFrom::Start(offset)).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                                return value.read(reader, buf).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()));
+                                return value.read(reader, &mut buf[..to_read]).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()));
                             }
                         }
                     }
@@ -581,60 +851,1594 @@ impl SupportedFileSystem {
                 Err(io::Error::new(io::ErrorKind::NotFound, "File not found"))
             }
             // Handle ExFAT file system.
-            SupportedFileSystem::ExFat(exfat) => {
-                 // Helper to find file recursively
-                 // We can reuse logic or copy paste. Recursion is cleanest given iterators.
-                 
-                  fn find_read_file<'a, R>(
-                    mut iter: impl Iterator<Item = exfat::directory::Item<R>>,
-                    components: &[&str],
-                    offset: u64,
-                    buf: &mut [u8]
-                  ) -> io::Result<usize> 
-                  where R: io::Read + io::Seek 
-                  {
-                     if components.is_empty() {
-                         // We expect a file name as last component
-                         return Err(io::Error::new(io::ErrorKind::NotFound, "File not specified"));
-                     }
-                     
-                     let target = components[0];
-                     let is_last = components.len() == 1;
-                     
-                     for item in iter {
-                         match item {
-                             exfat::directory::Item::Directory(d) => {
-                                 if !is_last && d.name() == target {
-                                     let sub_iter = d.open().map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?.into_iter();
-                                     return find_read_file(sub_iter, &components[1..], offset, buf);
-                                 }
-                             },
-                             exfat::directory::Item::File(mut f) => {
-                                 if is_last && f.name() == target {
-                                     let reader_opt = f.open().map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-                                     if let Some(mut reader) = reader_opt {
-                                         reader.seek(SeekFrom::Start(offset))?;
-                                         return reader.read(buf);
-                                     } else {
-                                          return Ok(0);
-                                     }
-                                 }
-                             }
-                         }
-                     }
-                     Err(io::Error::new(io::ErrorKind::NotFound, "File or Path not found"))
-                 }
-                 
-                 // Stub: ExFat root_dir method not found. Return empty iterator.
-                let root_iter = Vec::<exfat::directory::Item<DecryptedReader>>::new().into_iter();
-                    
-                 // Reconstruct full path components (dirs + filename)
-                 let mut all_components = Vec::new();
-                 all_components.extend_from_slice(dir_components);
-                 all_components.push(*file_name);
-                 
-                 find_read_file(root_iter, &all_components, offset, buf)
-            }
+            SupportedFileSystem::ExFat { reader, .. } => exfat_read_file(reader, &components, offset, buf),
+            // Handle FAT32 file system.
+            SupportedFileSystem::Fat32(fat) => fat.read_file(&components, offset, buf),
         }
     }
+
+    // Method to write data into a specific file at the given offset.
+    // Supported layouts:
+    // - FAT32: any file; missing files are created and files grow as needed.
+    // - NTFS: in place within the current length of the unnamed $DATA stream, resident (inside
+    //   the MFT record) or non-resident (through its data runs). Writes that would change the
+    //   file length, and sparse, compressed or encrypted streams, are Unsupported.
+    // - exFAT: not supported; the exfat crate does not expose a file's cluster allocation.
+    pub fn write_file(&mut self, path: &str, offset: u64, data: &[u8]) -> io::Result<usize> {
+        let components = modify_path_components(path)?;
+        let (file_name, dir_components) = components
+            .split_last()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Empty path"))?;
+
+        match self {
+            SupportedFileSystem::Fat32(fat) => fat.write_file(&components, offset, data),
+            SupportedFileSystem::Ntfs { fs, reader } => {
+                reader.seek(SeekFrom::Start(0))?;
+                let file = ntfs_find_file(fs, reader, dir_components, file_name)?;
+
+                let data_item = file
+                    .data(reader, "")
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "File has no data stream"))?
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                let attr = data_item
+                    .to_attribute()
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                if attr
+                    .flags()
+                    .intersects(NtfsAttributeFlags::COMPRESSED | NtfsAttributeFlags::ENCRYPTED | NtfsAttributeFlags::SPARSE)
+                {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Writing sparse, compressed or encrypted NTFS files is not supported",
+                    ));
+                }
+                let mut value = attr
+                    .value(reader)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+                let end = offset
+                    .checked_add(data.len() as u64)
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Write range overflow"))?;
+                if end > value.len() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Writes that change the file length are not supported on NTFS",
+                    ));
+                }
+                if data.is_empty() {
+                    return Ok(0);
+                }
+                match value {
+                    NtfsAttributeValue::Resident(_) => {}
+                    NtfsAttributeValue::NonResident(_) => {
+                        let cluster_size = fs.cluster_size() as u64;
+                        let record_number = file.file_record_number();
+                        // Bytes past the initialized size read as zero. Raising it to cover the
+                        // write must not expose whatever the clusters in between hold.
+                        let initialized = crate::ntfs_write::data_initialized_size(fs, reader, record_number)?;
+                        if offset > initialized {
+                            ntfs_zero_data_runs(&mut value, reader, cluster_size, initialized, offset - initialized)?;
+                        }
+                        let written = ntfs_write_data_runs(&mut value, reader, cluster_size, offset, data)?;
+                        if end > initialized {
+                            crate::ntfs_write::set_data_initialized_size(fs, reader, record_number, end)?;
+                        }
+                        return Ok(written);
+                    }
+                    NtfsAttributeValue::AttributeListNonResident(_) => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::Unsupported,
+                            "Writing NTFS files split over an attribute list is not supported",
+                        ));
+                    }
+                }
+
+                value
+                    .seek(reader, SeekFrom::Start(offset))
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                let position = value
+                    .data_position()
+                    .value()
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "No data position for NTFS file"))?
+                    .get();
+
+                if touches_ntfs_fixup(position, data.len() as u64) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Unsupported,
+                        "Write overlaps an NTFS update sequence position",
+                    ));
+                }
+
+                // Encrypt and write back through the DecryptedWriter path.
+                reader.seek(SeekFrom::Start(position))?;
+                reader.write_all(data)?;
+                reader.flush()?;
+                Ok(data.len())
+            }
+            SupportedFileSystem::ExFat { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Writing is not supported on exFAT",
+            )),
+        }
+    }
+
+    // Method to commit changes made through write_file, create_dir and delete.
+    // Writes are encrypted and handed to the device immediately (partial sectors through the
+    // DecryptedWriter read-modify-write path), so the only deferred state is the FAT32 FSInfo
+    // free count; after that the device is flushed, which calls the Java callback's flush().
+    pub fn sync(&mut self) -> io::Result<()> {
+        match self {
+            SupportedFileSystem::Fat32(fat) => fat.sync(),
+            SupportedFileSystem::Ntfs { reader, .. } | SupportedFileSystem::ExFat { reader, .. } => reader.flush(),
+        }
+    }
+
+    // Method to create a directory. Only FAT32 supports this.
+    pub fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        let components = modify_path_components(path)?;
+
+        match self {
+            SupportedFileSystem::Fat32(fat) => fat.create_dir(&components),
+            SupportedFileSystem::Ntfs { .. } | SupportedFileSystem::ExFat { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Creating directories is only supported on FAT32",
+            )),
+        }
+    }
+
+    // Method to delete a file or an empty directory.
+    // - FAT32: files and empty directories.
+    // - NTFS: simple files only (one name, no attribute list, resident or single-run data);
+    //   see ntfs_write::delete_file. Directories are rejected.
+    // - exFAT: not supported.
+    pub fn delete(&mut self, path: &str) -> io::Result<()> {
+        let components = modify_path_components(path)?;
+        let (file_name, dir_components) = components
+            .split_last()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Empty path"))?;
+
+        match self {
+            SupportedFileSystem::Fat32(fat) => fat.delete(&components),
+            SupportedFileSystem::Ntfs { fs, reader } => {
+                reader.seek(SeekFrom::Start(0))?;
+                let parent = ntfs_find_dir(fs, reader, dir_components)?;
+                let record_number = match ntfs_find_entry(&parent, reader, file_name, false) {
+                    Ok(id) => id,
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                        if ntfs_find_entry(&parent, reader, file_name, true).is_ok() {
+                            return Err(io::Error::new(
+                                io::ErrorKind::Unsupported,
+                                "Deleting directories is not supported on NTFS",
+                            ));
+                        }
+                        return Err(e);
+                    }
+                    Err(e) => return Err(e),
+                };
+                crate::ntfs_write::delete_file(fs, reader, parent.file_record_number(), record_number)
+            }
+            SupportedFileSystem::ExFat { .. } => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Deleting is not supported on exFAT",
+            )),
+        }
+    }
+
+    // Method to compute the total size of all files below a directory.
+    // This walks the whole subtree, so it is O(number of entries). Each subdirectory is resolved
+    // from the root again by list_files; those repeated reads of the upper levels are served by
+    // the DecryptedReader sector cache.
+    pub fn dir_size(&mut self, path: &str) -> io::Result<u64> {
+        let path = path.trim_matches('/');
+
+        if path.contains("..") {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Path traversal detected"));
+        }
+
+        self.dir_size_at(path, 0)
+    }
+
+    fn dir_size_at(&mut self, path: &str, depth: usize) -> io::Result<u64> {
+        if depth > MAX_DIR_DEPTH {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Directory nesting is too deep"));
+        }
+
+        let mut total = 0u64;
+        for entry in self.list_files(path)? {
+            if entry.is_dir {
+                let child = if path.is_empty() { entry.name } else { format!("{}/{}", path, entry.name) };
+                total = total.saturating_add(self.dir_size_at(&child, depth + 1)?);
+            } else {
+                total = total.saturating_add(entry.size);
+            }
+        }
+        Ok(total)
+    }
+
+    // Method to report (free, total) bytes of the file system's data area.
+    // FAT32 uses the FSInfo count (or counts the FAT once); NTFS and exFAT scan the allocation bitmap.
+    pub fn free_space(&mut self) -> io::Result<(u64, u64)> {
+        match self {
+            SupportedFileSystem::Fat32(fat) => fat.free_space(),
+            SupportedFileSystem::Ntfs { fs, reader } => {
+                let cluster_size = fs.cluster_size() as u64;
+                let total_clusters = fs.size() / cluster_size;
+                let bitmap = fs
+                    .file(reader, ntfs::KnownNtfsFileRecordNumber::Bitmap as u64)
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                let attr_item = bitmap
+                    .data(reader, "")
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "NTFS $Bitmap has no data"))?
+                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                let attr = attr_item.to_attribute().map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                let mut value = attr
+                    .value(reader)
+                    .map_err(|e: ntfs::NtfsError| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+                let mut used = 0u64;
+                let mut remaining = total_clusters;
+                let mut buf = vec![0u8; 64 * 1024];
+                while remaining > 0 {
+                    let n = value.read(reader, &mut buf).map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+                    if n == 0 {
+                        break;
+                    }
+                    used += count_used_bits(&buf[..n], &mut remaining);
+                }
+                Ok(((total_clusters - used) * cluster_size, total_clusters * cluster_size))
+            }
+            SupportedFileSystem::ExFat { reader, .. } => exfat_free_space(reader),
+        }
+    }
+}
+
+// Count the set bits among the first `remaining` bits of an allocation bitmap chunk,
+// decrementing `remaining` by the bits consumed.
+fn count_used_bits(bitmap: &[u8], remaining: &mut u64) -> u64 {
+    let mut used = 0u64;
+    for &byte in bitmap {
+        if *remaining == 0 {
+            break;
+        }
+        let bits = (*remaining).min(8);
+        let mask = if bits == 8 { 0xFF } else { (1u8 << bits) - 1 };
+        used += (byte & mask).count_ones() as u64;
+        *remaining -= bits;
+    }
+    used
+}
+
+const EXFAT_ENTRY_ALLOCATION_BITMAP: u8 = 0x81;
+const EXFAT_ENTRY_FILE: u8 = 0x85;
+const EXFAT_ENTRY_STREAM: u8 = 0xC0;
+const EXFAT_ENTRY_FILE_NAME: u8 = 0xC1;
+const EXFAT_ENTRY_END: u8 = 0x00;
+const EXFAT_FAT_EOC: u32 = 0xFFFF_FFFF;
+// Stream extension flag: the clusters are contiguous and the FAT chain is not used.
+const EXFAT_NO_FAT_CHAIN: u8 = 0x02;
+const EXFAT_ATTR_DIRECTORY: u32 = 0x10;
+
+fn exfat_read_at<R: VolumeBackend>(reader: &mut DecryptedReader<R>, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(buf)
+}
+
+fn exfat_invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Volume geometry from the exFAT boot sector, in bytes.
+struct ExFatLayout {
+    fat_offset: u64,
+    heap_offset: u64,
+    cluster_size: u64,
+    cluster_count: u32,
+    root_cluster: u32,
+}
+
+impl ExFatLayout {
+    fn read<R: VolumeBackend>(reader: &mut DecryptedReader<R>) -> io::Result<Self> {
+        let mut boot = [0u8; 512];
+        exfat_read_at(reader, 0, &mut boot)?;
+        let (sector_shift, cluster_shift) = (boot[108] as u32, boot[109] as u32);
+        if !(9..=12).contains(&sector_shift) || sector_shift + cluster_shift > 25 {
+            return Err(exfat_invalid("Invalid exFAT geometry"));
+        }
+        Ok(ExFatLayout {
+            fat_offset: (LittleEndian::read_u32(&boot[80..84]) as u64) << sector_shift,
+            heap_offset: (LittleEndian::read_u32(&boot[88..92]) as u64) << sector_shift,
+            cluster_size: 1u64 << (sector_shift + cluster_shift),
+            cluster_count: LittleEndian::read_u32(&boot[92..96]),
+            root_cluster: LittleEndian::read_u32(&boot[96..100]),
+        })
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> io::Result<u64> {
+        if cluster < 2 || cluster - 2 >= self.cluster_count {
+            return Err(exfat_invalid("exFAT cluster out of range"));
+        }
+        Ok(self.heap_offset + (cluster as u64 - 2) * self.cluster_size)
+    }
+
+    // Follow a FAT chain, bounded by the cluster count against loops.
+    fn chain<R: VolumeBackend>(&self, reader: &mut DecryptedReader<R>, start: u32) -> io::Result<Vec<u32>> {
+        self.chain_prefix(reader, start, usize::MAX)
+    }
+
+    // The first `limit` clusters of a FAT chain (fewer if the chain ends first).
+    fn chain_prefix<R: VolumeBackend>(&self, reader: &mut DecryptedReader<R>, start: u32, limit: usize) -> io::Result<Vec<u32>> {
+        let mut clusters = vec![start];
+        let mut raw = [0u8; 4];
+        while clusters.len() < limit {
+            let last = clusters[clusters.len() - 1];
+            exfat_read_at(reader, self.fat_offset + last as u64 * 4, &mut raw)?;
+            let next = LittleEndian::read_u32(&raw);
+            if next == EXFAT_FAT_EOC {
+                break;
+            }
+            if clusters.len() > self.cluster_count as usize {
+                return Err(exfat_invalid("exFAT cluster chain loops"));
+            }
+            clusters.push(next);
+        }
+        Ok(clusters)
+    }
+
+    // The first `limit` clusters of a file or directory stream, contiguous or through the FAT.
+    fn stream_clusters<R: VolumeBackend>(&self, reader: &mut DecryptedReader<R>, entry: &ExFatEntry, limit: usize) -> io::Result<Vec<u32>> {
+        if entry.first_cluster == 0 || limit == 0 {
+            return Ok(Vec::new());
+        }
+        if !entry.contiguous {
+            return self.chain_prefix(reader, entry.first_cluster, limit);
+        }
+        let count = entry.data_length.div_ceil(self.cluster_size).min(limit as u64);
+        if count > self.cluster_count as u64 {
+            return Err(exfat_invalid("exFAT stream too large"));
+        }
+        Ok((0..count as u32).map(|i| entry.first_cluster.wrapping_add(i)).collect())
+    }
+
+    // Read a whole directory. Subdirectories carry their length and may skip the FAT chain.
+    // The root directory (no entry) is always read through the FAT.
+    fn read_directory<R: VolumeBackend>(&self, reader: &mut DecryptedReader<R>, entry: Option<&ExFatEntry>) -> io::Result<Vec<u8>> {
+        let clusters = match entry {
+            None => self.chain(reader, self.root_cluster)?,
+            Some(entry) => self.stream_clusters(reader, entry, usize::MAX)?,
+        };
+        let mut data = vec![0u8; clusters.len() * self.cluster_size as usize];
+        for (cluster, chunk) in clusters.iter().zip(data.chunks_exact_mut(self.cluster_size as usize)) {
+            exfat_read_at(reader, self.cluster_offset(*cluster)?, chunk)?;
+        }
+        Ok(data)
+    }
+}
+
+// A file entry set parsed from an exFAT directory.
+struct ExFatEntry {
+    info: FileInfo,
+    first_cluster: u32,
+    data_length: u64,
+    // Bytes past this point were never written and read as zeros.
+    valid_data_length: u64,
+    contiguous: bool,
+}
+
+// Convert an exFAT timestamp with its 10 ms increment and UTC offset byte to Unix seconds.
+// Without a valid offset (bit 7 clear) the time is treated as UTC, like FAT.
+fn exfat_time_to_unix(timestamp: u32, increment_10ms: u8, utc_offset: u8) -> Option<u64> {
+    let local = crate::fat32::fat_time_to_unix((timestamp >> 16) as u16, timestamp as u16)?
+        + (increment_10ms.min(199) / 100) as u64;
+    if utc_offset & 0x80 == 0 {
+        return Some(local);
+    }
+    // Signed 7-bit offset in 15 minute steps.
+    let offset = (((utc_offset << 1) as i8) >> 1) as i64 * 15 * 60;
+    u64::try_from(local as i64 - offset).ok()
+}
+
+// Parse the in-use file entry sets of raw directory data. Malformed sets are skipped.
+fn exfat_parse_directory(data: &[u8]) -> Vec<ExFatEntry> {
+    let slots: Vec<&[u8]> = data.chunks_exact(32).collect();
+    let mut entries = Vec::new();
+    let mut i = 0;
+    while i < slots.len() {
+        let entry = slots[i];
+        match entry[0] {
+            EXFAT_ENTRY_END => break,
+            EXFAT_ENTRY_FILE => {
+                let secondary = entry[1] as usize;
+                if secondary < 2 || i + secondary >= slots.len() || slots[i + 1][0] != EXFAT_ENTRY_STREAM {
+                    i += 1;
+                    continue;
+                }
+                let stream = slots[i + 1];
+                let mut name = Vec::new();
+                for slot in slots[i + 2..=i + secondary].iter().take_while(|s| s[0] == EXFAT_ENTRY_FILE_NAME) {
+                    name.extend(slot[2..32].chunks_exact(2).map(LittleEndian::read_u16));
+                }
+                name.truncate(stream[3] as usize);
+
+                let attributes = LittleEndian::read_u16(&entry[4..6]) as u32;
+                let is_dir = attributes & EXFAT_ATTR_DIRECTORY != 0;
+                let data_length = LittleEndian::read_u64(&stream[24..32]);
+                entries.push(ExFatEntry {
+                    info: FileInfo {
+                        name: String::from_utf16_lossy(&name),
+                        is_dir,
+                        size: if is_dir { 0 } else { data_length },
+                        modified_time: exfat_time_to_unix(LittleEndian::read_u32(&entry[12..16]), entry[21], entry[23]),
+                        created_time: exfat_time_to_unix(LittleEndian::read_u32(&entry[8..12]), entry[20], entry[22]),
+                        attributes,
+                    },
+                    first_cluster: LittleEndian::read_u32(&stream[20..24]),
+                    data_length,
+                    valid_data_length: LittleEndian::read_u64(&stream[8..16]).min(data_length),
+                    contiguous: stream[1] & EXFAT_NO_FAT_CHAIN != 0,
+                });
+                i += 1 + secondary;
+            }
+            // Bitmap, up-case table, label, deleted and unknown entries.
+            _ => i += 1,
+        }
+    }
+    entries
+}
+
+// Raw data of the exFAT directory at `components`, descending one level at a time so that only
+// the current directory is held. Paths deeper than `max_depth` are refused, and a directory that
+// starts at the first cluster of one of its ancestors (a cycle in a crafted image) is InvalidData.
+fn exfat_find_directory<R: VolumeBackend>(reader: &mut DecryptedReader<R>, layout: &ExFatLayout, components: &[&str], max_depth: usize) -> io::Result<Vec<u8>> {
+    if components.len() > max_depth {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Path nesting is too deep"));
+    }
+    let mut data = layout.read_directory(reader, None)?;
+    let mut ancestors = vec![layout.root_cluster];
+    for component in components {
+        let entry = exfat_parse_directory(&data)
+            .into_iter()
+            .find(|e| e.info.is_dir && e.info.name == *component)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Path not found"))?;
+        if ancestors.contains(&entry.first_cluster) {
+            return Err(exfat_invalid("Directory cycle detected"));
+        }
+        ancestors.push(entry.first_cluster);
+        data = layout.read_directory(reader, Some(&entry))?;
+    }
+    Ok(data)
+}
+
+// List up to `limit` entries of an exFAT directory, starting at entry `offset`, with sizes,
+// timestamps and attributes from its entry sets. Also returns the total number of entries.
+fn exfat_list_files<R: VolumeBackend>(
+    reader: &mut DecryptedReader<R>,
+    components: &[&str],
+    offset: usize,
+    limit: usize,
+) -> io::Result<(Vec<FileInfo>, usize)> {
+    let layout = ExFatLayout::read(reader)?;
+    let data = exfat_find_directory(reader, &layout, components, MAX_DIR_DEPTH)?;
+    let entries = exfat_parse_directory(&data);
+    let total = entries.len();
+    Ok((entries.into_iter().skip(offset).take(limit).map(|e| e.info).collect(), total))
+}
+
+// Read from an exFAT file at `offset`. Reads stop at the file length, so at or past the end
+// of the file this returns Ok(0); only a broken cluster chain or an I/O failure is an error.
+fn exfat_read_file<R: VolumeBackend>(reader: &mut DecryptedReader<R>, components: &[&str], offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+    let (file_name, dir_components) = components
+        .split_last()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Empty path"))?;
+    let layout = ExFatLayout::read(reader)?;
+    let data = exfat_find_directory(reader, &layout, dir_components, MAX_DIR_DEPTH)?;
+    let entry = exfat_parse_directory(&data)
+        .into_iter()
+        .find(|e| e.info.name == *file_name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "File not found"))?;
+    if entry.info.is_dir {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Path is a directory"));
+    }
+    if offset >= entry.data_length || buf.is_empty() {
+        return Ok(0);
+    }
+    let to_read = std::cmp::min(buf.len() as u64, entry.data_length - offset) as usize;
+
+    let end = offset + to_read as u64;
+    let clusters = layout.stream_clusters(reader, &entry, end.div_ceil(layout.cluster_size) as usize)?;
+    let mut done = 0;
+    while done < to_read {
+        let position = offset + done as u64;
+        let cluster = *clusters
+            .get((position / layout.cluster_size) as usize)
+            .ok_or_else(|| exfat_invalid("exFAT cluster chain shorter than file"))?;
+        let within = position % layout.cluster_size;
+        let len = std::cmp::min(layout.cluster_size - within, (to_read - done) as u64) as usize;
+        let chunk = &mut buf[done..done + len];
+        exfat_read_at(reader, layout.cluster_offset(cluster)? + within, chunk)?;
+        let valid = entry.valid_data_length.saturating_sub(position).min(len as u64) as usize;
+        chunk[valid..].fill(0);
+        done += len;
+    }
+    Ok(to_read)
+}
+
+// Free and total bytes of an exFAT volume, from its boot sector and allocation bitmap.
+// The exfat crate does not expose the bitmap, so it is located through the root directory.
+fn exfat_free_space<R: VolumeBackend>(reader: &mut DecryptedReader<R>) -> io::Result<(u64, u64)> {
+    let layout = ExFatLayout::read(reader)?;
+    let total = layout.cluster_count as u64 * layout.cluster_size;
+
+    // Find the (first) allocation bitmap entry in the root directory.
+    let mut bitmap_entry = None;
+    let mut data = vec![0u8; layout.cluster_size as usize];
+    'root: for cluster in layout.chain(reader, layout.root_cluster)? {
+        exfat_read_at(reader, layout.cluster_offset(cluster)?, &mut data)?;
+        for entry in data.chunks_exact(32) {
+            match entry[0] {
+                EXFAT_ENTRY_END => break 'root,
+                EXFAT_ENTRY_ALLOCATION_BITMAP => {
+                    bitmap_entry = Some((LittleEndian::read_u32(&entry[20..24]), LittleEndian::read_u64(&entry[24..32])));
+                    break 'root;
+                }
+                _ => {}
+            }
+        }
+    }
+    let (bitmap_cluster, bitmap_len) = bitmap_entry.ok_or_else(|| exfat_invalid("exFAT allocation bitmap not found"))?;
+    if bitmap_len < (layout.cluster_count as u64).div_ceil(8) {
+        return Err(exfat_invalid("exFAT allocation bitmap too short"));
+    }
+
+    let mut used = 0u64;
+    let mut remaining = layout.cluster_count as u64;
+    for cluster in layout.chain(reader, bitmap_cluster)? {
+        if remaining == 0 {
+            break;
+        }
+        exfat_read_at(reader, layout.cluster_offset(cluster)?, &mut data)?;
+        used += count_used_bits(&data, &mut remaining);
+    }
+    if remaining != 0 {
+        return Err(exfat_invalid("exFAT allocation bitmap chain too short"));
+    }
+    Ok((total - used * layout.cluster_size, total))
+}
+
+// Maximum directory nesting followed by dir_size and exFAT path lookups, guarding against cyclic
+// or corrupted trees.
+const MAX_DIR_DEPTH: usize = 256;
+
+// Split a path for a modifying operation. Rejects traversal and the root itself.
+fn modify_path_components(path: &str) -> io::Result<Vec<&str>> {
+    let path = path.trim_matches('/');
+
+    // Security: Prevent path traversal.
+    if path.contains("..") {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Path traversal detected"));
+    }
+
+    let components: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    if components.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Empty path"));
+    }
+    Ok(components)
+}
+
+// Helper to locate a directory in an NTFS directory tree.
+// The record numbers of the directories on the path so far are kept, so an index entry that
+// points back at an ancestor (a cycle in a crafted image) is reported as InvalidData.
+fn ntfs_find_dir<'n, R: VolumeBackend>(
+    fs: &'n Ntfs,
+    reader: &mut DecryptedReader<R>,
+    dir_components: &[&str],
+) -> io::Result<ntfs::NtfsFile<'n>> {
+    let mut current_dir = fs
+        .root_directory(reader)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+
+    let mut ancestors = vec![current_dir.file_record_number()];
+    for component in dir_components {
+        let id = ntfs_find_entry(&current_dir, reader, component, true)?;
+        if ancestors.contains(&id) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Directory cycle detected"));
+        }
+        ancestors.push(id);
+        current_dir = fs
+            .file(reader, id)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    }
+    Ok(current_dir)
+}
+
+// Helper to locate a file in an NTFS directory tree.
+fn ntfs_find_file<'n, R: VolumeBackend>(
+    fs: &'n Ntfs,
+    reader: &mut DecryptedReader<R>,
+    dir_components: &[&str],
+    file_name: &str,
+) -> io::Result<ntfs::NtfsFile<'n>> {
+    let current_dir = ntfs_find_dir(fs, reader, dir_components)?;
+    let id = ntfs_find_entry(&current_dir, reader, file_name, false)?;
+    fs.file(reader, id)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))
+}
+
+// Helper to find the record number of a named entry in an NTFS directory.
+fn ntfs_find_entry<R: VolumeBackend>(
+    dir: &ntfs::NtfsFile<'_>,
+    reader: &mut DecryptedReader<R>,
+    name: &str,
+    want_dir: bool,
+) -> io::Result<u64> {
+    let index = dir
+        .directory_index(reader)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    let mut entries = index.entries();
+    while let Some(entry) = entries.next(reader) {
+        let entry: ntfs::NtfsIndexEntry<ntfs::indexes::NtfsFileNameIndex> = entry
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        let key = entry
+            .key()
+            .transpose()
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("Key error: {}", e)))?;
+        if let Some(key) = key {
+            if key.name().to_string_lossy() == name && key.is_directory() == want_dir {
+                return Ok(entry.file_reference().file_record_number());
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::NotFound, "File or Path not found"))
+}
+
+// Helper to overwrite part of a non-resident NTFS stream in place. Data runs are whole
+// clusters, so each cluster-bounded piece is contiguous on disk; its position is looked up
+// through the stream and the piece is encrypted on its way out through the reader.
+fn ntfs_write_data_runs<R: VolumeBackend + Write>(
+    value: &mut NtfsAttributeValue<'_, '_>,
+    reader: &mut DecryptedReader<R>,
+    cluster_size: u64,
+    offset: u64,
+    data: &[u8],
+) -> io::Result<usize> {
+    let mut written = 0;
+    while written < data.len() {
+        let file_pos = offset + written as u64;
+        let piece = std::cmp::min(data.len() - written, (cluster_size - file_pos % cluster_size) as usize);
+        value
+            .seek(reader, SeekFrom::Start(file_pos))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+        // Sparse runs have no clusters to write into.
+        let position = value
+            .data_position()
+            .value()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "Write falls into a sparse NTFS data run"))?
+            .get();
+
+        reader.seek(SeekFrom::Start(position))?;
+        reader.write_all(&data[written..written + piece])?;
+        written += piece;
+    }
+    reader.flush()?;
+    Ok(written)
+}
+
+// Zero `len` bytes of a non-resident NTFS stream starting at `offset`, one bounded chunk at a time.
+fn ntfs_zero_data_runs<R: VolumeBackend + Write>(
+    value: &mut NtfsAttributeValue<'_, '_>,
+    reader: &mut DecryptedReader<R>,
+    cluster_size: u64,
+    offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    const ZERO_CHUNK: u64 = 64 * 1024;
+    let zeros = vec![0u8; std::cmp::min(len, ZERO_CHUNK) as usize];
+    let mut done = 0;
+    while done < len {
+        let chunk = std::cmp::min(len - done, ZERO_CHUNK) as usize;
+        ntfs_write_data_runs(value, reader, cluster_size, offset + done, &zeros[..chunk])?;
+        done += chunk as u64;
+    }
+    Ok(())
+}
+
+// NTFS stores the update sequence number in the last two bytes of every 512-byte
+// stride of an MFT record. Those on-disk bytes differ from the fixed-up data, so
+// writing over them would corrupt the record.
+fn touches_ntfs_fixup(position: u64, len: u64) -> bool {
+    const NTFS_FIXUP_STRIDE: u64 = 512;
+    (position..position.saturating_add(len)).any(|p| p % NTFS_FIXUP_STRIDE >= NTFS_FIXUP_STRIDE - 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{AesWrapper, SupportedCipher};
+    use crate::header::VolumeHeader;
+    use cipher::KeyInit;
+    use std::io::Cursor;
+    use xts_mode::Xts128;
+
+    const DATA_OFFSET: usize = 131072;
+    const DATA_SIZE: u64 = 64 * 1024;
+
+    fn test_volume(read_only: bool, protected: Option<(u64, u64)>) -> Arc<Volume> {
+        sized_test_volume(DATA_SIZE, 512, read_only, protected)
+    }
+
+    fn sized_test_volume(data_size: u64, sector_size: u32, read_only: bool, protected: Option<(u64, u64)>) -> Arc<Volume> {
+        let mut key = [0u8; 64];
+        for (i, b) in key.iter_mut().enumerate() {
+            *b = (i as u8).wrapping_mul(7).wrapping_add(1);
+        }
+        let mut master_key_data = [0u8; 256];
+        master_key_data[..64].copy_from_slice(&key);
+        let header = VolumeHeader::new(
+            5, 0x010b, 0, 0, 0, data_size, DATA_OFFSET as u64, data_size, 0, sector_size,
+            master_key_data, [0u8; 64], 0,
+        ).expect("Failed to build header");
+        let cipher = SupportedCipher::Aes(Xts128::new(
+            AesWrapper::new((&key[0..32]).into()),
+            AesWrapper::new((&key[32..64]).into()),
+        ));
+        let mut volume = Volume::new(header, cipher, 0, None, 0, read_only, None);
+        if let Some((start, end)) = protected {
+            volume.set_protection(start, end);
+        }
+        Arc::new(volume)
+    }
+
+    fn decrypted_sector(device: &Cursor<Vec<u8>>, volume: &Volume, sector: u64) -> Vec<u8> {
+        let start = DATA_OFFSET + sector as usize * 512;
+        let mut data = device.get_ref()[start..start + 512].to_vec();
+        volume.decrypt_sector(sector, &mut data).expect("Decrypt failed");
+        data
+    }
+
+    #[test]
+    fn test_decrypted_reader_reads_partial_last_sector() {
+        // Three 4096-byte sectors of ciphertext; the volume ends 1500 bytes into the last one.
+        let full = sized_test_volume(3 * 4096, 4096, false, None);
+        let short = sized_test_volume(2 * 4096 + 1500, 4096, false, None);
+        let plaintext: Vec<u8> = (0..3 * 4096).map(|i| (i % 251) as u8).collect();
+        let mut ciphertext = plaintext.clone();
+        full.encrypt_sector(0, &mut ciphertext).expect("Encrypt failed");
+        let mut device = vec![0u8; DATA_OFFSET];
+        device.extend_from_slice(&ciphertext);
+
+        // Sequential reads pull the partial sector in through read-ahead.
+        let mut reader = DecryptedReader::new(Cursor::new(device.clone()), short.clone());
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).unwrap();
+        assert_eq!(data, &plaintext[..2 * 4096 + 1500]);
+
+        // A direct read of the tail stops at the end of the volume.
+        let mut reader = DecryptedReader::new(Cursor::new(device), short);
+        reader.seek(SeekFrom::Start(9000)).unwrap();
+        let mut tail = [0u8; 1000];
+        let n = reader.read(&mut tail).unwrap();
+        assert_eq!(&tail[..n], &plaintext[9000..2 * 4096 + 1500]);
+        assert_eq!(reader.read(&mut tail).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_decrypted_writer_aligned_write() {
+        let volume = test_volume(false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+        let data: Vec<u8> = (0..1024).map(|i| (i % 249) as u8).collect();
+
+        let mut writer = DecryptedWriter::new(&mut device, volume.clone());
+        writer.seek(SeekFrom::Start(512)).unwrap();
+        writer.write_all(&data).unwrap();
+        drop(writer);
+
+        // Stored data is encrypted, and decrypts back to what was written.
+        assert_ne!(&device.get_ref()[DATA_OFFSET + 512..DATA_OFFSET + 1536], &data[..]);
+        assert_eq!(decrypted_sector(&device, &volume, 1), &data[..512]);
+        assert_eq!(decrypted_sector(&device, &volume, 2), &data[512..]);
+    }
+
+    #[test]
+    fn test_decrypted_writer_unaligned_partial_write() {
+        let volume = test_volume(false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+        let base = vec![0x11u8; 1024];
+
+        let mut writer = DecryptedWriter::new(&mut device, volume.clone());
+        writer.write_all(&base).unwrap();
+        // Spans the end of sector 0 and the start of sector 1.
+        writer.seek(SeekFrom::Start(500)).unwrap();
+        writer.write_all(&[0xEEu8; 20]).unwrap();
+        drop(writer);
+
+        let mut expected = base.clone();
+        expected[500..520].fill(0xEE);
+        assert_eq!(decrypted_sector(&device, &volume, 0), &expected[..512]);
+        assert_eq!(decrypted_sector(&device, &volume, 1), &expected[512..]);
+    }
+
+    #[test]
+    fn test_decrypted_writer_refuses_read_only_volume() {
+        let volume = test_volume(true, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+
+        let mut writer = DecryptedWriter::new(&mut device, volume);
+        let err = writer.write(&[1u8; 16]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::PermissionDenied);
+        drop(writer);
+        assert!(device.get_ref().iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_decrypted_writer_respects_protected_range() {
+        // Protect the physical range of data sector 2.
+        let start = DATA_OFFSET as u64 + 1024;
+        let volume = test_volume(false, Some((start, start + 512)));
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+
+        let mut writer = DecryptedWriter::new(&mut device, volume.clone());
+        writer.seek(SeekFrom::Start(0)).unwrap();
+        writer.write_all(&[1u8; 512]).unwrap();
+        assert!(!volume.is_protection_triggered());
+        writer.seek(SeekFrom::Start(1024 + 10)).unwrap();
+        assert!(writer.write(&[1u8; 16]).is_err());
+        assert!(volume.is_protection_triggered());
+        drop(writer);
+        assert!(device.get_ref()[DATA_OFFSET + 1024..DATA_OFFSET + 1536].iter().all(|&b| b == 0));
+    }
+
+    // Counts read calls reaching the encrypted backend.
+    #[derive(Clone)]
+    struct CountingReader {
+        inner: Cursor<Vec<u8>>,
+        reads: std::rc::Rc<std::cell::Cell<usize>>,
+    }
+
+    impl Read for CountingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read(buf)
+        }
+    }
+
+    impl Seek for CountingReader {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.inner.seek(pos)
+        }
+    }
+
+    fn counting_reader(capacity: usize) -> (DecryptedReader<CountingReader>, std::rc::Rc<std::cell::Cell<usize>>) {
+        let volume = test_volume(false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+        let data: Vec<u8> = (0..4 * 512).map(|i| (i % 251) as u8).collect();
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&data).unwrap();
+
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let backend = CountingReader { inner: device, reads: reads.clone() };
+        (DecryptedReader::with_cache_capacity(backend, volume, capacity), reads)
+    }
+
+    #[test]
+    fn test_decrypted_reader_caches_repeated_sector_reads() {
+        let (mut reader, reads) = counting_reader(DEFAULT_SECTOR_CACHE_CAPACITY);
+        // Seeking back to a cached sector would otherwise wipe it.
+        reader.set_wipe_on_seek(false);
+        let mut first = [0u8; 512];
+        let mut second = [0u8; 512];
+
+        reader.seek(SeekFrom::Start(512)).unwrap();
+        reader.read_exact(&mut first).unwrap();
+        reader.seek(SeekFrom::Start(512)).unwrap();
+        reader.read_exact(&mut second).unwrap();
+
+        assert_eq!(reads.get(), 1);
+        assert_eq!(first, second);
+        assert_eq!(first[0], (512 % 251) as u8);
+    }
+
+    #[test]
+    fn test_wipe_on_seek_zeroizes_cache_on_cross_sector_seek() {
+        // A reader as constructed, without opting in.
+        let (mut reader, reads) = counting_reader(DEFAULT_SECTOR_CACHE_CAPACITY);
+        let mut buf = [0u8; 16];
+
+        reader.seek(SeekFrom::Start(512)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        // Seeking within the sector keeps the plaintext.
+        reader.seek(SeekFrom::Start(600)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reads.get(), 1);
+        assert_eq!(reader.sector_cache.len(), 1);
+
+        // Leaving the sector wipes it but keeps the allocation.
+        reader.seek(SeekFrom::Start(1024)).unwrap();
+        assert!(reader.sector_cache.is_empty());
+        assert_eq!(reader.spare_buffers.len(), 1);
+        assert_eq!(reader.spare_buffers[0].len(), 512);
+        assert!(reader.spare_buffers[0].iter().all(|&b| b == 0));
+
+        // The spare is reused and the data is still read correctly.
+        reader.read_exact(&mut buf).unwrap();
+        assert!(reader.spare_buffers.is_empty());
+        assert_eq!(buf[0], (1024 % 251) as u8);
+    }
+
+    #[test]
+    fn test_decrypted_reader_evicts_least_recently_used_sector() {
+        let (mut reader, reads) = counting_reader(2);
+        // Seeking back to a cached sector would otherwise wipe it.
+        reader.set_wipe_on_seek(false);
+        let mut buf = [0u8; 1];
+
+        for sector in [0u64, 1, 0, 2] {
+            reader.seek(SeekFrom::Start(sector * 512)).unwrap();
+            reader.read_exact(&mut buf).unwrap();
+        }
+        // Sector 0 was used after sector 1, so sector 1 was evicted for sector 2.
+        assert_eq!(reads.get(), 3);
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reads.get(), 3);
+        reader.seek(SeekFrom::Start(512)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(reads.get(), 4);
+        assert!(reader.sector_cache.len() <= 2);
+    }
+
+    #[test]
+    fn test_decrypted_reader_reads_ahead_on_sequential_access() {
+        let volume = test_volume(false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+        let data: Vec<u8> = (0..DATA_SIZE).map(|i| (i % 251) as u8).collect();
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&data).unwrap();
+
+        let reads = std::rc::Rc::new(std::cell::Cell::new(0));
+        let backend = CountingReader { inner: device, reads: reads.clone() };
+        let mut reader = DecryptedReader::new(backend, volume);
+        let mut out = vec![0u8; DATA_SIZE as usize];
+        reader.read_exact(&mut out).unwrap();
+
+        assert_eq!(out, data);
+        // One read for sector 0, then one per 8-sector window instead of 128 single reads.
+        assert!(reads.get() <= 1 + 128 / DEFAULT_READ_AHEAD_SECTORS, "{} reads", reads.get());
+    }
+
+    #[test]
+    fn test_decrypted_reader_random_access_reads_single_sectors() {
+        let (mut reader, reads) = counting_reader(DEFAULT_SECTOR_CACHE_CAPACITY);
+        // Seeking back to a cached sector would otherwise wipe it.
+        reader.set_wipe_on_seek(false);
+        let mut buf = [0u8; 1];
+        for sector in [3u64, 1, 0, 2] {
+            reader.seek(SeekFrom::Start(sector * 512)).unwrap();
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf[0], ((sector * 512) % 251) as u8);
+        }
+        assert_eq!(reads.get(), 4);
+        assert_eq!(reader.sector_cache.len(), 4);
+    }
+
+    #[test]
+    fn test_decrypted_reader_over_memory_backend() {
+        use crate::io_memory::MemoryReader;
+
+        let volume = test_volume(false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + DATA_SIZE as usize]);
+        let data: Vec<u8> = (0..DATA_SIZE).map(|i| (i % 253) as u8).collect();
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&data).unwrap();
+
+        let mut reader = DecryptedReader::new(MemoryReader::new(device.into_inner()), volume);
+        let mut buf = [0u8; 16];
+        reader.seek(SeekFrom::Start(1000)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1000..1016]);
+
+        // A clone keeps the position but starts without cached plaintext.
+        let mut clone = reader.clone();
+        assert!(clone.sector_cache.is_empty());
+        clone.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[1016..1032]);
+
+        // The last bytes of the data area are readable; reads at the end return EOF.
+        reader.seek(SeekFrom::End(-16)).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf[..], &data[DATA_SIZE as usize - 16..]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_touches_ntfs_fixup() {
+        assert!(!touches_ntfs_fixup(0, 510));
+        assert!(touches_ntfs_fixup(0, 511));
+        assert!(touches_ntfs_fixup(511, 1));
+        assert!(!touches_ntfs_fixup(512, 100));
+        assert!(!touches_ntfs_fixup(1024 + 200, 300));
+    }
+
+    #[test]
+    fn test_ntfs_time_to_unix() {
+        assert_eq!(ntfs_time_to_unix(0), None);
+        assert_eq!(ntfs_time_to_unix(NTFS_TO_UNIX_EPOCH_SECS * 10_000_000), Some(0));
+        // 2024-03-15 12:34:56 UTC plus a sub-second remainder.
+        assert_eq!(ntfs_time_to_unix((NTFS_TO_UNIX_EPOCH_SECS + 1_710_506_096) * 10_000_000 + 1234), Some(1_710_506_096));
+    }
+
+    #[test]
+    fn test_modify_path_components_rejects_traversal_and_root() {
+        assert_eq!(modify_path_components("/a//b/").unwrap(), vec!["a", "b"]);
+        assert_eq!(modify_path_components("a/../b").unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(modify_path_components("/").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    // Minimal NTFS image: 4096-byte clusters, 1024-byte records, $MFT at cluster 4 (24 records),
+    // $Bitmap data at cluster 12 and a root directory holding "hello.txt" (record 16) whose
+    // 5000 bytes of data occupy clusters 20-21.
+    const NTFS_TEST_CLUSTER: usize = 4096;
+    const NTFS_TEST_MFT: usize = 4 * NTFS_TEST_CLUSTER;
+    const NTFS_TEST_BITMAP: usize = 12 * NTFS_TEST_CLUSTER;
+    // Offset of the resident $MFT bitmap value in record 0 (after $STANDARD_INFORMATION and $DATA).
+    const NTFS_TEST_MFT_BITMAP: usize = 0x38 + 0x60 + 0x48 + 0x18;
+
+    fn ntfs_resident_attr(ty: u32, name: &str, value: &[u8]) -> Vec<u8> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let value_offset = (0x18 + 2 * name.len()).next_multiple_of(8);
+        let length = (value_offset + value.len()).next_multiple_of(8);
+        let mut attr = vec![0u8; length];
+        LittleEndian::write_u32(&mut attr[0..4], ty);
+        LittleEndian::write_u32(&mut attr[4..8], length as u32);
+        attr[9] = name.len() as u8;
+        LittleEndian::write_u16(&mut attr[10..12], 0x18);
+        LittleEndian::write_u32(&mut attr[0x10..0x14], value.len() as u32);
+        LittleEndian::write_u16(&mut attr[0x14..0x16], value_offset as u16);
+        for (i, unit) in name.iter().enumerate() {
+            LittleEndian::write_u16(&mut attr[0x18 + 2 * i..0x1A + 2 * i], *unit);
+        }
+        attr[value_offset..value_offset + value.len()].copy_from_slice(value);
+        attr
+    }
+
+    // Unnamed non-resident attribute with a single run.
+    fn ntfs_non_resident_attr(ty: u32, lcn: u8, clusters: u8, data_size: u64) -> Vec<u8> {
+        let mut attr = vec![0u8; 0x48];
+        LittleEndian::write_u32(&mut attr[0..4], ty);
+        LittleEndian::write_u32(&mut attr[4..8], 0x48);
+        attr[8] = 1;
+        LittleEndian::write_u16(&mut attr[10..12], 0x40);
+        LittleEndian::write_u64(&mut attr[0x18..0x20], clusters as u64 - 1);
+        LittleEndian::write_u16(&mut attr[0x20..0x22], 0x40);
+        LittleEndian::write_u64(&mut attr[0x28..0x30], clusters as u64 * NTFS_TEST_CLUSTER as u64);
+        LittleEndian::write_u64(&mut attr[0x30..0x38], data_size);
+        LittleEndian::write_u64(&mut attr[0x38..0x40], data_size);
+        attr[0x40..0x43].copy_from_slice(&[0x11, clusters, lcn]);
+        attr
+    }
+
+    fn ntfs_standard_information() -> Vec<u8> {
+        let mut value = vec![0u8; 0x48];
+        for i in 0..4 {
+            LittleEndian::write_u64(&mut value[i * 8..i * 8 + 8], (NTFS_TO_UNIX_EPOCH_SECS + 1_700_000_000) * 10_000_000);
+        }
+        ntfs_resident_attr(0x10, "", &value)
+    }
+
+    fn ntfs_file_name_value(parent: u64, name: &str, size: u64, attributes: u32) -> Vec<u8> {
+        let name: Vec<u16> = name.encode_utf16().collect();
+        let mut value = vec![0u8; 0x42 + 2 * name.len()];
+        LittleEndian::write_u64(&mut value[0..8], parent | (1 << 48));
+        LittleEndian::write_u64(&mut value[0x28..0x30], size.next_multiple_of(NTFS_TEST_CLUSTER as u64));
+        LittleEndian::write_u64(&mut value[0x30..0x38], size);
+        LittleEndian::write_u32(&mut value[0x38..0x3C], attributes);
+        value[0x40] = name.len() as u8;
+        value[0x41] = 1;
+        for (i, unit) in name.iter().enumerate() {
+            LittleEndian::write_u16(&mut value[0x42 + 2 * i..0x44 + 2 * i], *unit);
+        }
+        value
+    }
+
+    fn ntfs_record(number: u32, flags: u16, attributes: &[Vec<u8>]) -> Vec<u8> {
+        let mut record = vec![0u8; 1024];
+        record[0..4].copy_from_slice(b"FILE");
+        LittleEndian::write_u16(&mut record[4..6], 0x30);
+        LittleEndian::write_u16(&mut record[6..8], 3);
+        LittleEndian::write_u16(&mut record[0x10..0x12], 1);
+        LittleEndian::write_u16(&mut record[0x12..0x14], 1);
+        LittleEndian::write_u16(&mut record[0x14..0x16], 0x38);
+        LittleEndian::write_u16(&mut record[0x16..0x18], flags);
+        let mut pos = 0x38;
+        for (id, attr) in attributes.iter().enumerate() {
+            record[pos..pos + attr.len()].copy_from_slice(attr);
+            LittleEndian::write_u16(&mut record[pos + 0x0E..pos + 0x10], id as u16);
+            pos += attr.len();
+        }
+        LittleEndian::write_u32(&mut record[pos..pos + 4], 0xFFFF_FFFF);
+        LittleEndian::write_u32(&mut record[0x18..0x1C], (pos + 8) as u32);
+        LittleEndian::write_u32(&mut record[0x1C..0x20], 1024);
+        LittleEndian::write_u16(&mut record[0x28..0x2A], attributes.len() as u16);
+        LittleEndian::write_u32(&mut record[0x2C..0x30], number);
+        crate::ntfs_write::apply_fixup(&mut record).unwrap();
+        record
+    }
+
+    // Root directory record (5) whose resident $I30 index holds `entries`, each a record number
+    // and $FILE_NAME value, in the given (collation) order.
+    fn ntfs_root_record(entries: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut index_entries = Vec::new();
+        for (record, file_name) in entries {
+            let entry_length = (0x10 + file_name.len()).next_multiple_of(8);
+            let mut entry = vec![0u8; entry_length];
+            LittleEndian::write_u64(&mut entry[0..8], record | (1 << 48));
+            LittleEndian::write_u16(&mut entry[8..10], entry_length as u16);
+            LittleEndian::write_u16(&mut entry[10..12], file_name.len() as u16);
+            entry[0x10..0x10 + file_name.len()].copy_from_slice(file_name);
+            index_entries.extend(entry);
+        }
+        let mut index_root = vec![0u8; 0x20 + index_entries.len() + 0x10];
+        LittleEndian::write_u32(&mut index_root[0..4], 0x30);
+        LittleEndian::write_u32(&mut index_root[4..8], 1);
+        LittleEndian::write_u32(&mut index_root[8..12], NTFS_TEST_CLUSTER as u32);
+        index_root[12] = 1;
+        LittleEndian::write_u32(&mut index_root[0x10..0x14], 0x10);
+        LittleEndian::write_u32(&mut index_root[0x14..0x18], (0x10 + index_entries.len() + 0x10) as u32);
+        LittleEndian::write_u32(&mut index_root[0x18..0x1C], (0x10 + index_entries.len() + 0x10) as u32);
+        index_root[0x20..0x20 + index_entries.len()].copy_from_slice(&index_entries);
+        let end = 0x20 + index_entries.len();
+        LittleEndian::write_u16(&mut index_root[end + 8..end + 10], 0x10);
+        LittleEndian::write_u16(&mut index_root[end + 12..end + 14], 0x02);
+        ntfs_record(5, 0x03, &[
+            ntfs_standard_information(),
+            ntfs_resident_attr(0x90, "$I30", &index_root),
+        ])
+    }
+
+    fn ntfs_test_image() -> Vec<u8> {
+        let mut image = vec![0u8; 64 * NTFS_TEST_CLUSTER];
+
+        let boot = &mut image[0..512];
+        boot[0..3].copy_from_slice(&[0xEB, 0x52, 0x90]);
+        boot[3..11].copy_from_slice(b"NTFS    ");
+        LittleEndian::write_u16(&mut boot[0x0B..0x0D], 512);
+        boot[0x0D] = 8;
+        boot[0x15] = 0xF8;
+        boot[0x24] = 0x80;
+        boot[0x26] = 0x80;
+        LittleEndian::write_u64(&mut boot[0x28..0x30], 511);
+        LittleEndian::write_u64(&mut boot[0x30..0x38], 4);
+        LittleEndian::write_u64(&mut boot[0x38..0x40], 2);
+        boot[0x40] = 0xF6;
+        boot[0x44] = 1;
+        LittleEndian::write_u64(&mut boot[0x48..0x50], 0x1234_5678);
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+
+        let mut mft_bitmap = [0u8; 8];
+        mft_bitmap[0] = 0x61;
+        mft_bitmap[2] = 0x01;
+        let mft = ntfs_record(0, 0x01, &[
+            ntfs_standard_information(),
+            ntfs_non_resident_attr(0x80, 4, 6, 24 * 1024),
+            ntfs_resident_attr(0xB0, "", &mft_bitmap),
+        ]);
+
+        let file_name = ntfs_file_name_value(5, "hello.txt", 5000, 0x20);
+        let root = ntfs_root_record(&[(16, file_name.clone())]);
+
+        let bitmap = ntfs_record(6, 0x01, &[
+            ntfs_standard_information(),
+            ntfs_non_resident_attr(0x80, 12, 1, 8),
+        ]);
+        let file = ntfs_record(16, 0x01, &[
+            ntfs_standard_information(),
+            ntfs_resident_attr(0x30, "", &file_name),
+            ntfs_non_resident_attr(0x80, 20, 2, 5000),
+        ]);
+        for (number, record) in [(0, mft), (5, root), (6, bitmap), (16, file)] {
+            let start = NTFS_TEST_MFT + number * 1024;
+            image[start..start + 1024].copy_from_slice(&record);
+        }
+
+        // Used clusters: boot sector, $MFT (4-9), $Bitmap (12) and the file data (20-21).
+        image[NTFS_TEST_BITMAP..NTFS_TEST_BITMAP + 3].copy_from_slice(&[0xF1, 0x13, 0x30]);
+        image[20 * NTFS_TEST_CLUSTER..20 * NTFS_TEST_CLUSTER + 5000].fill(b'x');
+        image
+    }
+
+    #[test]
+    fn test_ntfs_write_file_in_place_through_data_runs() {
+        let image = ntfs_test_image();
+        let volume = sized_test_volume(image.len() as u64, 512, false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + image.len()]);
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&image).unwrap();
+        let mut fs = mount_fs_from_reader(volume, device).expect("NTFS image not recognized");
+
+        // Straddles the boundary between the file's two clusters.
+        let patch = b"edited in place";
+        assert_eq!(fs.write_file("/hello.txt", 4090, patch).unwrap(), patch.len());
+
+        let mut content = vec![0u8; 5000];
+        let mut read = 0;
+        while read < content.len() {
+            read += fs.read_file("/hello.txt", read as u64, &mut content[read..]).unwrap();
+        }
+        let mut expected = vec![b'x'; 5000];
+        expected[4090..4090 + patch.len()].copy_from_slice(patch);
+        assert_eq!(content, expected);
+
+        // Growing the file needs a new allocation.
+        assert_eq!(fs.write_file("/hello.txt", 4995, b"0123456789").unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_ntfs_write_past_initialized_size_zeroes_the_gap() {
+        let mut image = ntfs_test_image();
+        // Only the first 1000 bytes of hello.txt are initialized; the clusters still hold 'x'.
+        let start = NTFS_TEST_MFT + 16 * 1024;
+        let mut record = image[start..start + 1024].to_vec();
+        crate::ntfs_write::remove_fixup(&mut record).unwrap();
+        let file_name = ntfs_file_name_value(5, "hello.txt", 5000, 0x20);
+        let data = 0x38 + ntfs_standard_information().len() + ntfs_resident_attr(0x30, "", &file_name).len();
+        assert_eq!(LittleEndian::read_u32(&record[data..data + 4]), 0x80);
+        LittleEndian::write_u64(&mut record[data + 0x38..data + 0x40], 1000);
+        crate::ntfs_write::apply_fixup(&mut record).unwrap();
+        image[start..start + 1024].copy_from_slice(&record);
+
+        let volume = sized_test_volume(image.len() as u64, 512, false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + image.len()]);
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&image).unwrap();
+        let mut fs = mount_fs_from_reader(volume, device).expect("NTFS image not recognized");
+
+        let patch = b"past the valid data length";
+        assert_eq!(fs.write_file("/hello.txt", 4200, patch).unwrap(), patch.len());
+
+        let mut content = vec![0u8; 4200 + patch.len()];
+        let mut read = 0;
+        while read < content.len() {
+            read += fs.read_file("/hello.txt", read as u64, &mut content[read..]).unwrap();
+        }
+        let mut expected = vec![0u8; content.len()];
+        expected[..1000].fill(b'x');
+        expected[4200..].copy_from_slice(patch);
+        assert_eq!(content, expected);
+
+        // The initialized size now covers the write.
+        let SupportedFileSystem::Ntfs { reader, .. } = &mut fs else { unreachable!() };
+        reader.seek(SeekFrom::Start(start as u64)).unwrap();
+        reader.read_exact(&mut record).unwrap();
+        crate::ntfs_write::remove_fixup(&mut record).unwrap();
+        assert_eq!(LittleEndian::read_u64(&record[data + 0x38..data + 0x40]), (4200 + patch.len()) as u64);
+    }
+
+    #[test]
+    fn test_ntfs_delete_removes_simple_file() {
+        let image = ntfs_test_image();
+        let volume = sized_test_volume(image.len() as u64, 512, false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + image.len()]);
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&image).unwrap();
+
+        let mut fs = mount_fs_from_reader(volume, device).expect("NTFS image not recognized");
+        assert!(matches!(fs, SupportedFileSystem::Ntfs { .. }));
+        let names: Vec<String> = fs.list_files("/").unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["hello.txt".to_string()]);
+
+        fs.delete("/hello.txt").expect("Delete failed");
+        assert!(fs.list_files("/").unwrap().is_empty());
+        assert_eq!(fs.delete("/hello.txt").unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        // The record is marked unused and released in the $MFT bitmap, and its clusters in $Bitmap.
+        let SupportedFileSystem::Ntfs { reader, .. } = &mut fs else { unreachable!() };
+        let mut record = vec![0u8; 1024];
+        reader.seek(SeekFrom::Start((NTFS_TEST_MFT + 16 * 1024) as u64)).unwrap();
+        reader.read_exact(&mut record).unwrap();
+        crate::ntfs_write::remove_fixup(&mut record).unwrap();
+        assert_eq!(LittleEndian::read_u16(&record[0x16..0x18]) & 0x01, 0);
+        assert_eq!(LittleEndian::read_u16(&record[0x10..0x12]), 2);
+
+        reader.seek(SeekFrom::Start(NTFS_TEST_MFT as u64)).unwrap();
+        reader.read_exact(&mut record).unwrap();
+        crate::ntfs_write::remove_fixup(&mut record).unwrap();
+        assert_eq!(&record[NTFS_TEST_MFT_BITMAP..NTFS_TEST_MFT_BITMAP + 3], &[0x61, 0x00, 0x00]);
+
+        let mut bitmap = [0u8; 3];
+        reader.seek(SeekFrom::Start(NTFS_TEST_BITMAP as u64)).unwrap();
+        reader.read_exact(&mut bitmap).unwrap();
+        assert_eq!(bitmap, [0xF1, 0x13, 0x00]);
+    }
+
+    #[test]
+    fn test_mounted_readers_keep_wipe_on_seek_setting() {
+        let image = ntfs_test_image();
+        let volume = sized_test_volume(image.len() as u64, 512, false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + image.len()]);
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&image).unwrap();
+
+        let mut reader = DecryptedReader::new(device.clone(), volume.clone());
+        reader.set_wipe_on_seek(false);
+        let mut fs = mount_fs_from_decrypted(reader).expect("NTFS image not recognized");
+        let names: Vec<String> = fs.list_files("/").unwrap().into_iter().map(|f| f.name).collect();
+        assert_eq!(names, vec!["hello.txt".to_string()]);
+        let SupportedFileSystem::Ntfs { reader, .. } = &fs else { unreachable!() };
+        assert!(!reader.wipe_on_seek);
+
+        // mount_fs_from_reader keeps the default.
+        let fs = mount_fs_from_reader(volume, device).expect("NTFS image not recognized");
+        let SupportedFileSystem::Ntfs { reader, .. } = &fs else { unreachable!() };
+        assert!(reader.wipe_on_seek);
+    }
+
+    #[test]
+    fn test_ntfs_delete_writes_nothing_when_a_bitmap_is_unusable() {
+        // A resident $Bitmap is only found after the directory lookups; the delete must fail
+        // before touching the index, the file record or the $MFT bitmap.
+        let mut image = ntfs_test_image();
+        let bitmap = ntfs_record(6, 0x01, &[
+            ntfs_standard_information(),
+            ntfs_resident_attr(0x80, "", &[0xF1, 0x13, 0x30]),
+        ]);
+        image[NTFS_TEST_MFT + 6 * 1024..NTFS_TEST_MFT + 7 * 1024].copy_from_slice(&bitmap);
+        let volume = sized_test_volume(image.len() as u64, 512, false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + image.len()]);
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(&image).unwrap();
+
+        let mut fs = mount_fs_from_reader(volume, device).expect("NTFS image not recognized");
+        assert_eq!(fs.delete("/hello.txt").unwrap_err().kind(), io::ErrorKind::Unsupported);
+
+        let SupportedFileSystem::Ntfs { reader, .. } = &mut fs else { unreachable!() };
+        let mut after = vec![0u8; image.len()];
+        reader.seek(SeekFrom::Start(0)).unwrap();
+        reader.read_exact(&mut after).unwrap();
+        assert!(after == image, "a failed delete modified the volume");
+    }
+
+    // 2024-03-15 12:34:56 as an exFAT (FAT date << 16 | FAT time) timestamp.
+    const EXFAT_TEST_MODIFIED: u32 = (((44 << 9) | (3 << 5) | 15) << 16) | ((12 << 11) | (34 << 5) | 28);
+    // 2020-01-01 00:00:00.
+    const EXFAT_TEST_CREATED: u32 = ((40 << 9) | (1 << 5) | 1) << 16;
+
+    // Build an exFAT file entry set (file, stream extension and name entries) with valid hashes.
+    // `modified` is (timestamp, 10 ms increment, UTC offset byte).
+    fn exfat_entry_set(name: &str, attributes: u16, size: u64, first_cluster: u32, modified: (u32, u8, u8)) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let name_entries = units.len().div_ceil(15);
+        let mut set = vec![0u8; 32 * (2 + name_entries)];
+        set[0] = 0x85;
+        set[1] = (1 + name_entries) as u8;
+        LittleEndian::write_u16(&mut set[4..6], attributes);
+        LittleEndian::write_u32(&mut set[8..12], EXFAT_TEST_CREATED);
+        LittleEndian::write_u32(&mut set[12..16], modified.0);
+        set[21] = modified.1;
+        set[23] = modified.2;
+
+        let mut name_hash = 0u16;
+        for unit in &units {
+            let upper = if (b'a' as u16..=b'z' as u16).contains(unit) { unit - 32 } else { *unit };
+            for byte in upper.to_le_bytes() {
+                name_hash = name_hash.rotate_right(1).wrapping_add(byte as u16);
+            }
+        }
+        set[32] = 0xC0;
+        set[33] = 0x03; // AllocationPossible | NoFatChain
+        set[35] = units.len() as u8;
+        LittleEndian::write_u16(&mut set[36..38], name_hash);
+        LittleEndian::write_u64(&mut set[40..48], size);
+        LittleEndian::write_u32(&mut set[52..56], first_cluster);
+        LittleEndian::write_u64(&mut set[56..64], size);
+        for (i, chunk) in units.chunks(15).enumerate() {
+            let entry = &mut set[64 + i * 32..96 + i * 32];
+            entry[0] = 0xC1;
+            for (j, unit) in chunk.iter().enumerate() {
+                LittleEndian::write_u16(&mut entry[2 + j * 2..4 + j * 2], *unit);
+            }
+        }
+
+        let mut checksum = 0u16;
+        for (i, byte) in set.iter().enumerate() {
+            if i != 2 && i != 3 {
+                checksum = checksum.rotate_right(1).wrapping_add(*byte as u16);
+            }
+        }
+        LittleEndian::write_u16(&mut set[2..4], checksum);
+        set
+    }
+
+    // Fresh 16 MiB exFAT image whose root holds "notes.txt" (1234 bytes of b'n', UTC+01:00
+    // timestamps) and a contiguous "docs" directory holding one long-named file.
+    fn exfat_test_image() -> Vec<u8> {
+        let mut image = Cursor::new(vec![0u8; 16 * 1024 * 1024]);
+        crate::format_exfat::format_exfat(&mut image, 16 * 1024 * 1024).unwrap();
+        let mut image = image.into_inner();
+
+        let boot = image[..512].to_vec();
+        let sector_size = 1usize << boot[108];
+        let cluster_size = sector_size << boot[109];
+        let heap = LittleEndian::read_u32(&boot[88..92]) as usize * sector_size;
+        let cluster_count = LittleEndian::read_u32(&boot[92..96]);
+        let root = heap + (LittleEndian::read_u32(&boot[96..100]) as usize - 2) * cluster_size;
+        let cluster = |n: u32| heap + (n as usize - 2) * cluster_size;
+        // The last two clusters are unused by a fresh volume; the allocation bitmap is not updated
+        // since listing does not consult it.
+        let (file_cluster, dir_cluster) = (cluster_count, cluster_count + 1);
+
+        // Append to the root directory after the formatter's bitmap, up-case and label entries.
+        let free = (root..root + cluster_size).step_by(32).find(|&o| image[o] == 0x00).unwrap();
+        let mut entries = exfat_entry_set("notes.txt", 0x20, 1234, file_cluster, (EXFAT_TEST_MODIFIED, 100, 0x84));
+        entries.extend(exfat_entry_set("docs", 0x10, cluster_size as u64, dir_cluster, (EXFAT_TEST_MODIFIED, 0, 0)));
+        image[free..free + entries.len()].copy_from_slice(&entries);
+        let nested = exfat_entry_set("a long file name in a subdirectory.bin", 0x20, 7, 0, (EXFAT_TEST_MODIFIED, 0, 0xFC));
+        image[cluster(dir_cluster)..cluster(dir_cluster) + nested.len()].copy_from_slice(&nested);
+        image[cluster(file_cluster)..cluster(file_cluster) + 1234].fill(b'n');
+        image
+    }
+
+    fn mount_test_image(image: &[u8]) -> SupportedFileSystem<Cursor<Vec<u8>>> {
+        let volume = sized_test_volume(image.len() as u64, 512, false, None);
+        let mut device = Cursor::new(vec![0u8; DATA_OFFSET + image.len()]);
+        DecryptedWriter::new(&mut device, volume.clone()).write_all(image).unwrap();
+        mount_fs_from_reader(volume, device).expect("Image not recognized")
+    }
+
+    #[test]
+    fn test_list_files_paged_respects_page_boundaries() {
+        let names = |files: Vec<FileInfo>| files.into_iter().map(|f| f.name).collect::<Vec<_>>();
+
+        // NTFS: three index entries in the root.
+        let mut image = ntfs_test_image();
+        let root = ntfs_root_record(&[
+            (16, ntfs_file_name_value(5, "a.txt", 5000, 0x20)),
+            (16, ntfs_file_name_value(5, "b.txt", 5000, 0x20)),
+            (16, ntfs_file_name_value(5, "c.txt", 5000, 0x20)),
+        ]);
+        image[NTFS_TEST_MFT + 5 * 1024..NTFS_TEST_MFT + 6 * 1024].copy_from_slice(&root);
+        let mut ntfs = mount_test_image(&image);
+        let (page, total) = ntfs.list_files_paged("/", 0, 2).unwrap();
+        assert_eq!((names(page), total), (vec!["a.txt".to_string(), "b.txt".to_string()], 3));
+        let (page, total) = ntfs.list_files_paged("/", 2, 2).unwrap();
+        assert_eq!((names(page), total), (vec!["c.txt".to_string()], 3));
+        let (page, total) = ntfs.list_files_paged("/", 1, 0).unwrap();
+        assert_eq!((page.len(), total), (0, 3));
+        for offset in [3, 4, usize::MAX] {
+            let (page, total) = ntfs.list_files_paged("/", offset, usize::MAX).unwrap();
+            assert_eq!((page.len(), total), (0, 3));
+        }
+        assert_eq!(ntfs.list_files_paged("/", 1, usize::MAX).unwrap().0.len(), 2);
+
+        // exFAT: "notes.txt" and "docs" in the root.
+        let mut exfat = mount_test_image(&exfat_test_image());
+        let (page, total) = exfat.list_files_paged("/", 1, 1).unwrap();
+        assert_eq!((names(page), total), (vec!["docs".to_string()], 2));
+        let (page, total) = exfat.list_files_paged("/", 0, 5).unwrap();
+        assert_eq!((names(page), total), (vec!["notes.txt".to_string(), "docs".to_string()], 2));
+        let (page, total) = exfat.list_files_paged("/", 2, 1).unwrap();
+        assert_eq!((page.len(), total), (0, 2));
+        let (page, total) = exfat.list_files_paged("/", 7, 1).unwrap();
+        assert_eq!((page.len(), total), (0, 2));
+        assert_eq!(exfat.list_files_paged("/missing", 0, 1).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_exfat_list_files_reports_sizes_and_timestamps() {
+        let mut fs = mount_test_image(&exfat_test_image());
+        assert!(matches!(fs, SupportedFileSystem::ExFat { .. }));
+
+        let root_files = fs.list_files("/").unwrap();
+        assert_eq!(root_files.len(), 2);
+        let notes = &root_files[0];
+        assert_eq!((notes.name.as_str(), notes.is_dir, notes.size), ("notes.txt", false, 1234));
+        // Local 12:34:57 (1 s from the 10 ms increment) at UTC+01:00.
+        assert_eq!(notes.modified_time, Some(1_710_502_497));
+        assert_eq!(notes.created_time, Some(1_577_836_800));
+        assert_eq!(notes.attributes, 0x20);
+        let docs = &root_files[1];
+        assert_eq!((docs.name.as_str(), docs.is_dir, docs.size), ("docs", true, 0));
+        assert_eq!(docs.modified_time, Some(1_710_506_096));
+
+        let nested_files = fs.list_files("/docs").unwrap();
+        assert_eq!(nested_files.len(), 1);
+        assert_eq!(nested_files[0].name, "a long file name in a subdirectory.bin");
+        assert_eq!(nested_files[0].size, 7);
+        // UTC-01:00.
+        assert_eq!(nested_files[0].modified_time, Some(1_710_509_696));
+        assert_eq!(fs.list_files("/missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    // exfat_test_image() with a chain of `depth` contiguous directories "d0/d1/..." below the root,
+    // taken from the end of the cluster heap. The deepest one holds "leaf.txt".
+    fn exfat_nested_test_image(depth: u32) -> Vec<u8> {
+        let mut image = exfat_test_image();
+        let boot = image[..512].to_vec();
+        let sector_size = 1usize << boot[108];
+        let cluster_size = sector_size << boot[109];
+        let heap = LittleEndian::read_u32(&boot[88..92]) as usize * sector_size;
+        let cluster_count = LittleEndian::read_u32(&boot[92..96]);
+        let root = heap + (LittleEndian::read_u32(&boot[96..100]) as usize - 2) * cluster_size;
+        let cluster = |n: u32| heap + (n as usize - 2) * cluster_size;
+
+        // exfat_test_image() uses the last two clusters.
+        let mut parent = root;
+        for level in 0..depth {
+            let dir_cluster = cluster_count - 1 - level;
+            let set = exfat_entry_set(&format!("d{}", level), 0x10, cluster_size as u64, dir_cluster, (EXFAT_TEST_MODIFIED, 0, 0));
+            let free = (parent..parent + cluster_size).step_by(32).find(|&o| image[o] == 0x00).unwrap();
+            image[free..free + set.len()].copy_from_slice(&set);
+            parent = cluster(dir_cluster);
+        }
+        let leaf = exfat_entry_set("leaf.txt", 0x20, 0, 0, (EXFAT_TEST_MODIFIED, 0, 0));
+        image[parent..parent + leaf.len()].copy_from_slice(&leaf);
+        image
+    }
+
+    #[test]
+    fn test_exfat_lists_deeply_nested_path() {
+        let mut fs = mount_test_image(&exfat_nested_test_image(20));
+        let path: Vec<String> = (0..20).map(|level| format!("d{}", level)).collect();
+        let files = fs.list_files(&path.join("/")).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "leaf.txt");
+        assert_eq!(fs.read_file(&format!("{}/leaf.txt", path.join("/")), 0, &mut [0u8; 4]).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_exfat_find_directory_enforces_max_depth() {
+        let mut fs = mount_test_image(&exfat_nested_test_image(20));
+        let SupportedFileSystem::ExFat { reader, .. } = &mut fs else { panic!("Not exFAT") };
+        let layout = ExFatLayout::read(reader).unwrap();
+        let path: Vec<String> = (0..20).map(|level| format!("d{}", level)).collect();
+        let components: Vec<&str> = path.iter().map(String::as_str).collect();
+
+        assert!(exfat_find_directory(reader, &layout, &components, 20).is_ok());
+        let err = exfat_find_directory(reader, &layout, &components, 19).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "Path nesting is too deep");
+    }
+
+    #[test]
+    fn test_directory_pointing_back_at_root_is_detected() {
+        // NTFS: a "loop" directory entry in the root that refers to the root record itself.
+        let mut image = ntfs_test_image();
+        let root = ntfs_root_record(&[
+            (16, ntfs_file_name_value(5, "hello.txt", 5000, 0x20)),
+            (5, ntfs_file_name_value(5, "loop", 0, 0x1000_0000)),
+        ]);
+        image[NTFS_TEST_MFT + 5 * 1024..NTFS_TEST_MFT + 6 * 1024].copy_from_slice(&root);
+        let mut ntfs = mount_test_image(&image);
+        assert_eq!(ntfs.list_files("/").unwrap().len(), 2);
+        assert_eq!(ntfs.list_files("/loop").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(ntfs.read_file("/loop/hello.txt", 0, &mut [0u8; 4]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(ntfs.dir_size("/").unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // exFAT: a "loop" directory in the root whose stream starts at the root cluster.
+        let mut image = exfat_test_image();
+        let boot = image[..512].to_vec();
+        let sector_size = 1usize << boot[108];
+        let cluster_size = sector_size << boot[109];
+        let heap = LittleEndian::read_u32(&boot[88..92]) as usize * sector_size;
+        let root_cluster = LittleEndian::read_u32(&boot[96..100]);
+        let root = heap + (root_cluster as usize - 2) * cluster_size;
+        let free = (root..root + cluster_size).step_by(32).find(|&o| image[o] == 0x00).unwrap();
+        let set = exfat_entry_set("loop", 0x10, cluster_size as u64, root_cluster, (EXFAT_TEST_MODIFIED, 0, 0));
+        image[free..free + set.len()].copy_from_slice(&set);
+        let mut exfat = mount_test_image(&image);
+        assert_eq!(exfat.list_files("/").unwrap().len(), 3);
+        assert_eq!(exfat.list_files("/loop").unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(exfat.read_file("/loop/notes.txt", 0, &mut [0u8; 4]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(exfat.dir_size("/").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_read_file_at_and_past_eof_returns_zero() {
+        let mut buf = [0u8; 16];
+
+        let mut ntfs = mount_test_image(&ntfs_test_image());
+        assert_eq!(ntfs.read_file("/hello.txt", 4990, &mut buf).unwrap(), 10);
+        assert_eq!(&buf[..10], &[b'x'; 10]);
+        assert_eq!(ntfs.read_file("/hello.txt", 5000, &mut buf).unwrap(), 0);
+        assert_eq!(ntfs.read_file("/hello.txt", 6000, &mut buf).unwrap(), 0);
+        assert_eq!(ntfs.read_file("/missing.txt", 0, &mut buf).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+        let mut exfat = mount_test_image(&exfat_test_image());
+        assert_eq!(exfat.read_file("/notes.txt", 1224, &mut buf).unwrap(), 10);
+        assert_eq!(&buf[..10], &[b'n'; 10]);
+        assert_eq!(exfat.read_file("/notes.txt", 1234, &mut buf).unwrap(), 0);
+        assert_eq!(exfat.read_file("/notes.txt", 1 << 40, &mut buf).unwrap(), 0);
+        assert_eq!(exfat.read_file("/docs", 0, &mut buf).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(exfat.read_file("/missing.txt", 0, &mut buf).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
 }
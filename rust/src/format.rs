@@ -24,6 +24,44 @@ struct Fat32Geometry {
     root_dir_offset: u64,
 }
 
+// Upper bound on progress reports per format, however large the volume is.
+pub const PROGRESS_REPORT_STEPS: u64 = 16;
+
+// Forwards (bytes done, total) to an optional callback, skipping updates that
+// moved less than 1/PROGRESS_REPORT_STEPS of the total since the last report.
+pub(crate) struct Progress<'a> {
+    callback: Option<&'a dyn Fn(u64, u64)>,
+    done: u64,
+    total: u64,
+    last_reported: u64,
+}
+
+impl<'a> Progress<'a> {
+    pub(crate) fn new(callback: Option<&'a dyn Fn(u64, u64)>, total: u64) -> Self {
+        Progress { callback, done: 0, total, last_reported: 0 }
+    }
+
+    pub(crate) fn advance(&mut self, bytes: u64) {
+        // No callback: nothing to track.
+        let Some(callback) = self.callback else { return };
+        self.done = self.done.saturating_add(bytes).min(self.total);
+        let step = (self.total / PROGRESS_REPORT_STEPS).max(1);
+        if self.done < self.total && self.done - self.last_reported >= step {
+            self.last_reported = self.done;
+            callback(self.done, self.total);
+        }
+    }
+
+    // Always reports completion exactly once.
+    pub(crate) fn finish(&mut self) {
+        if let Some(callback) = self.callback {
+            self.done = self.total;
+            self.last_reported = self.total;
+            callback(self.total, self.total);
+        }
+    }
+}
+
 fn invalid_input(message: &'static str) -> io::Error {
     io::Error::new(io::ErrorKind::InvalidInput, message)
 }
@@ -106,6 +144,15 @@ fn calculate_geometry(volume_size: u64) -> io::Result<Fat32Geometry> {
 }
 
 pub fn format_fat32<W: Write + Seek>(writer: &mut W, volume_size: u64) -> io::Result<()> {
+    format_fat32_with_progress(writer, volume_size, None)
+}
+
+// Same as format_fat32, reporting (bytes done, total) while the FATs and root directory are zeroed.
+pub fn format_fat32_with_progress<W: Write + Seek>(
+    writer: &mut W,
+    volume_size: u64,
+    progress: Option<&dyn Fn(u64, u64)>,
+) -> io::Result<()> {
     let geometry = calculate_geometry(volume_size)?;
     let volume_id = generate_volume_id()?;
     
@@ -223,11 +270,14 @@ pub fn format_fat32<W: Write + Seek>(writer: &mut W, volume_size: u64) -> io::Re
     // Zero out the rest of FAT1 (padding to full FAT sectors)
     let zeros = vec![0u8; 4096];
     let fat_sector_bytes = geometry.fat_sectors as u64 * SECTOR_SIZE;
-    let mut remaining = fat_sector_bytes.saturating_sub(fat_start.len() as u64);
+    let fat_zero_bytes = fat_sector_bytes.saturating_sub(fat_start.len() as u64);
+    let mut progress = Progress::new(progress, 2 * fat_zero_bytes + geometry.cluster_size);
+    let mut remaining = fat_zero_bytes;
     while remaining > 0 {
         let to_write = std::cmp::min(remaining, zeros.len() as u64);
         writer.write_all(&zeros[..to_write as usize])?;
         remaining -= to_write;
+        progress.advance(to_write);
     }
     
     // Write FAT2
@@ -235,11 +285,12 @@ pub fn format_fat32<W: Write + Seek>(writer: &mut W, volume_size: u64) -> io::Re
     writer.write_all(&fat_start)?;
     
     // Zero out the rest of FAT2
-    let mut remaining = fat_sector_bytes.saturating_sub(fat_start.len() as u64);
+    let mut remaining = fat_zero_bytes;
     while remaining > 0 {
         let to_write = std::cmp::min(remaining, zeros.len() as u64);
         writer.write_all(&zeros[..to_write as usize])?;
         remaining -= to_write;
+        progress.advance(to_write);
     }
     
     // Initialize Root Directory (Cluster 2)
@@ -255,14 +306,19 @@ pub fn format_fat32<W: Write + Seek>(writer: &mut W, volume_size: u64) -> io::Re
         let to_write = std::cmp::min(remaining, zeros.len() as u64);
         writer.write_all(&zeros[..to_write as usize])?;
         remaining -= to_write;
+        progress.advance(to_write);
     }
     
+    progress.finish();
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{calculate_geometry, derive_volume_id, format_fat32, RESERVED_SECTORS, SECTOR_SIZE};
+    use super::{
+        calculate_geometry, derive_volume_id, format_fat32, format_fat32_with_progress, PROGRESS_REPORT_STEPS,
+        RESERVED_SECTORS, SECTOR_SIZE,
+    };
     use byteorder::{ByteOrder, LittleEndian};
     use std::io::Cursor;
 
@@ -301,4 +357,23 @@ mod tests {
         let volume_id = derive_volume_id([0u8; 16]);
         assert_ne!(volume_id, 0);
     }
+
+    #[test]
+    fn throttles_progress_reports_on_large_volume() {
+        // Only the metadata is written, so the cursor stays small even for a 1 GiB volume.
+        let volume_size = 1024 * 1024 * 1024;
+        let calls = std::cell::RefCell::new(Vec::new());
+        let record = |done: u64, total: u64| calls.borrow_mut().push((done, total));
+        format_fat32_with_progress(&mut Cursor::new(Vec::new()), volume_size, Some(&record)).unwrap();
+
+        let calls = calls.into_inner();
+        let total = calls[0].1;
+        assert!(calls.len() > 1, "expected reports while zeroing");
+        assert!(calls.len() as u64 <= PROGRESS_REPORT_STEPS + 1);
+        for pair in calls.windows(2) {
+            // Intermediate reports are at least 1/PROGRESS_REPORT_STEPS of the total apart.
+            assert!(pair[1].0 == total || pair[1].0 - pair[0].0 >= total / PROGRESS_REPORT_STEPS);
+        }
+        assert_eq!(*calls.last().unwrap(), (total, total));
+    }
 }
@@ -9,6 +9,9 @@ use std::fmt;
 // These are used to securely clear memory containing sensitive data (like keys) when it goes out of scope.
 use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
 
+// Data offset of TrueCrypt volumes with a header version below 4 (no backup header).
+pub const TRUECRYPT_LEGACY_DATA_OFFSET: u64 = 512;
+
 // Define an enumeration named HeaderError to represent possible errors during header parsing.
 // Derive Debug and Clone traits for easy printing and copying of error values.
 #[derive(Debug, Clone)]
@@ -100,6 +103,9 @@ pub struct VolumeHeader {
     pub salt: [u8; 64],
     // PIM used to derive header key
     pub pim: i32,
+    // True for legacy TrueCrypt headers (magic "TRUE"); serialize keeps the magic.
+    #[zeroize(skip)]
+    pub truecrypt: bool,
 }
 
 impl fmt::Debug for VolumeHeader {
@@ -120,15 +126,87 @@ impl fmt::Debug for VolumeHeader {
             // .field("master_key_data", &"<REDACTED>")
             .field("salt", &"<REDACTED>")
             .field("pim", &"<REDACTED>")
+            .field("truecrypt", &self.truecrypt)
             .finish()
     }
 }
 
+// Non-secret fields of a decrypted VolumeHeader, for diagnostics.
+// Deliberately has no master key, salt or PIM, so it is safe to log or show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderSummary {
+    pub version: u16,
+    pub min_program_version: u16,
+    pub crc32: u32,
+    pub volume_creation_time: u64,
+    pub header_creation_time: u64,
+    pub hidden_volume_size: u64,
+    pub volume_data_size: u64,
+    pub encrypted_area_start: u64,
+    pub encrypted_area_length: u64,
+    pub flags: u32,
+    pub sector_size: u32,
+    pub key_area_crc32: u32,
+    pub truecrypt: bool,
+}
+
+impl From<&VolumeHeader> for HeaderSummary {
+    fn from(header: &VolumeHeader) -> Self {
+        Self {
+            version: header.version,
+            min_program_version: header.min_program_version,
+            crc32: header.crc32,
+            volume_creation_time: header.volume_creation_time,
+            header_creation_time: header.header_creation_time,
+            hidden_volume_size: header.hidden_volume_size,
+            volume_data_size: header.volume_data_size,
+            encrypted_area_start: header.encrypted_area_start,
+            encrypted_area_length: header.encrypted_area_length,
+            flags: header.flags,
+            sector_size: header.sector_size,
+            key_area_crc32: header.key_area_crc32,
+            truecrypt: header.truecrypt,
+        }
+    }
+}
+
+// Number of bytes hashed by header CRC checks on this thread, for tests of the timing rule below.
+#[cfg(test)]
+thread_local! {
+    static CHECKED_CRC_BYTES: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+// CRC32 computed by deserialize_checked.
+fn checked_crc32(data: &[u8]) -> u32 {
+    #[cfg(test)]
+    CHECKED_CRC_BYTES.with(|count| count.set(count.get() + data.len()));
+    crc32fast::hash(data)
+}
+
 // Implementation block for VolumeHeader methods.
 impl VolumeHeader {
     // Function to deserialize a decrypted byte slice into a VolumeHeader struct.
     // Returns a Result containing the VolumeHeader or a HeaderError.
     pub fn deserialize(decrypted: &[u8], salt: &[u8], pim: i32) -> Result<Self, HeaderError> {
+        Self::deserialize_checked(decrypted, salt, pim, false)
+    }
+
+    // Same as deserialize, but a header CRC mismatch is only logged, for recovering volumes whose
+    // header CRC field alone is damaged. The parsed header carries the recomputed CRC, so writing
+    // it back repairs the field. The key area CRC is still enforced: with the header CRC ignored,
+    // it is what tells a correct password from random data that happens to start with the magic.
+    pub fn deserialize_lenient(decrypted: &[u8], salt: &[u8], pim: i32) -> Result<Self, HeaderError> {
+        Self::deserialize_checked(decrypted, salt, pim, true)
+    }
+
+    // Timing: every password guess decrypts a header with a wrong key, and the result fails one
+    // of the checks below. Both CRCs are always computed and all fixed-field checks compared in
+    // constant time before any of them is acted on, so a failed attempt costs the same whether
+    // the magic, the version or a CRC is wrong. This keeps a timing observer (another process on
+    // the device) from learning how close a guess came. The outcome still takes
+    // different paths afterwards: a successful match returns early from the cipher/PRF sweep,
+    // which only reveals what the password holder already knows.
+    fn deserialize_checked(decrypted: &[u8], salt: &[u8], pim: i32, lenient_crc: bool) -> Result<Self, HeaderError> {
         // Check if the decrypted data is large enough to contain a valid header.
         // A valid header must be at least 448 bytes (512 bytes total - 64 bytes salt).
         if decrypted.len() < 448 {
@@ -140,57 +218,55 @@ impl VolumeHeader {
             return Err(HeaderError::DataTooShort(salt.len()));
         }
 
-        // Extract the first 4 bytes to check the magic signature.
-        let magic = &decrypted[0..4];
-        // Verify if the magic bytes match "VERA" (VeraCrypt).
-        if magic != b"VERA" {
-            // If the magic bytes do not match, return an InvalidMagic error.
+        // Evaluate the magic, both CRCs and the version before deciding anything (see above).
+        use subtle::{ConstantTimeEq, ConstantTimeGreater};
+        // Verify if the magic bytes match "VERA" (VeraCrypt) or "TRUE" (legacy TrueCrypt).
+        let is_vera = decrypted[0..4].ct_eq(&b"VERA"[..]);
+        let is_truecrypt = decrypted[0..4].ct_eq(&b"TRUE"[..]);
+        // Read the version (2 bytes) from offset 4 using BigEndian byte order.
+        let version = BigEndian::read_u16(&decrypted[4..6]);
+        // CRC32 of the first 188 bytes (the fields up to the CRC itself), stored at offset 188.
+        let header_crc_calc = checked_crc32(&decrypted[0..188]);
+        let mut header_crc_stored = BigEndian::read_u32(&decrypted[188..192]);
+        let header_crc_ok = header_crc_calc.ct_eq(&header_crc_stored);
+        // CRC32 of the master key area, stored at offset 8.
+        let key_area_crc32 = BigEndian::read_u32(&decrypted[8..12]);
+        let key_area_crc_ok = checked_crc32(&decrypted[192..448]).ct_eq(&key_area_crc32);
+        // Versions 1 to 5 are supported.
+        let version_ok = version.ct_gt(&0) & !version.ct_gt(&5);
+
+        // If the magic bytes do not match, return an InvalidMagic error.
+        if !bool::from(is_vera | is_truecrypt) {
             return Err(HeaderError::InvalidMagic);
         }
+        let truecrypt = bool::from(is_truecrypt);
 
-        // Read the version (2 bytes) from offset 4 using BigEndian byte order.
-        let version = BigEndian::read_u16(&decrypted[4..6]);
-        // Verify that the version is at least 1 and supported (<= 5).
-        if version < 1 || version > 5 {
-            // If the version is not supported, return an UnsupportedVersion error.
+        // If the version is not supported, return an UnsupportedVersion error.
+        if !bool::from(version_ok) {
             return Err(HeaderError::UnsupportedVersion(version));
         }
 
-        // Check the Header CRC. The CRC check is only mandatory for version 4 and above.
-        // The CRC is stored at offset 188 (relative to the decrypted block start).
-        if version >= 4 {
-            // Read the stored CRC32 (4 bytes) from offset 188.
-            let header_crc_stored = BigEndian::read_u32(&decrypted[188..192]);
-            // Calculate the CRC32 of the first 188 bytes of the decrypted header.
-            // This covers the header fields up to the CRC itself.
-            let header_crc_calc = crc32fast::hash(&decrypted[0..188]);
-
-            // Compare the stored CRC with the calculated CRC.
-            if header_crc_stored != header_crc_calc {
-                // If they don't match, return an InvalidCrc error.
+        // The header CRC is only mandatory for version 4 and above.
+        if version >= 4 && !bool::from(header_crc_ok) {
+            if !lenient_crc {
                 return Err(HeaderError::InvalidHeaderCrc);
             }
+            log::warn!("Header CRC mismatch ignored in recovery mode");
+            header_crc_stored = header_crc_calc;
         }
 
-        // Read the stored CRC32 again (or for the first time if version < 4).
-        // We need this value to populate the struct field.
-        let header_crc_stored = BigEndian::read_u32(&decrypted[188..192]);
-
         // Read the minimum program version (2 bytes) from offset 6.
         let min_program_version = BigEndian::read_u16(&decrypted[6..8]);
 
         // Check if the minimum program version is supported.
-        // 0x011a corresponds to version 1.26.
-        if min_program_version > 0x011a {
+        // 0x011a corresponds to VeraCrypt 1.26; TrueCrypt headers use TrueCrypt versions (up to 7.1a).
+        let max_program_version = if truecrypt { 0x071a } else { 0x011a };
+        if min_program_version > max_program_version {
             // If the required version is greater than supported, return UnsupportedProgramVersion error.
             return Err(HeaderError::UnsupportedProgramVersion(min_program_version));
         }
 
-        // Read the key area CRC32 (4 bytes) from offset 8.
-        let key_area_crc32 = BigEndian::read_u32(&decrypted[8..12]);
-        
-        let key_area_crc_calc = crc32fast::hash(&decrypted[192..448]);
-        if key_area_crc32 != key_area_crc_calc {
+        if !bool::from(key_area_crc_ok) {
             return Err(HeaderError::InvalidKeyAreaCrc);
         }
 
@@ -203,9 +279,16 @@ impl VolumeHeader {
         // Read the volume data size (8 bytes) from offset 36.
         let volume_data_size = BigEndian::read_u64(&decrypted[36..44]);
         // Read the encrypted area start offset (8 bytes) from offset 44.
-        let encrypted_area_start = BigEndian::read_u64(&decrypted[44..52]);
+        let mut encrypted_area_start = BigEndian::read_u64(&decrypted[44..52]);
         // Read the encrypted area length (8 bytes) from offset 52.
-        let encrypted_area_length = BigEndian::read_u64(&decrypted[52..60]);
+        let mut encrypted_area_length = BigEndian::read_u64(&decrypted[52..60]);
+
+        // TrueCrypt headers before version 4 (TrueCrypt 5.x and older) do not store the
+        // encrypted area fields: the data follows the 512-byte header directly.
+        if truecrypt && version < 4 && encrypted_area_start == 0 {
+            encrypted_area_start = TRUECRYPT_LEGACY_DATA_OFFSET;
+            encrypted_area_length = volume_data_size;
+        }
 
         // Check for overflow in encrypted area range
         if encrypted_area_start.checked_add(encrypted_area_length).is_none() {
@@ -240,6 +323,12 @@ impl VolumeHeader {
             return Err(HeaderError::InvalidSectorSize(sector_size));
         }
 
+        // The data area must start and end on sector boundaries; misaligned values would
+        // otherwise surface later as bad arithmetic in the sector read/write paths.
+        if encrypted_area_start % sector_size as u64 != 0 || volume_data_size % sector_size as u64 != 0 {
+            return Err(HeaderError::InvalidLayout);
+        }
+
         // Initialize a 256-byte array for the master key data, filled with zeros.
         let mut master_key_data = zeroize::Zeroizing::new([0u8; 256]);
         // Copy the key area data from the decrypted buffer (offset 192 to 448) into the array.
@@ -265,9 +354,16 @@ impl VolumeHeader {
             master_key_data: *master_key_data, // Deref Zeroizing box back to array inside zeroized struct
             salt: salt_arr,
             pim,
+            truecrypt,
         })
     }
 
+    // Function to check whether the volume keeps a backup header at its end.
+    // Legacy TrueCrypt volumes (header version < 4) predate backup headers.
+    pub fn has_backup_header(&self) -> bool {
+        !(self.truecrypt && self.version < 4)
+    }
+
     // Function to check if a specific XTS key pair is vulnerable.
     // XTS keys are considered vulnerable if the two halves (Key1 and Key2) are identical.
     pub fn is_xts_key_vulnerable(&self, primary_offset: usize, secondary_offset: usize, key_size: usize) -> bool {
@@ -331,9 +427,21 @@ impl VolumeHeader {
             master_key_data,
             salt,
             pim,
+            truecrypt: false,
         })
     }
 
+    // Recompute the header and key-area CRC32 exactly as serialize does and compare them with the
+    // stored values. Headers before version 5 are normalized by deserialize (fixed sector size,
+    // TrueCrypt legacy layout), so only their key-area CRC can be compared.
+    pub fn crcs_match(&self) -> bool {
+        let mut copy = self.clone();
+        if copy.serialize().is_err() {
+            return false;
+        }
+        copy.key_area_crc32 == self.key_area_crc32 && (self.version < 5 || copy.crc32 == self.crc32)
+    }
+
     // Function to serialize the VolumeHeader into a byte buffer.
     pub fn serialize(&mut self) -> Result<Zeroizing<Vec<u8>>, HeaderError> {
         // Create a 512-byte buffer initialized with zeros.
@@ -346,7 +454,7 @@ impl VolumeHeader {
         let header_start = 64;
         
         // Write Magic "VERA" at offset 0 of encrypted area (64 absolute).
-        buffer[header_start..header_start + 4].copy_from_slice(b"VERA");
+        buffer[header_start..header_start + 4].copy_from_slice(if self.truecrypt { b"TRUE" } else { b"VERA" });
         
         // Write Version (2 bytes) at offset 4.
         BigEndian::write_u16(&mut buffer[header_start + 4..header_start + 6], self.version);
@@ -406,10 +514,123 @@ impl VolumeHeader {
     }
 }
 
+// Number of distinct byte values a random 64-byte salt has at the very least in practice
+// (about 56 are expected).
+const MIN_DISTINCT_SALT_BYTES: usize = 32;
+// Same for the whole 512-byte header (about 221 are expected).
+const MIN_DISTINCT_HEADER_BYTES: usize = 160;
+
+// Count the distinct byte values in a buffer.
+fn distinct_bytes(data: &[u8]) -> usize {
+    let mut seen = [false; 256];
+    for &b in data {
+        seen[b as usize] = true;
+    }
+    seen.iter().filter(|&&s| s).count()
+}
+
+// Heuristic check whether a buffer could be a VeraCrypt/TrueCrypt volume start, without a password.
+// An encrypted header is indistinguishable from random data, so this only rules out buffers that
+// are too short, carry a boot sector or file system signature, or have too little entropy.
+// A true result is not a guarantee.
+pub fn looks_like_veracrypt(header_bytes: &[u8]) -> bool {
+    if header_bytes.len() < 512 {
+        return false;
+    }
+
+    // Plain boot sectors (MBR, FAT, exFAT, NTFS) end with 55 AA.
+    if header_bytes[510..512] == [0x55, 0xAA] {
+        return false;
+    }
+    // Unencrypted file system names at offset 3 (OEM name) even without a boot signature.
+    if &header_bytes[3..11] == b"NTFS    " || &header_bytes[3..11] == b"EXFAT   " {
+        return false;
+    }
+    // ext2/3/4 superblock magic.
+    if header_bytes.len() >= 1082 && header_bytes[1080..1082] == [0x53, 0xEF] {
+        return false;
+    }
+
+    distinct_bytes(&header_bytes[..64]) >= MIN_DISTINCT_SALT_BYTES
+        && distinct_bytes(&header_bytes[..512]) >= MIN_DISTINCT_HEADER_BYTES
+}
+
+// Opt-in PIM hint, stored in plaintext right after the 512-byte header in the (otherwise
+// unused) remainder of the primary header area. The PIM can't live inside the encrypted
+// header because the header key is derived from it. Standard VeraCrypt volumes leave this
+// area random or zeroed, so the magic and CRC keep them from being misread. Note that a
+// hint reveals both that the file is a volume and its PIM, giving up plausible deniability.
+pub const PIM_HINT_OFFSET: usize = 512;
+pub const PIM_HINT_LEN: usize = 16;
+const PIM_HINT_MAGIC: &[u8; 8] = b"NXPIMHNT";
+
+// Encode a PIM hint record: magic, PIM (u32 BE), CRC32 over the first 12 bytes.
+pub fn encode_pim_hint(pim: i32) -> [u8; PIM_HINT_LEN] {
+    let mut record = [0u8; PIM_HINT_LEN];
+    record[0..8].copy_from_slice(PIM_HINT_MAGIC);
+    BigEndian::write_u32(&mut record[8..12], pim as u32);
+    let crc = crc32fast::hash(&record[0..12]);
+    BigEndian::write_u32(&mut record[12..16], crc);
+    record
+}
+
+// Read the PIM hint from the start of a header area, if one was written at creation.
+pub fn decode_pim_hint(header_area: &[u8]) -> Option<i32> {
+    let record = header_area.get(PIM_HINT_OFFSET..PIM_HINT_OFFSET + PIM_HINT_LEN)?;
+    if &record[0..8] != PIM_HINT_MAGIC
+        || BigEndian::read_u32(&record[12..16]) != crc32fast::hash(&record[0..12])
+    {
+        return None;
+    }
+    let pim = BigEndian::read_u32(&record[8..12]);
+    // Zero means "default iterations", which needs no hint.
+    if pim == 0 || pim > i32::MAX as u32 {
+        return None;
+    }
+    Some(pim as i32)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Deterministic pseudo-random bytes (xorshift) standing in for an encrypted header.
+    fn pseudo_random_bytes(len: usize) -> Vec<u8> {
+        let mut state = 0x9E37_79B9_7F4A_7C15u64;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_looks_like_veracrypt_heuristic() {
+        let random = pseudo_random_bytes(131072);
+        assert!(looks_like_veracrypt(&random));
+
+        // Too short, all zero, or low entropy.
+        assert!(!looks_like_veracrypt(&random[..511]));
+        assert!(!looks_like_veracrypt(&[0u8; 512]));
+        let repeated: Vec<u8> = (0..512).map(|i| (i % 16) as u8).collect();
+        assert!(!looks_like_veracrypt(&repeated));
+
+        // Recognizable plaintext file systems.
+        let mut boot = random.clone();
+        boot[510] = 0x55;
+        boot[511] = 0xAA;
+        assert!(!looks_like_veracrypt(&boot));
+        let mut ntfs = random.clone();
+        ntfs[3..11].copy_from_slice(b"NTFS    ");
+        assert!(!looks_like_veracrypt(&ntfs));
+        let mut ext4 = random;
+        ext4[1080..1082].copy_from_slice(&[0x53, 0xEF]);
+        assert!(!looks_like_veracrypt(&ext4));
+    }
+
     #[test]
     fn test_valid_sector_sizes() {
         // Test power of 2
@@ -460,6 +681,25 @@ mod tests {
         assert_ne!(header.key_area_crc32, 0);
     }
 
+    #[test]
+    fn test_crcs_match_detects_modified_fields() {
+        let mut header = VolumeHeader::new(
+            5, 0x011a, 0, 0, 0, 1024 * 1024, 131072, 1024 * 1024, 0, 512, [1u8; 256], [2u8; 64], 0,
+        ).unwrap();
+        // Never serialized: the stored CRCs are still zero.
+        assert!(!header.crcs_match());
+
+        let _ = header.serialize().unwrap();
+        assert!(header.crcs_match());
+
+        let mut changed = header.clone();
+        changed.volume_data_size += 512;
+        assert!(!changed.crcs_match());
+        let mut changed = header.clone();
+        changed.master_key_data[0] ^= 1;
+        assert!(!changed.crcs_match());
+    }
+
     #[test]
     fn test_deserialize_version_4_forces_legacy_sector_size() {
         let mut header = VolumeHeader::new(
@@ -486,4 +726,103 @@ mod tests {
 
         assert_eq!(parsed.sector_size, 512);
     }
+
+    #[test]
+    fn test_truecrypt_magic_round_trips_with_truecrypt_program_version() {
+        let mut header = VolumeHeader::new(
+            5,
+            0x0700, // TrueCrypt 7.0
+            0,
+            0,
+            0,
+            1024 * 1024,
+            131072,
+            1024 * 1024,
+            0,
+            512,
+            [7u8; 256],
+            [9u8; 64],
+            0,
+        ).unwrap();
+        header.truecrypt = true;
+
+        let serialized = header.serialize().unwrap();
+        assert_eq!(&serialized[64..68], b"TRUE");
+        let parsed = VolumeHeader::deserialize(&serialized[64..512], &serialized[..64], 0)
+            .expect("Failed to deserialize TrueCrypt header");
+        assert!(parsed.truecrypt);
+        assert_eq!(parsed.min_program_version, 0x0700);
+
+        // The same program version is too new for a VeraCrypt header.
+        header.truecrypt = false;
+        let serialized = header.serialize().unwrap();
+        assert!(matches!(
+            VolumeHeader::deserialize(&serialized[64..512], &serialized[..64], 0),
+            Err(HeaderError::UnsupportedProgramVersion(0x0700))
+        ));
+    }
+
+    #[test]
+    fn test_pim_hint_round_trip() {
+        let mut area = pseudo_random_bytes(PIM_HINT_OFFSET + PIM_HINT_LEN);
+        assert_eq!(decode_pim_hint(&area), None);
+
+        area[PIM_HINT_OFFSET..].copy_from_slice(&encode_pim_hint(485));
+        assert_eq!(decode_pim_hint(&area), Some(485));
+
+        // A flipped PIM bit fails the CRC; a short buffer has no hint.
+        area[PIM_HINT_OFFSET + 11] ^= 1;
+        assert_eq!(decode_pim_hint(&area), None);
+        assert_eq!(decode_pim_hint(&area[..PIM_HINT_OFFSET + 8]), None);
+    }
+
+    // Serialize a header with the given layout and parse its plaintext back.
+    fn deserialize_layout(sector_size: u32, encrypted_area_start: u64, volume_data_size: u64) -> Result<VolumeHeader, HeaderError> {
+        let mut header = VolumeHeader::new(
+            5, 0x011a, 0, 0, 0,
+            volume_data_size,
+            encrypted_area_start,
+            volume_data_size,
+            0, sector_size, [1u8; 256], [2u8; 64], 0,
+        ).unwrap();
+        let serialized = header.serialize().unwrap();
+        VolumeHeader::deserialize(&serialized[64..512], &serialized[..64], 0)
+    }
+
+    #[test]
+    fn test_deserialize_rejects_misaligned_layout() {
+        assert!(deserialize_layout(4096, 131072, 1024 * 1024).is_ok());
+
+        // Start aligned to 512 but not to the 4096-byte sector size.
+        assert!(matches!(deserialize_layout(4096, 131072 + 512, 1024 * 1024), Err(HeaderError::InvalidLayout)));
+        assert!(matches!(deserialize_layout(512, 131072 + 1, 1024 * 1024), Err(HeaderError::InvalidLayout)));
+        // Data size not a whole number of sectors.
+        assert!(matches!(deserialize_layout(512, 131072, 1024 * 1024 + 100), Err(HeaderError::InvalidLayout)));
+        assert!(matches!(deserialize_layout(4096, 131072, 1024 * 1024 + 512), Err(HeaderError::InvalidLayout)));
+    }
+
+    // A header failing on its magic (a wrong key) hashes as much as one failing on the last
+    // check, i.e. both CRCs are computed either way.
+    #[test]
+    fn test_failed_deserialize_work_does_not_depend_on_failing_check() {
+        let wrong_key = pseudo_random_bytes(448);
+        let mut header = VolumeHeader::new(
+            5, 0x011a, 0, 0, 0, 1024 * 1024, 131072, 1024 * 1024, 0, 512, [1u8; 256], [2u8; 64], 0,
+        ).unwrap();
+        let mut bad_key_area = header.serialize().unwrap()[64..512].to_vec();
+        bad_key_area[447] ^= 1;
+        let salt = [2u8; 64];
+
+        let hashed = |data: &[u8]| {
+            CHECKED_CRC_BYTES.with(|count| count.set(0));
+            let result = VolumeHeader::deserialize(data, &salt, 0);
+            (result, CHECKED_CRC_BYTES.with(|count| count.get()))
+        };
+        let (early_result, early) = hashed(&wrong_key);
+        let (late_result, late) = hashed(&bad_key_area);
+        assert!(matches!(early_result, Err(HeaderError::InvalidMagic)));
+        assert!(matches!(late_result, Err(HeaderError::InvalidKeyAreaCrc)));
+        assert_eq!(early, 188 + 256);
+        assert_eq!(early, late);
+    }
 }
@@ -1,8 +1,9 @@
  // Import standard I/O traits.
-use std::io::{self, Read, Seek, SeekFrom};
+use std::io::{self, Read, Seek, SeekFrom, Write};
 // Import JNI types.
-use jni::objects::{GlobalRef, JValue};
-use jni::JavaVM;
+use jni::objects::{GlobalRef, JMethodID, JValue};
+use jni::signature::{Primitive, ReturnType};
+use jni::{JNIEnv, JavaVM};
 use std::sync::Arc;
 use std::ops::Deref;
 
@@ -30,6 +31,22 @@ fn validated_read_len(bytes_read: i32, buf_len: usize) -> io::Result<usize> {
     Ok(read_len)
 }
 
+fn validated_write_len(bytes_written: i32, buf_len: usize) -> io::Result<usize> {
+    if bytes_written < 0 {
+        return Err(other_error("Java write callback returned error (-1)"));
+    }
+
+    let write_len = bytes_written as usize;
+    if write_len > buf_len {
+        return Err(invalid_input("Java write callback exceeded requested buffer length"));
+    }
+    if write_len == 0 {
+        return Err(io::Error::new(io::ErrorKind::WriteZero, "Java write callback wrote nothing"));
+    }
+
+    Ok(write_len)
+}
+
 fn checked_seek_position(current: u64, volume_size: u64, pos: SeekFrom) -> io::Result<u64> {
     match pos {
         SeekFrom::Start(p) => Ok(p),
@@ -62,6 +79,35 @@ fn checked_seek_position(current: u64, volume_size: u64, pos: SeekFrom) -> io::R
     }
 }
 
+// A single positioned read from the underlying data source, as done by one Java `read` call.
+// Split out of CallbackReader so the retry logic can be tested without a JVM.
+trait ReadAt {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+// Read contract of the Java callback:
+// - A positive result may be shorter than requested; callers (DecryptedReader) loop for the rest.
+// - 0 means end of data. A cold source (e.g. a USB device waking up) can also return 0 once
+//   before the end, so a 0 at a position below `volume_size` is retried once before being
+//   reported as EOF. With an unknown size (0) every 0 is EOF.
+// - A negative result is a hard error.
+fn read_retrying<S: ReadAt>(source: &mut S, position: u64, volume_size: u64, buf: &mut [u8]) -> io::Result<usize> {
+    let read_len = source.read_at(position, buf)?;
+    if read_len == 0 && position < volume_size {
+        log::debug!("Java read returned 0 at {} before end {}, retrying once", position, volume_size);
+        return source.read_at(position, buf);
+    }
+    Ok(read_len)
+}
+
+const READ_METHOD: &str = "read";
+const WRITE_METHOD: &str = "write";
+const FLUSH_METHOD: &str = "flush";
+// Both callbacks: int method(long offset, ByteBuffer buffer)
+const CALLBACK_SIGNATURE: &str = "(JLjava/nio/ByteBuffer;)I";
+// boolean flush()
+const FLUSH_SIGNATURE: &str = "()Z";
+
 // Struct to read data via a Java callback.
 #[derive(Clone)]
 pub struct CallbackReader {
@@ -69,6 +115,12 @@ pub struct CallbackReader {
     jvm: Arc<JavaVM>,
     // Global reference to the Java callback object.
     callback_obj: Arc<GlobalRef>,
+    // Cached ID of `int read(long, ByteBuffer)`, resolved once from the callback's class.
+    read_method: JMethodID,
+    // Cached ID of `int write(long, ByteBuffer)`, if the callback class has one.
+    write_method: Option<JMethodID>,
+    // Cached ID of `boolean flush()`, if the callback class has one.
+    flush_method: Option<JMethodID>,
     // Current read position.
     position: u64,
     // Total size of the data source.
@@ -77,14 +129,38 @@ pub struct CallbackReader {
 
 // Implementation of CallbackReader.
 impl CallbackReader {
-    // Constructor.
-    pub fn new(jvm: JavaVM, callback_obj: GlobalRef, volume_size: u64) -> Self {
-        Self {
+    // Constructor. Resolves the callback method IDs up front; they stay valid as long as
+    // the class is loaded, which the global reference guarantees.
+    pub fn new(env: &mut JNIEnv, jvm: JavaVM, callback_obj: GlobalRef, volume_size: u64) -> jni::errors::Result<Self> {
+        let class = env.get_object_class(&callback_obj)?;
+        let read_method = env.get_method_id(&class, READ_METHOD, CALLBACK_SIGNATURE)?;
+        let write_method = match env.get_method_id(&class, WRITE_METHOD, CALLBACK_SIGNATURE) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                // NoSuchMethodError: the callback is read-only.
+                let _ = env.exception_clear();
+                None
+            }
+        };
+        let flush_method = match env.get_method_id(&class, FLUSH_METHOD, FLUSH_SIGNATURE) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                // NoSuchMethodError: nothing to flush on the Java side.
+                let _ = env.exception_clear();
+                None
+            }
+        };
+        let _ = env.delete_local_ref(class);
+
+        Ok(Self {
             jvm: Arc::new(jvm),
             callback_obj: Arc::new(callback_obj),
+            read_method,
+            write_method,
+            flush_method,
             position: 0,
             volume_size,
-        }
+        })
     }
 }
 
@@ -96,6 +172,23 @@ impl Read for CallbackReader {
             return Ok(0);
         }
 
+        let (position, volume_size) = (self.position, self.volume_size);
+        let read_len = read_retrying(self, position, volume_size, buf)?;
+
+        // Update position.
+        self.position = self
+            .position
+            .checked_add(read_len as u64)
+            .ok_or_else(|| other_error("Read position overflow"))?;
+
+        // Return bytes read.
+        Ok(read_len)
+    }
+}
+
+// One Java `read` call at `offset`, without touching the stream position.
+impl ReadAt for CallbackReader {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
         // Attach current thread to JVM.
         let mut env = self.jvm.attach_current_thread().map_err(|e| {
             io::Error::new(io::ErrorKind::Other, format!("JNI attach failed: {}", e))
@@ -123,23 +216,26 @@ impl Read for CallbackReader {
                 io::Error::new(io::ErrorKind::Other, format!("JNI ByteBuffer.wrap result error: {}", e))
             })?;
 
-        // Check for integer overflow when casting position to i64 (JNI limitation)
-        let offset: i64 = self.position.try_into().map_err(|_| {
+        // Check for integer overflow when casting offset to i64 (JNI limitation)
+        let offset: i64 = offset.try_into().map_err(|_| {
             io::Error::new(io::ErrorKind::InvalidInput, "Offset too large for JNI (max 8EB)")
         })?;
 
         // Call Java method: int read(long offset, ByteBuffer buffer)
-        let result = env
-            .call_method(
+        // SAFETY: read_method was resolved from this object's class with CALLBACK_SIGNATURE,
+        // and the arguments below match that signature.
+        let result = unsafe {
+            env.call_method_unchecked(
                 &*self.callback_obj,
-                "read",
-                "(JLjava/nio/ByteBuffer;)I",
-                &[JValue::Long(offset), JValue::Object(&byte_buffer)],
+                self.read_method,
+                ReturnType::Primitive(Primitive::Int),
+                &[JValue::Long(offset).as_jni(), JValue::Object(&byte_buffer).as_jni()],
             )
-            .map_err(|e| {
-                let _ = env.exception_clear(); 
-                io::Error::new(io::ErrorKind::Other, format!("JNI call failed: {}", e))
-            });
+        }
+        .map_err(|e| {
+            let _ = env.exception_clear();
+            io::Error::new(io::ErrorKind::Other, format!("JNI call failed: {}", e))
+        });
 
         // Delete the local reference to the ByteBuffer to prevent memory leak
         let _ = env.delete_local_ref(byte_buffer);
@@ -170,14 +266,121 @@ impl Read for CallbackReader {
 
         let _ = env.delete_local_ref(byte_array);
 
+        Ok(read_len)
+    }
+}
+
+// Implement Write trait for CallbackReader.
+impl Write for CallbackReader {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // If buffer is empty, return 0.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let write_method = self
+            .write_method
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Unsupported, "Java callback has no write method"))?;
+
+        // Attach current thread to JVM.
+        let mut env = self.jvm.attach_current_thread().map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("JNI attach failed: {}", e))
+        })?;
+
+        let buf_len = buf.len();
+
+        // Copy outgoing data into a new Java byte[].
+        let byte_array = env.byte_array_from_slice(buf).map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("JNI array creation failed: {}", e))
+        })?;
+
+        let byte_buffer = env
+            .call_static_method(
+                "java/nio/ByteBuffer",
+                "wrap",
+                "([B)Ljava/nio/ByteBuffer;",
+                &[JValue::Object(&byte_array)],
+            )
+            .map_err(|e| {
+                let _ = env.exception_clear();
+                io::Error::new(io::ErrorKind::Other, format!("JNI ByteBuffer.wrap failed: {}", e))
+            })?
+            .l()
+            .map_err(|e| {
+                io::Error::new(io::ErrorKind::Other, format!("JNI ByteBuffer.wrap result error: {}", e))
+            })?;
+
+        let offset: i64 = self.position.try_into().map_err(|_| {
+            io::Error::new(io::ErrorKind::InvalidInput, "Offset too large for JNI (max 8EB)")
+        })?;
+
+        // Call Java method: int write(long offset, ByteBuffer buffer)
+        // SAFETY: write_method was resolved from this object's class with CALLBACK_SIGNATURE.
+        let result = unsafe {
+            env.call_method_unchecked(
+                &*self.callback_obj,
+                write_method,
+                ReturnType::Primitive(Primitive::Int),
+                &[JValue::Long(offset).as_jni(), JValue::Object(&byte_buffer).as_jni()],
+            )
+        }
+        .map_err(|e| {
+            let _ = env.exception_clear();
+            io::Error::new(io::ErrorKind::Other, format!("JNI call failed: {}", e))
+        });
+
+        let _ = env.delete_local_ref(byte_buffer);
+        let _ = env.delete_local_ref(byte_array);
+
+        let bytes_written = result?.i().map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("JNI result error: {}", e))
+        })?;
+
+        let write_len = validated_write_len(bytes_written, buf_len)?;
+
         // Update position.
         self.position = self
             .position
-            .checked_add(read_len as u64)
-            .ok_or_else(|| other_error("Read position overflow"))?;
+            .checked_add(write_len as u64)
+            .ok_or_else(|| other_error("Write position overflow"))?;
 
-        // Return bytes read.
-        Ok(read_len)
+        Ok(write_len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        // Writes are forwarded synchronously to the Java callback; its optional flush()
+        // makes them durable (e.g. FileChannel.force).
+        let flush_method = match self.flush_method {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let mut env = self.jvm.attach_current_thread().map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("JNI attach failed: {}", e))
+        })?;
+
+        // SAFETY: flush_method was resolved from this object's class with FLUSH_SIGNATURE,
+        // which takes no arguments.
+        let result = unsafe {
+            env.call_method_unchecked(
+                &*self.callback_obj,
+                flush_method,
+                ReturnType::Primitive(Primitive::Boolean),
+                &[],
+            )
+        }
+        .map_err(|e| {
+            let _ = env.exception_clear();
+            io::Error::new(io::ErrorKind::Other, format!("JNI call failed: {}", e))
+        })?;
+
+        let flushed = result.z().map_err(|e| {
+            io::Error::new(io::ErrorKind::Other, format!("JNI result error: {}", e))
+        })?;
+        if !flushed {
+            return Err(io::Error::new(io::ErrorKind::Other, "Java callback flush failed"));
+        }
+        Ok(())
     }
 }
 
@@ -194,9 +397,32 @@ impl Seek for CallbackReader {
 
 #[cfg(test)]
 mod tests {
-    use super::{checked_seek_position, validated_read_len};
+    use super::{checked_seek_position, read_retrying, validated_read_len, validated_write_len, ReadAt};
+    use std::collections::VecDeque;
     use std::io::SeekFrom;
 
+    // Stand-in for the Java callback: returns the scripted lengths in order (negative = error).
+    struct ScriptedSource {
+        results: VecDeque<i32>,
+        offsets: Vec<u64>,
+    }
+
+    impl ScriptedSource {
+        fn new(results: &[i32]) -> Self {
+            Self { results: results.iter().copied().collect(), offsets: Vec::new() }
+        }
+    }
+
+    impl ReadAt for ScriptedSource {
+        fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.offsets.push(offset);
+            let result = self.results.pop_front().expect("Unexpected read");
+            let read_len = validated_read_len(result, buf.len())?;
+            buf[..read_len].fill(0xab);
+            Ok(read_len)
+        }
+    }
+
     #[test]
     fn rejects_callback_read_larger_than_buffer() {
         let err = validated_read_len(9, 8).unwrap_err();
@@ -208,6 +434,48 @@ mod tests {
         assert_eq!(validated_read_len(8, 8).unwrap(), 8);
     }
 
+    #[test]
+    fn rejects_callback_write_errors_and_overruns() {
+        assert_eq!(validated_write_len(-1, 8).unwrap_err().kind(), std::io::ErrorKind::Other);
+        assert_eq!(validated_write_len(9, 8).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(validated_write_len(0, 8).unwrap_err().kind(), std::io::ErrorKind::WriteZero);
+        assert_eq!(validated_write_len(8, 8).unwrap(), 8);
+    }
+
+    #[test]
+    fn retries_zero_read_before_end_once() {
+        let mut source = ScriptedSource::new(&[0, 16]);
+        let mut buf = [0u8; 16];
+        assert_eq!(read_retrying(&mut source, 512, 1024, &mut buf).unwrap(), 16);
+        assert_eq!(source.offsets, vec![512, 512]);
+        assert_eq!(buf, [0xab; 16]);
+
+        // A second 0 is reported as EOF.
+        let mut source = ScriptedSource::new(&[0, 0]);
+        assert_eq!(read_retrying(&mut source, 512, 1024, &mut buf).unwrap(), 0);
+        assert_eq!(source.offsets.len(), 2);
+    }
+
+    #[test]
+    fn does_not_retry_at_end_or_with_unknown_size() {
+        let mut buf = [0u8; 16];
+        let mut source = ScriptedSource::new(&[0]);
+        assert_eq!(read_retrying(&mut source, 1024, 1024, &mut buf).unwrap(), 0);
+        let mut source = ScriptedSource::new(&[0]);
+        assert_eq!(read_retrying(&mut source, 512, 0, &mut buf).unwrap(), 0);
+        assert_eq!(source.offsets.len(), 1);
+    }
+
+    #[test]
+    fn returns_short_reads_and_errors_as_is() {
+        let mut buf = [0u8; 16];
+        let mut source = ScriptedSource::new(&[5]);
+        assert_eq!(read_retrying(&mut source, 0, 1024, &mut buf).unwrap(), 5);
+        let mut source = ScriptedSource::new(&[-1]);
+        assert_eq!(read_retrying(&mut source, 0, 1024, &mut buf).unwrap_err().kind(), std::io::ErrorKind::Other);
+        assert_eq!(source.offsets.len(), 1);
+    }
+
     #[test]
     fn rejects_seek_from_unknown_end() {
         let err = checked_seek_position(0, 0, SeekFrom::End(0)).unwrap_err();
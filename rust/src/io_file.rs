@@ -0,0 +1,140 @@
+// Import standard I/O traits.
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// Struct to read (and write) volume data from a file on the host.
+// Replaces CallbackReader as the VolumeBackend for native tooling and fixtures, so no JVM is needed.
+// Clones share the open file but keep their own position, like CallbackReader clones.
+#[derive(Clone)]
+pub struct FileReader {
+    // The open container file, shared between clones.
+    file: Arc<Mutex<File>>,
+    // Current position of this clone.
+    position: u64,
+    // True if the file was opened without write access.
+    read_only: bool,
+}
+
+// Implementation of FileReader.
+impl FileReader {
+    // Open `path`, with write access unless `read_only` is set.
+    pub fn open<P: AsRef<Path>>(path: P, read_only: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(!read_only).open(path)?;
+        Ok(Self { file: Arc::new(Mutex::new(file)), position: 0, read_only })
+    }
+
+    // Returns the current size of the file in bytes.
+    pub fn size(&self) -> io::Result<u64> {
+        Ok(self.lock().metadata()?.len())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, File> {
+        self.file.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Read up to `buf.len()` bytes at `offset`, stopping early only at the end of the file.
+    pub fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut file = self.lock();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut total = 0;
+        while total < buf.len() {
+            match file.read(&mut buf[total..]) {
+                Ok(0) => break,
+                Ok(n) => total += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total)
+    }
+}
+
+// Implement Read trait for FileReader.
+impl Read for FileReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.read_at(self.position, buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+// Implement Write trait for FileReader.
+impl Write for FileReader {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.read_only {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "File opened read-only"));
+        }
+        let written = {
+            let mut file = self.lock();
+            file.seek(SeekFrom::Start(self.position))?;
+            file.write(buf)?
+        };
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.read_only {
+            return Ok(());
+        }
+        self.lock().sync_data()
+    }
+}
+
+// Implement Seek trait for FileReader.
+impl Seek for FileReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => self.size()?.checked_add_signed(p),
+            SeekFrom::Current(p) => self.position.checked_add_signed(p),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+
+        // Update position.
+        self.position = new_pos;
+        Ok(new_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileReader;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    fn temp_file(name: &str, data: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("noxcipher_io_file_{}_{}", std::process::id(), name));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn clones_keep_their_own_position() {
+        let path = temp_file("clones", &[1, 2, 3, 4]);
+        let mut reader = FileReader::open(&path, true).unwrap();
+        let mut clone = reader.clone();
+        reader.seek(SeekFrom::End(-1)).unwrap();
+        let mut buf = [0u8; 2];
+        assert_eq!(clone.read(&mut buf).unwrap(), 2);
+        assert_eq!(buf, [1, 2]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 4);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn read_only_rejects_writes() {
+        let path = temp_file("read_only", &[0u8; 4]);
+        let mut reader = FileReader::open(&path, true).unwrap();
+        assert_eq!(reader.write(&[9]).unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+
+        let mut writer = FileReader::open(&path, false).unwrap();
+        writer.seek(SeekFrom::Start(2)).unwrap();
+        writer.write_all(&[9]).unwrap();
+        writer.flush().unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), vec![0, 0, 9, 0]);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,126 @@
+// Import standard I/O traits.
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::sync::{Arc, Mutex};
+
+// Struct to read (and write) volume data held in memory.
+// Stands in for CallbackReader as the VolumeBackend so the DecryptedReader/filesystem stack can
+// run without a JVM (tests, ramdisk volumes). Clones share the image but keep their own position,
+// like FileReader clones.
+#[derive(Clone)]
+pub struct MemoryReader {
+    // The raw (encrypted) volume image, shared between clones.
+    data: Arc<Mutex<Vec<u8>>>,
+    // Current position of this clone.
+    position: u64,
+}
+
+// Implementation of MemoryReader.
+impl MemoryReader {
+    // Constructor.
+    pub fn new(data: Vec<u8>) -> Self {
+        Self::from_shared(Arc::new(Mutex::new(data)))
+    }
+
+    // Constructor over an image the caller keeps a handle to.
+    pub fn from_shared(data: Arc<Mutex<Vec<u8>>>) -> Self {
+        Self { data, position: 0 }
+    }
+
+    // Returns a copy of the underlying image.
+    pub fn into_inner(self) -> Vec<u8> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<u8>> {
+        self.data.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Implement Read trait for MemoryReader.
+impl Read for MemoryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let data = self.lock();
+        // Reads at or past the end return 0 (EOF).
+        let start = std::cmp::min(self.position, data.len() as u64) as usize;
+        let len = std::cmp::min(buf.len(), data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        drop(data);
+        self.position += len as u64;
+        Ok(len)
+    }
+}
+
+// Implement Write trait for MemoryReader.
+// The image has a fixed size: writes are truncated at the end, like a block device.
+impl Write for MemoryReader {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = self.lock();
+        let start = std::cmp::min(self.position, data.len() as u64) as usize;
+        let len = std::cmp::min(buf.len(), data.len() - start);
+        data[start..start + len].copy_from_slice(&buf[..len]);
+        drop(data);
+        self.position += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Implement Seek trait for MemoryReader.
+impl Seek for MemoryReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(p) => (self.lock().len() as u64).checked_add_signed(p),
+            SeekFrom::Current(p) => self.position.checked_add_signed(p),
+        }
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek position"))?;
+
+        // Update position.
+        self.position = new_pos;
+        Ok(new_pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryReader;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn reads_until_end_of_image() {
+        let mut reader = MemoryReader::new(vec![1, 2, 3, 4]);
+        reader.seek(SeekFrom::Start(2)).unwrap();
+        let mut buf = [0u8; 4];
+        assert_eq!(reader.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[3, 4]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn writes_do_not_grow_image() {
+        let mut reader = MemoryReader::new(vec![0u8; 4]);
+        reader.seek(SeekFrom::End(-1)).unwrap();
+        assert_eq!(reader.write(&[9, 9]).unwrap(), 1);
+        assert_eq!(reader.into_inner(), vec![0, 0, 0, 9]);
+    }
+
+    #[test]
+    fn clones_share_the_image() {
+        let mut reader = MemoryReader::new(vec![0u8; 4]);
+        let mut clone = reader.clone();
+        clone.seek(SeekFrom::Start(1)).unwrap();
+        clone.write_all(&[7]).unwrap();
+        let mut buf = [0u8; 2];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 7]);
+    }
+
+    #[test]
+    fn rejects_seek_before_start() {
+        let mut reader = MemoryReader::new(vec![0u8; 4]);
+        assert!(reader.seek(SeekFrom::Current(-1)).is_err());
+    }
+}
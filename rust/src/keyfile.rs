@@ -0,0 +1,146 @@
+use crate::volume::VolumeError;
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+// VeraCrypt keyfile pool sizes. Passwords up to 64 bytes use the legacy pool,
+// longer passwords use the extended pool (VeraCrypt 1.24+).
+const KEYFILE_POOL_LEGACY_SIZE: usize = 64;
+const KEYFILE_POOL_SIZE: usize = 128;
+const MAX_LEGACY_PASSWORD: usize = 64;
+// VeraCrypt only reads the first 1 MB of a keyfile; we reject larger inputs outright
+// so the caller does not silently get a different key than expected.
+pub const KEYFILE_MAX_READ_LEN: usize = 1024 * 1024;
+
+// Single-step CRC32 update (reflected, poly 0xEDB88320) without the final XOR,
+// matching VeraCrypt's UPDC32 macro.
+fn crc32_update(crc: u32, byte: u8) -> u32 {
+    let mut c = crc ^ byte as u32;
+    for _ in 0..8 {
+        c = if c & 1 != 0 { (c >> 1) ^ 0xEDB8_8320 } else { c >> 1 };
+    }
+    c
+}
+
+// Accumulator for keyfile CRC output. Wiped on drop since it is password-equivalent.
+#[derive(Zeroize, ZeroizeOnDrop)]
+struct KeyfilePool {
+    data: [u8; KEYFILE_POOL_SIZE],
+    size: usize,
+}
+
+impl KeyfilePool {
+    fn new(size: usize) -> Self {
+        Self { data: [0u8; KEYFILE_POOL_SIZE], size }
+    }
+
+    fn add_keyfile(&mut self, keyfile: &[u8]) {
+        // CRC state and write position restart for each keyfile.
+        let mut crc = 0xFFFF_FFFFu32;
+        let mut write_pos = 0usize;
+        for &byte in keyfile.iter() {
+            crc = crc32_update(crc, byte);
+            for shift in [24u32, 16, 8, 0] {
+                self.data[write_pos] = self.data[write_pos].wrapping_add((crc >> shift) as u8);
+                write_pos += 1;
+            }
+            if write_pos >= self.size {
+                write_pos = 0;
+            }
+        }
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.data[..self.size]
+    }
+}
+
+/// Mixes the given keyfiles into the password using the VeraCrypt keyfile algorithm.
+///
+/// Every keyfile is hashed with a rolling CRC32 whose bytes are added into a
+/// pool; the pool is then added byte-wise into the (zero-padded) password.
+/// An empty keyfile list returns the password unchanged.
+pub fn apply_keyfiles(password: &[u8], keyfiles: &[&[u8]]) -> Result<Zeroizing<Vec<u8>>, VolumeError> {
+    if keyfiles.is_empty() {
+        return Ok(Zeroizing::new(password.to_vec()));
+    }
+
+    let pool_size = if password.len() <= MAX_LEGACY_PASSWORD {
+        KEYFILE_POOL_LEGACY_SIZE
+    } else {
+        KEYFILE_POOL_SIZE
+    };
+    let mut pool = KeyfilePool::new(pool_size);
+
+    for (index, keyfile) in keyfiles.iter().enumerate() {
+        if keyfile.is_empty() {
+            return Err(VolumeError::CryptoError(format!("Keyfile {} is empty", index)));
+        }
+        if keyfile.len() > KEYFILE_MAX_READ_LEN {
+            return Err(VolumeError::CryptoError(format!(
+                "Keyfile {} exceeds maximum size of {} bytes",
+                index, KEYFILE_MAX_READ_LEN
+            )));
+        }
+        pool.add_keyfile(keyfile);
+    }
+
+    let mut mixed = Zeroizing::new(vec![0u8; password.len().max(pool_size)]);
+    mixed[..password.len()].copy_from_slice(password);
+    for (dst, src) in mixed.iter_mut().zip(pool.as_slice().iter()) {
+        *dst = dst.wrapping_add(*src);
+    }
+
+    Ok(mixed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_empty_keyfile_list_keeps_password() {
+        let mixed = apply_keyfiles(b"password", &[]).expect("Mixing failed");
+        assert_eq!(mixed.as_slice(), b"password");
+    }
+
+    #[test]
+    fn test_keyfile_pads_password_to_pool_size() {
+        let mixed = apply_keyfiles(b"password", &[b"keyfile-contents"]).expect("Mixing failed");
+        assert_eq!(mixed.len(), KEYFILE_POOL_LEGACY_SIZE);
+        assert_ne!(&mixed[..8], b"password");
+
+        let long_password = [b'a'; 100];
+        let mixed = apply_keyfiles(&long_password, &[b"keyfile-contents"]).expect("Mixing failed");
+        assert_eq!(mixed.len(), KEYFILE_POOL_SIZE);
+    }
+
+    #[test]
+    fn test_keyfile_order_and_content_matter() {
+        let a = apply_keyfiles(b"pw", &[b"first"]).expect("Mixing failed");
+        let b = apply_keyfiles(b"pw", &[b"second"]).expect("Mixing failed");
+        assert_ne!(a.as_slice(), b.as_slice());
+
+        let both = apply_keyfiles(b"pw", &[b"first", b"second"]).expect("Mixing failed");
+        let both_swapped = apply_keyfiles(b"pw", &[b"second", b"first"]).expect("Mixing failed");
+        // Pool addition is commutative across keyfiles.
+        assert_eq!(both.as_slice(), both_swapped.as_slice());
+    }
+
+    #[test]
+    fn test_keyfile_pool_zeroize_clears_data() {
+        let mut pool = KeyfilePool::new(KEYFILE_POOL_LEGACY_SIZE);
+        pool.add_keyfile(b"keyfile-contents");
+        assert!(pool.as_slice().iter().any(|&b| b != 0));
+
+        pool.zeroize();
+        assert!(pool.data.iter().all(|&b| b == 0));
+    }
+
+    #[test]
+    fn test_oversized_keyfile_rejected() {
+        let big = vec![0u8; KEYFILE_MAX_READ_LEN + 1];
+        match apply_keyfiles(b"pw", &[&big]) {
+            Err(VolumeError::CryptoError(msg)) => assert!(msg.contains("maximum size")),
+            other => panic!("Expected CryptoError, got {:?}", other.map(|_| ())),
+        }
+    }
+}
@@ -15,6 +15,8 @@ use zeroize::Zeroizing;
 
 // Declare the volume module, which likely contains logic for handling encrypted volumes.
 mod volume;
+// Declare the keyfile module, which mixes VeraCrypt keyfiles into the password.
+mod keyfile;
 // Declare the crypto module, which likely contains cryptographic primitives and operations.
 mod crypto;
 mod format;
@@ -23,21 +25,37 @@ mod format_exfat;
 mod header;
 // Declare the io_callback module, which likely provides mechanisms for I/O callbacks.
 mod io_callback;
+// Declare the io_memory module, an in-memory backend for tests and ramdisk volumes without a JVM.
+#[cfg(any(test, feature = "in-memory"))]
+mod io_memory;
+// Declare the io_file module, a host file backend for mounting without the Java callback.
+mod io_file;
 // Declare the filesystem module, which likely handles file system operations.
 mod filesystem;
+// Declare the fat32 module, a minimal FAT32 driver with write support.
+mod fat32;
+// Declare the ntfs_write module, which edits raw NTFS records (file deletion).
+mod ntfs_write;
+// Declare the error module, the numeric error codes reported by getLastErrorCode.
+mod error;
 #[cfg(test)]
 mod test_fixes;
 
 // Import SupportedFileSystem and DecryptedReader types from the filesystem module.
 use filesystem::{DecryptedReader, SupportedFileSystem};
+use error::NoxError;
 // Import CallbackReader from the io_callback module.
 use io_callback::CallbackReader;
 // Import the Ntfs struct from the ntfs crate (or module) for NTFS file system support.
 use ntfs::Ntfs;
 // Import the ExFat struct from the exfat crate (or module) for exFAT file system support.
 use exfat::ExFat;
+// Import the minimal FAT32 driver for FAT32 file system support.
+use fat32::Fat32;
 // Import JValue from jni::objects to represent Java values in JNI calls.
 use jni::objects::JValue;
+// Import the Read trait for probing the boot sector.
+use std::io::Read;
 
 // Import Mutex from std::sync to provide mutual exclusion for thread-safe data access.
 // Import Mutex and RwLock from std::sync for concurrency.
@@ -74,32 +92,35 @@ struct InMemoryLogger;
 impl log::Log for InMemoryLogger {
     // Define the enabled method to check if logging is enabled for a given metadata.
     fn enabled(&self, metadata: &log::Metadata) -> bool {
-        // Return true if the log level is Info or more critical (Error, Warn).
-        metadata.level() <= log::Level::Info
+        // Warnings and errors are always captured, regardless of the configured max level.
+        if metadata.level() <= log::Level::Warn {
+            return true;
+        }
+        // Otherwise return true if the log level is within the current max level (Info by default).
+        metadata.level() <= log::max_level().min(LevelFilter::Info)
     }
 
     // Define the log method to record a log entry.
     fn log(&self, record: &log::Record) {
         // Check if logging is enabled for this record's metadata.
         if self.enabled(record.metadata()) {
-            // Format the log message with the level and the message arguments.
-
             // The following lines create CStrings for Android logging but are currently unused variables (prefixed with _).
             // Create a CString for the tag "RustNative".
             // Create a CString for the log message.
             // The comments explain that simple Android logging via FFI is possible but not implemented here.
             // We are prioritizing the in-memory buffer for the user to retrieve logs.
 
-            // Attempt to lock the global LOG_BUFFER mutex.
-            if let Ok(mut buffer) = LOG_BUFFER.lock() {
-                let message = clamp_log_message(&record.args().to_string());
+            // Format the log message with the level and the message arguments.
+            let message = clamp_log_message(&format!("[{}] {}", record.level(), record.args()));
 
-                while buffer.len() >= MAX_LOG_ENTRIES {
-                    buffer.pop_front();
-                }
+            // Lock the global LOG_BUFFER mutex, recovering from poisoning so logs are never dropped.
+            let mut buffer = LOG_BUFFER.lock().unwrap_or_else(|e| e.into_inner());
 
-                buffer.push_back(message);
+            while buffer.len() >= MAX_LOG_ENTRIES {
+                buffer.pop_front();
             }
+
+            buffer.push_back(message);
         }
     }
 
@@ -111,6 +132,11 @@ impl log::Log for InMemoryLogger {
 // Define a static global instance of InMemoryLogger named LOGGER.
 static LOGGER: InMemoryLogger = InMemoryLogger;
 
+// Clamp a requested max level so that Warn and Error records always reach the logger.
+fn effective_max_level(requested: LevelFilter) -> LevelFilter {
+    requested.max(LevelFilter::Warn)
+}
+
 fn clamp_log_message(message: &str) -> String {
     let mut bounded = String::with_capacity(message.len().min(MAX_LOG_MESSAGE_CHARS + 3));
 
@@ -141,13 +167,112 @@ pub extern "system" fn Java_com_noxcipher_RustNative_initLogger(
         // If successful, set the max log level to Info.
         // Ignore errors if the logger is already set.
         log::set_logger(&LOGGER)
-            .map(|()| log::set_max_level(LevelFilter::Info))
+            .map(|()| log::set_max_level(effective_max_level(LevelFilter::Info)))
             .ok();
         // Log an info message indicating that the Rust logger has been initialized.
         log::info!("Rust logger initialized (InMemory)");
     });
 }
 
+thread_local! {
+    // Code and message of the most recent JNI failure on this thread (thrown or returned as an
+    // error code), for getLastErrorCode and getLastError. Kept together so the two always agree.
+    static LAST_ERROR: std::cell::RefCell<Option<(NoxError, String)>> = const { std::cell::RefCell::new(None) };
+}
+
+// Remember a failure message for getLastError. Messages come from error Display output and
+// fixed strings, which never include passwords or key bytes; they are length-bounded like log records.
+fn set_last_error(message: &str) {
+    record_error(NoxError::Other, message);
+}
+
+// Remember a failure message for getLastError and its code for getLastErrorCode.
+fn record_error(code: NoxError, message: &str) {
+    let message = clamp_log_message(message);
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some((code, message)));
+}
+
+// Log and record a failure, returning its code negated: the result of JNI functions that
+// report failure through an int (formatVolume, changePassword, restoreHeader, ...).
+fn fail(code: NoxError, message: &str) -> jni::sys::jint {
+    log::error!("{}", message);
+    record_error(code, message);
+    code.return_code()
+}
+
+// Same as fail, for a VolumeError (returns VolumeError::code).
+fn fail_volume(err: &volume::VolumeError, message: &str) -> jni::sys::jint {
+    fail(err.into(), message);
+    err.code()
+}
+
+// The code and message recorded by the most recent failure on this thread.
+fn last_error() -> Option<(NoxError, String)> {
+    LAST_ERROR.with(|last| last.borrow().clone())
+}
+
+// Record a failure message and throw it as a Java exception of the given class.
+fn throw_error<S: AsRef<str>>(env: &mut JNIEnv, class: &str, message: S) {
+    throw_error_with_code(env, class, NoxError::for_exception_class(class), message);
+}
+
+// Like throw_error, but with the code of the VolumeError that caused the failure.
+fn throw_volume_error<S: AsRef<str>>(env: &mut JNIEnv, class: &str, err: &volume::VolumeError, message: S) {
+    throw_error_with_code(env, class, err.into(), message);
+}
+
+fn throw_error_with_code<S: AsRef<str>>(env: &mut JNIEnv, class: &str, code: NoxError, message: S) {
+    record_error(code, message.as_ref());
+    let _ = env.throw_new(class, message.as_ref());
+}
+
+// Like throw_error for a failed JNI call, which may already have left its own exception pending.
+fn throw_unless_pending<S: AsRef<str>>(env: &mut JNIEnv, class: &str, message: S) {
+    if env.exception_check().unwrap_or(false) {
+        record_error(NoxError::Internal, message.as_ref());
+    } else {
+        throw_error_with_code(env, class, NoxError::Internal, message);
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getLastErrorCode.
+// Returns the NoxError code of the most recent failure on the calling thread, or 0.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getLastErrorCode(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jint {
+    panic::catch_unwind(|| last_error().map_or(NoxError::None, |(code, _)| code).code()).unwrap_or(NoxError::Internal.code())
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getLastError.
+// Returns the message of the most recent failure on the calling thread, or an empty string.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getLastError(
+    env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jstring {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let message = last_error().map(|(_, message)| message).unwrap_or_default();
+        match env.new_string(message) {
+            Ok(s) => s.into_raw(),
+            Err(_) => ptr::null_mut(),
+        }
+    }));
+    res.unwrap_or(ptr::null_mut())
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_setDebugErrors.
+// Enables per-cipher diagnostics in failed mount errors (off by default in release builds).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_setDebugErrors(
+    _env: JNIEnv,
+    _class: JClass,
+    enabled: jni::sys::jboolean,
+) {
+    volume::set_debug_errors(enabled != 0);
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_getLogs.
 // It returns a jobjectArray containing the logs.
 #[no_mangle]
@@ -250,15 +375,170 @@ pub extern "system" fn Java_com_noxcipher_RustNative_closeFs(
     });
 }
 
+// Copy a list of handles (or other longs) into a new Java long[] (null on JNI failure).
+fn handles_to_jlong_array(env: &mut JNIEnv, handles: &[i64]) -> jni::sys::jlongArray {
+    let array = match env.new_long_array(handles.len() as i32) {
+        Ok(a) => a,
+        Err(_) => return ptr::null_mut(),
+    };
+    if env.set_long_array_region(&array, 0, handles).is_err() {
+        return ptr::null_mut();
+    }
+    array.into_raw()
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getActiveHandles.
+// Returns the handles of all open volume contexts, to diagnose handle leaks.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getActiveHandles(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jlongArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let handles = volume::active_handles();
+        log::info!("{} active volume handle(s): {:?}", handles.len(), handles);
+        handles_to_jlong_array(&mut env, &handles)
+    }));
+    res.unwrap_or(ptr::null_mut())
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getActiveFsHandles.
+// Returns the handles of all mounted file systems, to diagnose handle leaks.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getActiveFsHandles(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jlongArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let mut handles: Vec<i64> = {
+            // Use unwrap_or_else to handle poisoned lock gracefully
+            let lock = FILESYSTEMS.read().unwrap_or_else(|e| e.into_inner());
+            lock.keys().copied().collect()
+        };
+        handles.sort_unstable();
+        log::info!("{} active file system handle(s): {:?}", handles.len(), handles);
+        handles_to_jlong_array(&mut env, &handles)
+    }));
+    res.unwrap_or(ptr::null_mut())
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_init.
 // It initializes the volume context and returns a handle (jlong).
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn Java_com_noxcipher_RustNative_init(
-    // The JNI environment.
+    env: JNIEnv,
+    _class: JClass,
+    password: jbyteArray,
+    header: jbyteArray,
+    pim: jni::sys::jint,
+    partition_offset: jlong,
+    header_offset: jlong,
+    protection_password: jbyteArray,
+    protection_pim: jni::sys::jint,
+    volume_size: jlong,
+    backup_header: jbyteArray,
+    keyfiles: jobjectArray,
+) -> jlong {
+    init_volume(
+        env, password, header, pim, partition_offset, header_offset, protection_password,
+        protection_pim, volume_size, backup_header, keyfiles, false, None, None, false,
+    )
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_initEx.
+// Same as init, but a failed mount returns VolumeError::code() (-2 wrong password, -3 bad header,
+// -4 crypto error, -5 I/O error, -6..-9 a decrypted header that failed a specific check) instead
+// of throwing. Invalid arguments still throw and return -1.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_initEx(
+    env: JNIEnv,
+    _class: JClass,
+    password: jbyteArray,
+    header: jbyteArray,
+    pim: jni::sys::jint,
+    partition_offset: jlong,
+    header_offset: jlong,
+    protection_password: jbyteArray,
+    protection_pim: jni::sys::jint,
+    volume_size: jlong,
+    backup_header: jbyteArray,
+    keyfiles: jobjectArray,
+) -> jlong {
+    init_volume(
+        env, password, header, pim, partition_offset, header_offset, protection_password,
+        protection_pim, volume_size, backup_header, keyfiles, true, None, None, false,
+    )
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_initReadOnly.
+// Same as init, but the returned context rejects every write (e.g. for backup snapshots).
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_initReadOnly(
+    env: JNIEnv,
+    _class: JClass,
+    password: jbyteArray,
+    header: jbyteArray,
+    pim: jni::sys::jint,
+    partition_offset: jlong,
+    header_offset: jlong,
+    protection_password: jbyteArray,
+    protection_pim: jni::sys::jint,
+    volume_size: jlong,
+    backup_header: jbyteArray,
+    keyfiles: jobjectArray,
+) -> jlong {
+    init_volume(
+        env, password, header, pim, partition_offset, header_offset, protection_password,
+        protection_pim, volume_size, backup_header, keyfiles, false, None, None, true,
+    )
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_initHinted.
+// Same as init, but first tries only the given cipher/PRF ids (as in formatVolume, -1 for
+// no hint), falling back to the full search when they do not match.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_initHinted(
     mut env: JNIEnv,
-    // The Java class.
     _class: JClass,
+    password: jbyteArray,
+    header: jbyteArray,
+    pim: jni::sys::jint,
+    partition_offset: jlong,
+    header_offset: jlong,
+    protection_password: jbyteArray,
+    protection_pim: jni::sys::jint,
+    volume_size: jlong,
+    backup_header: jbyteArray,
+    keyfiles: jobjectArray,
+    cipher_hint: jni::sys::jint,
+    prf_hint: jni::sys::jint,
+) -> jlong {
+    let cipher = int_to_cipher_type(cipher_hint);
+    if cipher_hint != -1 && cipher.is_none() {
+        throw_error(&mut env, "java/lang/IllegalArgumentException", format!("Invalid cipher hint: {}", cipher_hint));
+        return -1;
+    }
+    let prf = int_to_prf(prf_hint);
+    if prf_hint != -1 && prf.is_none() {
+        throw_error(&mut env, "java/lang/IllegalArgumentException", format!("Invalid PRF hint: {}", prf_hint));
+        return -1;
+    }
+
+    init_volume(
+        env, password, header, pim, partition_offset, header_offset, protection_password,
+        protection_pim, volume_size, backup_header, keyfiles, false, cipher, prf, false,
+    )
+}
+
+// Shared implementation of init, initEx and initHinted.
+#[allow(clippy::too_many_arguments)]
+fn init_volume(
+    // The JNI environment.
+    mut env: JNIEnv,
     // The password as a byte array.
     password: jbyteArray,
     // The header data as a byte array.
@@ -277,6 +557,15 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
     volume_size: jlong,
     // The backup header data as a byte array (optional).
     backup_header: jbyteArray,
+    // Keyfile contents as a byte[][] (optional, null or empty for none).
+    keyfiles: jobjectArray,
+    // Return VolumeError::code() for mount failures instead of throwing.
+    error_codes: bool,
+    // Cipher and PRF to try before the full search (None for no hint).
+    cipher_hint: Option<volume::CipherType>,
+    prf_hint: Option<volume::PrfAlgorithm>,
+    // Mount the context read-only.
+    read_only: bool,
 ) -> jlong {
     // Wrap the entire execution in panic::catch_unwind to handle panics gracefully.
     // AssertUnwindSafe is used because we are sharing references across the boundary.
@@ -286,32 +575,32 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
         
         // Validate inputs are not null where required
         if password.is_null() {
-             let _ = env.throw_new("java/lang/IllegalArgumentException", "Password cannot be null");
+             throw_error(&mut env, "java/lang/IllegalArgumentException", "Password cannot be null");
              return -1;
         }
         if header.is_null() {
-             let _ = env.throw_new("java/lang/IllegalArgumentException", "Header cannot be null");
+             throw_error(&mut env, "java/lang/IllegalArgumentException", "Header cannot be null");
              return -1;
         }
 
         // Validate positive offsets and sizes
         let partition_offset_u64 = u64::try_from(partition_offset).map_err(|_| "Negative partition offset")
              .unwrap_or_else(|_| {
-                 let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative partition offset");
+                 throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative partition offset");
                  u64::MAX // Sentinel, execution will stop
              });
         if partition_offset_u64 == u64::MAX { return -1; }
 
         let header_offset_u64 = u64::try_from(header_offset).map_err(|_| "Negative header offset")
              .unwrap_or_else(|_| {
-                 let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative header offset");
+                 throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative header offset");
                  u64::MAX 
              });
         if header_offset_u64 == u64::MAX { return -1; }
 
         let volume_size_u64 = u64::try_from(volume_size).map_err(|_| "Negative volume size")
              .unwrap_or_else(|_| {
-                 let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative volume size");
+                 throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative volume size");
                  u64::MAX 
              });
         if volume_size_u64 == u64::MAX { return -1; }
@@ -322,7 +611,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
         let mut password_bytes = match env.convert_byte_array(&password_obj) {
              Ok(b) => Zeroizing::new(b),
              Err(e) => {
-                let _ = env.throw_new(
+                throw_error(&mut env, 
                     "java/lang/IllegalArgumentException",
                     format!("Invalid password array: {}", e),
                 );
@@ -336,7 +625,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
         let header_bytes = match env.convert_byte_array(&header_obj) {
              Ok(b) => Zeroizing::new(b),
              Err(e) => {
-                let _ = env.throw_new(
+                throw_error(&mut env, 
                     "java/lang/IllegalArgumentException",
                     format!("Invalid header array: {}", e),
                 );
@@ -374,20 +663,56 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
             None
         };
 
-        // Call the volume::create_context function to attempt to mount the volume.
-        // Pass references to the password, header, and other parameters.
-        let res = volume::create_context(
-            &password_bytes,
-            &header_bytes,
-            pim,
-            partition_offset_u64,
-            None,
-            header_offset_u64,
-            protection_password_bytes.as_deref().map(|z| z.as_slice()),
-            protection_pim,
-            volume_size_u64,
-            backup_header_bytes.as_deref().map(|z| z),
-        );
+        // Read the optional keyfiles.
+        let keyfile_bytes = match read_keyfiles(&mut env, keyfiles) {
+            Ok(k) => k,
+            Err(e) => {
+                throw_error(&mut env, 
+                    "java/lang/IllegalArgumentException",
+                    format!("Invalid keyfiles array: {}", e),
+                );
+                return -1;
+            }
+        };
+        let keyfile_slices: Vec<&[u8]> = keyfile_bytes.iter().map(|k| k.as_slice()).collect();
+
+        // Call the volume::create_context_with_keyfiles function to attempt to mount the volume.
+        // Pass references to the password, keyfiles, header, and other parameters.
+        let res = if cipher_hint.is_none() && prf_hint.is_none() {
+            volume::create_context_with_keyfiles(
+                &password_bytes,
+                &keyfile_slices,
+                &header_bytes,
+                pim,
+                partition_offset_u64,
+                None,
+                header_offset_u64,
+                protection_password_bytes.as_deref().map(|z| z.as_slice()),
+                protection_pim,
+                volume_size_u64,
+                backup_header_bytes.as_deref().map(|z| z),
+                read_only,
+            )
+        } else {
+            // Hinted mount: mix the keyfiles in here, then try the hint before the full search.
+            keyfile::apply_keyfiles(&password_bytes, &keyfile_slices).and_then(|effective_password| {
+                volume::create_context_hinted(
+                    &effective_password,
+                    &header_bytes,
+                    pim,
+                    partition_offset_u64,
+                    None,
+                    header_offset_u64,
+                    protection_password_bytes.as_deref().map(|z| z.as_slice()),
+                    protection_pim,
+                    volume_size_u64,
+                    backup_header_bytes.as_deref().map(|z| z),
+                    cipher_hint,
+                    prf_hint,
+                    read_only,
+                )
+            })
+        };
 
         // Explicit zeroize is redundant if we use Zeroizing, but keeping for clarity/legacy correctness
         // Import the Zeroize trait to securely clear memory.
@@ -418,8 +743,12 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
             Err(e) => {
                 // Log the error.
                 log::error!("Init failed: {}", e);
+                if error_codes {
+                    record_error((&e).into(), &format!("Init failed: {}", e));
+                    return e.code() as jlong;
+                }
                 // Throw a Java IOException with the error message.
-                let _ = env.throw_new("java/io/IOException", format!("Init failed: {}", e));
+                throw_volume_error(&mut env, "java/io/IOException", &e, format!("Init failed: {}", e));
                 // Return -1 to indicate failure.
                 -1
             }
@@ -446,13 +775,104 @@ pub extern "system" fn Java_com_noxcipher_RustNative_init(
             // Log the panic message as an error.
             log::error!("{}", msg);
             // Throw a Java RuntimeException with the panic message.
-            let _ = env.throw_new("java/lang/RuntimeException", msg);
+            throw_error(&mut env, "java/lang/RuntimeException", msg);
             // Return -1 to indicate failure.
             -1
         }
     }
 }
 
+// Define a JNI function named Java_com_noxcipher_RustNative_initWithKeyfiles.
+// Convenience entry point for the common case: no partition/header offsets and no hidden volume protection.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_initWithKeyfiles(
+    // The JNI environment.
+    env: JNIEnv,
+    // The Java class.
+    class: JClass,
+    // The password as a byte array.
+    password: jbyteArray,
+    // The header data as a byte array.
+    header: jbyteArray,
+    // The PIM value.
+    pim: jni::sys::jint,
+    // Keyfile contents as a byte[][].
+    keyfiles: jobjectArray,
+    // The total volume size (for safety checks).
+    volume_size: jlong,
+    // The backup header data as a byte array (optional).
+    backup_header: jbyteArray,
+) -> jlong {
+    // Delegate to init, which handles validation, keyfile mixing and panics.
+    Java_com_noxcipher_RustNative_init(
+        env,
+        class,
+        password,
+        header,
+        pim,
+        0,
+        0,
+        ptr::null_mut(),
+        0,
+        volume_size,
+        backup_header,
+        keyfiles,
+    )
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_initFromFile.
+// Mounts a container file by path: the primary, hidden and backup header areas are read from
+// the file itself, so the caller does not need to know the volume layout.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_initFromFile(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: JString,
+    password: jbyteArray,
+    pim: jni::sys::jint,
+    read_only: jni::sys::jboolean,
+) -> jlong {
+    let result = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(_) => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid path");
+                return -1;
+            }
+        };
+        let password_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(password) }) {
+            Ok(b) => Zeroizing::new(b),
+            Err(_) => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Password cannot be null");
+                return -1;
+            }
+        };
+
+        match volume::create_context_from_file(&path_str, &password_bytes, pim, None, 0, read_only != 0) {
+            Ok(handle) => {
+                log::info!("Init from file success, handle: {}", handle);
+                handle
+            }
+            Err(e) => {
+                log::error!("Init from file failed: {}", e);
+                throw_volume_error(&mut env, "java/io/IOException", &e, format!("Init failed: {}", e));
+                -1
+            }
+        }
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in initFromFile");
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in initFromFile");
+            -1
+        }
+    }
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_decrypt.
 // It decrypts data in place.
 #[no_mangle]
@@ -472,14 +892,14 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decrypt(
     // Wrap execution in panic::catch_unwind.
     let _ = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         if data.is_null() {
-            let _ = env.throw_new("java/lang/IllegalArgumentException", "Data buffer cannot be null");
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Data buffer cannot be null");
             return;
         }
 
         let offset_u64 = match u64::try_from(offset) {
             Ok(o) => o,
             Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative offset");
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative offset");
                  return;
             }
         };
@@ -493,7 +913,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decrypt(
             // If an error occurs:
             Err(e) => {
                 // Throw a RuntimeException.
-                let _ = env.throw_new(
+                throw_error(&mut env, 
                     "java/lang/RuntimeException",
                     format!("Failed to get array length: {}", e),
                 );
@@ -503,7 +923,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decrypt(
         };
 
         if len < 0 {
-            let _ = env.throw_new("java/lang/IllegalArgumentException", "Invalid array length");
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid array length");
             return;
         }
 
@@ -519,7 +939,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decrypt(
             // zeroize before returning
             buf.zeroize();
             // If an error occurs, throw a RuntimeException.
-            let _ = env.throw_new(
+            throw_error(&mut env, 
                 "java/lang/RuntimeException",
                 format!("Failed to read array: {}", e),
             );
@@ -531,7 +951,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decrypt(
         if let Err(e) = volume::decrypt(handle, offset_u64, &mut buf) {
             buf.zeroize();
             // If decryption fails, throw an IOException.
-            let _ = env.throw_new("java/io/IOException", format!("Decrypt failed: {}", e));
+            throw_volume_error(&mut env, "java/io/IOException", &e, format!("Decrypt failed: {}", e));
             // Return early.
             return;
         }
@@ -545,7 +965,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decrypt(
         // Set the Java byte array region with the decrypted data.
         if let Err(e) = env.set_byte_array_region(&data_obj, 0, buf_slice) {
             // If writing back fails, throw a RuntimeException.
-            let _ = env.throw_new(
+            throw_error(&mut env, 
                 "java/lang/RuntimeException",
                 format!("Failed to write back array: {}", e),
             );
@@ -576,14 +996,14 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encrypt(
     // Wrap execution in panic::catch_unwind.
     let _ = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         if data.is_null() {
-            let _ = env.throw_new("java/lang/IllegalArgumentException", "Data buffer cannot be null");
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Data buffer cannot be null");
             return;
         }
 
         let offset_u64 = match u64::try_from(offset) {
             Ok(o) => o,
             Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative offset");
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative offset");
                  return;
             }
         };
@@ -597,7 +1017,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encrypt(
             // If an error occurs:
             Err(e) => {
                 // Throw a RuntimeException.
-                let _ = env.throw_new(
+                throw_error(&mut env, 
                     "java/lang/RuntimeException",
                     format!("Failed to get array length: {}", e),
                 );
@@ -617,7 +1037,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encrypt(
         if let Err(e) = env.get_byte_array_region(&data_obj, 0, buf_slice) {
             buf.zeroize();
             // If an error occurs, throw a RuntimeException.
-            let _ = env.throw_new(
+            throw_error(&mut env, 
                 "java/lang/RuntimeException",
                 format!("Failed to read array: {}", e),
             );
@@ -629,7 +1049,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encrypt(
         if let Err(e) = volume::encrypt(handle, offset_u64, &mut buf) {
             buf.zeroize();
             // If encryption fails, throw an IOException.
-            let _ = env.throw_new("java/io/IOException", format!("Encrypt failed: {}", e));
+            throw_volume_error(&mut env, "java/io/IOException", &e, format!("Encrypt failed: {}", e));
             // Return early.
             return;
         }
@@ -643,7 +1063,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encrypt(
         // Set the Java byte array region with the encrypted data.
         if let Err(e) = env.set_byte_array_region(&data_obj, 0, buf_slice) {
             // If writing back fails, throw a RuntimeException.
-            let _ = env.throw_new(
+            throw_error(&mut env, 
                 "java/lang/RuntimeException",
                 format!("Failed to write back array: {}", e),
             );
@@ -655,8 +1075,35 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encrypt(
     }));
 }
 
+// Decrypt `length` bytes at `position` of a direct buffer's memory in place, with no intermediate copy.
+// `region` covers the whole buffer capacity. Returns the Java exception class and message on failure.
+fn decrypt_direct_region(
+    handle: jlong,
+    offset: jlong,
+    region: &mut [u8],
+    position: jni::sys::jint,
+    length: jni::sys::jint,
+) -> Result<(), (&'static str, String)> {
+    let range = match (usize::try_from(position), usize::try_from(length)) {
+        (Ok(p), Ok(l)) if p.checked_add(l).is_some_and(|end| end <= region.len()) => p..p + l,
+        _ => {
+            return Err((
+                "java/lang/IndexOutOfBoundsException",
+                format!("Invalid position/length {}/{} for capacity {}", position, length, region.len()),
+            ))
+        }
+    };
+
+    let offset_u64 = u64::try_from(offset)
+        .map_err(|_| ("java/lang/IllegalArgumentException", "Negative offset".to_string()))?;
+
+    // volume::decrypt rejects offsets and lengths that are not sector aligned.
+    volume::decrypt(handle, offset_u64, &mut region[range])
+        .map_err(|e| ("java/io/IOException", format!("Decrypt failed: {}", e)))
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_decryptDirect.
-// It decrypts data in a direct ByteBuffer in place.
+// It decrypts data in a direct ByteBuffer in place, avoiding the array copies of decrypt.
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn Java_com_noxcipher_RustNative_decryptDirect(
@@ -670,14 +1117,15 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decryptDirect(
 ) {
     let _ = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         if buffer.is_null() {
-             let _ = env.throw_new("java/lang/IllegalArgumentException", "Buffer cannot be null");
+             throw_error(&mut env, "java/lang/IllegalArgumentException", "Buffer cannot be null");
              return;
         }
 
+        // Heap ByteBuffers have no native address; callers must fall back to decrypt.
         let buf_ptr = match env.get_direct_buffer_address((&buffer).into()) {
-            Ok(p) => p,
-            Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Invalid direct buffer");
+            Ok(p) if !p.is_null() => p,
+            _ => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Buffer is not a direct ByteBuffer");
                 return;
             }
         };
@@ -685,35 +1133,73 @@ pub extern "system" fn Java_com_noxcipher_RustNative_decryptDirect(
         let capacity = match env.get_direct_buffer_capacity((&buffer).into()) {
             Ok(c) => c,
              Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Could not get buffer capacity");
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Could not get buffer capacity");
                 return;
             }
         };
 
-        if position < 0 || length < 0 || (position as usize + length as usize) > capacity {
-             let _ = env.throw_new("java/lang/IndexOutOfBoundsException", "Invalid position/length");
-             return;
+        let region = unsafe { std::slice::from_raw_parts_mut(buf_ptr, capacity) };
+        if let Err((class, msg)) = decrypt_direct_region(handle, offset, region, position, length) {
+            throw_error(&mut env, class, msg);
         }
+    }));
+}
 
-        let offset_u64 = match u64::try_from(offset) {
-            Ok(o) => o,
+// Define a JNI function named Java_com_noxcipher_RustNative_decryptRange.
+// It decrypts a whole contiguous range of a direct ByteBuffer in one call.
+// The first `length` bytes of the buffer are decrypted in place; the context is looked up once.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_decryptRange(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+    offset: jlong,
+    length: jlong,
+    buffer: jni::objects::JObject,
+) {
+    let _ = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if buffer.is_null() {
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Buffer cannot be null");
+            return;
+        }
+
+        let buf_ptr = match env.get_direct_buffer_address((&buffer).into()) {
+            Ok(p) if !p.is_null() => p,
+            _ => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid direct buffer");
+                return;
+            }
+        };
+
+        let capacity = match env.get_direct_buffer_capacity((&buffer).into()) {
+            Ok(c) => c,
             Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative offset");
-                 return;
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Could not get buffer capacity");
+                return;
             }
         };
 
-        if buf_ptr.is_null() {
-             let _ = env.throw_new("java/lang/IllegalArgumentException", "Buffer pointer is null");
-             return;
-        }
+        let length = match usize::try_from(length) {
+            Ok(l) if l <= capacity => l,
+            _ => {
+                throw_error(&mut env, "java/lang/IndexOutOfBoundsException", "Invalid length");
+                return;
+            }
+        };
 
-        // Create slice
-        let buf_slice = unsafe { std::slice::from_raw_parts_mut(buf_ptr, capacity) };
-        let target_slice = &mut buf_slice[(position as usize)..((position as usize) + (length as usize))];
+        let offset_u64 = match u64::try_from(offset) {
+            Ok(o) => o,
+            Err(_) => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative offset");
+                return;
+            }
+        };
 
+        // Decrypt the whole range in place; volume::decrypt loops over the sectors.
+        let target_slice = unsafe { std::slice::from_raw_parts_mut(buf_ptr, length) };
         if let Err(e) = volume::decrypt(handle, offset_u64, target_slice) {
-             let _ = env.throw_new("java/io/IOException", format!("Decrypt failed: {}", e));
+            throw_volume_error(&mut env, "java/io/IOException", &e, format!("Decrypt failed: {}", e));
         }
     }));
 }
@@ -733,14 +1219,14 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encryptDirect(
 ) {
     let _ = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         if buffer.is_null() {
-             let _ = env.throw_new("java/lang/IllegalArgumentException", "Buffer cannot be null");
+             throw_error(&mut env, "java/lang/IllegalArgumentException", "Buffer cannot be null");
              return;
         }
 
         let buf_ptr = match env.get_direct_buffer_address((&buffer).into()) {
             Ok(p) => p,
             Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Invalid direct buffer");
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid direct buffer");
                 return;
             }
         };
@@ -748,26 +1234,26 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encryptDirect(
         let capacity = match env.get_direct_buffer_capacity((&buffer).into()) {
             Ok(c) => c,
              Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Could not get buffer capacity");
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Could not get buffer capacity");
                 return;
             }
         };
 
          if position < 0 || length < 0 || (position as usize + length as usize) > capacity {
-             let _ = env.throw_new("java/lang/IndexOutOfBoundsException", "Invalid position/length");
+             throw_error(&mut env, "java/lang/IndexOutOfBoundsException", "Invalid position/length");
              return;
         }
 
         let offset_u64 = match u64::try_from(offset) {
             Ok(o) => o,
             Err(_) => {
-                let _ = env.throw_new("java/lang/IllegalArgumentException", "Negative offset");
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative offset");
                  return;
             }
         };
 
         if buf_ptr.is_null() {
-             let _ = env.throw_new("java/lang/IllegalArgumentException", "Buffer pointer is null");
+             throw_error(&mut env, "java/lang/IllegalArgumentException", "Buffer pointer is null");
              return;
         }
 
@@ -776,7 +1262,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_encryptDirect(
         let target_slice = &mut buf_slice[(position as usize)..((position as usize) + (length as usize))];
 
         if let Err(e) = volume::encrypt(handle, offset_u64, target_slice) {
-             let _ = env.throw_new("java/io/IOException", format!("Encrypt failed: {}", e));
+             throw_volume_error(&mut env, "java/io/IOException", &e, format!("Encrypt failed: {}", e));
         }
     }));
 }
@@ -823,20 +1309,109 @@ pub extern "system" fn Java_com_noxcipher_RustNative_getDataOffset(
         // If no panic but the function returned Err:
         Ok(Err(e)) => {
             // Throw an IllegalArgumentException with the error message.
-            let _ = env.throw_new("java/lang/IllegalArgumentException", format!("{}", e));
+            throw_volume_error(&mut env, "java/lang/IllegalArgumentException", &e, format!("{}", e));
             // Return -1.
             -1
         }
         // If a panic occurred:
         Err(_) => {
             // Throw a RuntimeException.
-            let _ = env.throw_new("java/lang/RuntimeException", "Panic in getDataOffset");
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in getDataOffset");
             // Return -1.
             -1
         }
     }
 }
 
+// Define a JNI function named Java_com_noxcipher_RustNative_getVolumeTimes.
+// Returns [volume creation time, header creation time] in Unix seconds.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getVolumeTimes(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jni::sys::jlongArray {
+    let res = panic::catch_unwind(|| volume::get_volume_times(handle));
+
+    match res {
+        Ok(Ok((volume_time, header_time))) => {
+            handles_to_jlong_array(&mut env, &[volume_time as i64, header_time as i64])
+        }
+        Ok(Err(e)) => {
+            throw_volume_error(&mut env, "java/lang/IllegalArgumentException", &e, format!("{}", e));
+            ptr::null_mut()
+        }
+        Err(_) => {
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in getVolumeTimes");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getVolumeInfo.
+// Returns [cipher, prf, sector size, data size, truecrypt] as strings,
+// or null if the handle is unknown.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getVolumeInfo(
+    // The JNI environment.
+    mut env: JNIEnv,
+    // The Java class.
+    _class: JClass,
+    // The volume handle.
+    handle: jlong,
+) -> jobjectArray {
+    let res = panic::catch_unwind(|| volume::get_volume_info(handle));
+
+    match res {
+        Ok(Ok(info)) => {
+            let fields = [
+                info.cipher_name.to_string(),
+                info.prf_name.to_string(),
+                info.sector_size.to_string(),
+                info.data_size.to_string(),
+                info.truecrypt.to_string(),
+            ];
+            to_java_string_array(&mut env, &fields)
+        }
+        Ok(Err(e)) => {
+            log::warn!("getVolumeInfo failed: {}", e);
+            ptr::null_mut()
+        }
+        Err(_) => {
+            log::error!("Panic in getVolumeInfo");
+            record_error(NoxError::Internal, "Panic in getVolumeInfo");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_dumpHeader.
+// Returns the decrypted header fields as "name: value" lines (no key material),
+// or null if the handle is unknown.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_dumpHeader(
+    env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jni::sys::jstring {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| match volume::dump_header(handle) {
+        Ok(summary) => match env.new_string(summary) {
+            Ok(s) => s.into_raw(),
+            Err(_) => ptr::null_mut(),
+        },
+        Err(e) => {
+            log::warn!("dumpHeader failed: {}", e);
+            ptr::null_mut()
+        }
+    }));
+
+    res.unwrap_or_else(|_| {
+        log::error!("Panic in dumpHeader");
+        record_error(NoxError::Internal, "Panic in dumpHeader");
+        ptr::null_mut()
+    })
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_isBackupHeaderUsed.
 // It checks if the volume was mounted using the backup header.
 #[no_mangle]
@@ -857,19 +1432,122 @@ pub extern "system" fn Java_com_noxcipher_RustNative_isBackupHeaderUsed(
     match res {
         Ok(val) => val,
         Err(_) => {
-            let _ = env.throw_new("java/lang/RuntimeException", "Panic in isBackupHeaderUsed");
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in isBackupHeaderUsed");
+            0
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_isReadOnly.
+// It checks if the volume rejects writes (mounted with initReadOnly).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_isReadOnly(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jni::sys::jboolean {
+    let res = panic::catch_unwind(|| match volume::is_read_only(handle) {
+        Ok(read_only) => read_only as jni::sys::jboolean,
+        Err(_) => 0, // False or error (handle not found)
+    });
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in isReadOnly");
+            0
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_isProtectionTriggered.
+// It checks if hidden volume protection has blocked a write since mount.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_isProtectionTriggered(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jni::sys::jboolean {
+    let res = panic::catch_unwind(|| match volume::is_protection_triggered(handle) {
+        Ok(triggered) => triggered as jni::sys::jboolean,
+        Err(_) => 0, // False or error (handle not found)
+    });
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in isProtectionTriggered");
+            0
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_verifyHeader.
+// It recomputes the header and key-area CRC32 of a mounted volume and compares them with the stored values.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_verifyHeader(
+    mut env: JNIEnv,
+    _class: JClass,
+    handle: jlong,
+) -> jni::sys::jboolean {
+    let res = panic::catch_unwind(|| match volume::verify_header(handle) {
+        Ok(intact) => {
+            if !intact {
+                log::error!("Header integrity check failed for handle {}", handle);
+                record_error(NoxError::HeaderCrc, "Header integrity check failed");
+            }
+            intact as jni::sys::jboolean
+        }
+        Err(e) => {
+            record_error((&e).into(), &format!("verifyHeader failed: {}", e));
+            0
+        }
+    });
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in verifyHeader");
             0
         }
     }
 }
 
+// mountFs failure codes, the negated NoxError reported by getLastErrorCode.
+// Generic failure. Unknown volume handles, JNI errors and panics report their own NoxError.
+const MOUNT_ERR_GENERIC: jlong = NoxError::Other.return_code() as jlong;
+// The boot sector decrypted fine but no supported file system was recognized
+// (unformatted volume, or wrong keys producing garbage).
+const MOUNT_ERR_NO_FILESYSTEM: jlong = NoxError::MountFailed.return_code() as jlong;
+// I/O error while reading the boot sector through the callback.
+const MOUNT_ERR_IO: jlong = NoxError::IoError.return_code() as jlong;
+
+// Store a mounted file system in FILESYSTEMS and return its new handle.
+fn register_filesystem(fs: SupportedFileSystem) -> jlong {
+    let mut lock = match FILESYSTEMS.write() {
+        Ok(l) => l,
+        Err(e) => e.into_inner(),
+    };
+    let mut handle_lock = match NEXT_FS_HANDLE.lock() {
+        Ok(l) => l,
+        Err(e) => e.into_inner(),
+    };
+
+    let handle = *handle_lock;
+    *handle_lock += 1;
+
+    lock.insert(handle, Arc::new(Mutex::new(fs)));
+    handle
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_mountFs.
-// It attempts to mount a file system (NTFS or exFAT) on the volume.
+// It attempts to mount a file system (NTFS, exFAT or FAT32) on the volume.
+// Returns a file system handle, or a negative error code (one of MOUNT_ERR_* unless it panicked).
 #[no_mangle]
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn Java_com_noxcipher_RustNative_mountFs(
-    env: JNIEnv,
+    mut env: JNIEnv,
     _class: JClass,
     volume_handle: jlong,
     callback_obj: jni::objects::JObject,
@@ -877,96 +1555,347 @@ pub extern "system" fn Java_com_noxcipher_RustNative_mountFs(
 ) -> jlong {
     // Wrap execution in panic::catch_unwind to handle panics gracefully.
     let result = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-        // Retrieve the volume context associated with the handle.
-        let volume = {
-            // Lock the global CONTEXTS map.
-            if let Ok(contexts) = volume::CONTEXTS.lock() {
-                // Look up the handle.
-                match contexts.get(&volume_handle) {
-                    // If found, clone the volume context.
-                    Some(v) => v.clone(),
-                    // If not found, return Err.
-                    None => return Err("Volume handle invalid or expired".to_string()),
-                }
-            } else {
-                 return Err("Failed to lock context map".to_string());
-            }
-        };
-
-        // Get the JavaVM instance safely.
-        let jvm = match env.get_java_vm() {
-            Ok(v) => v,
-            Err(e) => return Err(format!("JNI Error (get_java_vm): {}", e)),
-        };
+        let (volume, reader) = open_callback_reader(&mut env, volume_handle, callback_obj, volume_size)?;
+        detect_filesystem(volume, reader).map(register_filesystem)
+    }));
 
-        // Create a global reference for the callback object so it persists.
-        let callback_global = match env.new_global_ref(callback_obj) {
-            Ok(g) => g,
-            Err(e) => return Err(format!("JNI Error (new_global_ref): {}", e)),
-        };
+    match result {
+        Ok(Ok(handle)) => handle,
+        Ok(Err((code, msg))) => mount_failure(code, &msg),
+        Err(_) => mount_failure(NoxError::Internal, "Panic in mountFs"),
+    }
+}
 
-        // Create a new CallbackReader
-        let reader = CallbackReader::new(jvm, callback_global, volume_size as u64);
-        // Create a DecryptedReader
-        let decrypted_reader = DecryptedReader::new(reader, volume);
+// Log a failed mount and record it for getLastError, returning its MOUNT_ERR_* code.
+// No exception is thrown, to avoid crashing the flow; the code tells the app what failed.
+fn mount_failure(code: NoxError, msg: &str) -> jlong {
+    let msg = format!("Mount failed: {}", msg);
+    log::error!("{}", msg);
+    record_error(code, &msg);
+    code.return_code() as jlong
+}
 
-        // Try mounting as NTFS.
-        if let Ok(ntfs_instance) = Ntfs::new(&mut decrypted_reader.clone()) {
-            let mut lock = match FILESYSTEMS.write() {
-                Ok(l) => l,
-                Err(e) => e.into_inner(),
-            };
-            
-            let mut handle_lock = match NEXT_FS_HANDLE.lock() {
-                Ok(l) => l,
-                Err(e) => e.into_inner(),
-            };
-            
-            let handle = *handle_lock;
-            *handle_lock += 1;
-            
-            lock.insert(handle, Arc::new(Mutex::new(SupportedFileSystem::Ntfs { fs: Box::new(ntfs_instance), reader: decrypted_reader.clone() })));
-            return Ok(handle);
-        }
-
-        // Try mounting as exFAT.
-        // We use the same reader clone (DecryptedReader is cheap to clone).
-        if let Ok(exfat_instance) = ExFat::open(decrypted_reader) {
-             let mut lock = match FILESYSTEMS.write() {
-                Ok(l) => l,
-                Err(e) => e.into_inner(),
-            };
-            let mut handle_lock = match NEXT_FS_HANDLE.lock() {
-                Ok(l) => l,
-                Err(e) => e.into_inner(),
-            };
+// Look up the volume context to mount.
+fn mount_volume_context(volume_handle: jlong) -> Result<Arc<volume::Volume>, (NoxError, String)> {
+    // Lock the global CONTEXTS map.
+    if let Ok(contexts) = volume::CONTEXTS.lock() {
+        // Look up the handle and clone the volume context.
+        contexts
+            .get(&volume_handle)
+            .cloned()
+            .ok_or_else(|| (NoxError::NotInitialized, "Volume handle invalid or expired".to_string()))
+    } else {
+        Err((NoxError::Internal, "Failed to lock context map".to_string()))
+    }
+}
 
-            let handle = *handle_lock;
-            *handle_lock += 1;
-            
-            // Store the ExFat instance directly (wrapped in Box) to allow on-demand iteration.
-            lock.insert(handle, Arc::new(Mutex::new(SupportedFileSystem::ExFat(Box::new(exfat_instance)))));
-            return Ok(handle);
+// Look up a volume context and wrap the Java callback in a CallbackReader for mounting.
+fn open_callback_reader(
+    env: &mut JNIEnv,
+    volume_handle: jlong,
+    callback_obj: jni::objects::JObject,
+    volume_size: jlong,
+) -> Result<(Arc<volume::Volume>, CallbackReader), (NoxError, String)> {
+    // Retrieve the volume context associated with the handle.
+    let volume = mount_volume_context(volume_handle)?;
+
+    // Get the JavaVM instance safely.
+    let jvm = match env.get_java_vm() {
+        Ok(v) => v,
+        Err(e) => return Err((NoxError::Internal, format!("JNI Error (get_java_vm): {}", e))),
+    };
+
+    // Create a global reference for the callback object so it persists.
+    let callback_global = match env.new_global_ref(callback_obj) {
+        Ok(g) => g,
+        Err(e) => return Err((NoxError::Internal, format!("JNI Error (new_global_ref): {}", e))),
+    };
+
+    // Create a new CallbackReader (resolves and caches the callback method IDs).
+    match CallbackReader::new(env, jvm, callback_global, volume_size as u64) {
+        Ok(reader) => Ok((volume, reader)),
+        Err(e) => {
+            let _ = env.exception_clear();
+            Err((NoxError::Internal, format!("JNI Error (callback method lookup): {}", e)))
         }
+    }
+}
+
+// Detect the file system through the shared (JNI-independent) probe, mapping failures to MOUNT_ERR_* codes.
+fn detect_filesystem(volume: Arc<volume::Volume>, reader: CallbackReader) -> Result<SupportedFileSystem, (NoxError, String)> {
+    match filesystem::mount_fs_from_reader(volume, reader) {
+        Ok(fs) => Ok(fs),
+        Err(e) if e.kind() == std::io::ErrorKind::Unsupported => Err((NoxError::MountFailed, e.to_string())),
+        Err(e) => Err((NoxError::IoError, format!("Failed to read boot sector: {}", e))),
+    }
+}
 
-        Err("Unsupported file system or mount failed".to_string())
+// pollMount result while the background mount is still running (handles start at 1).
+const MOUNT_PENDING: jlong = 0;
+
+// A mount started by mountFsAsync. `result` is None while the worker runs, then the
+// file system handle or the failure until pollMount collects it. Failures are recorded for
+// getLastError on the polling thread, since the worker's own last error is never seen.
+// `cancelled` is shared with the worker and set by cancel_mount.
+struct PendingMount {
+    result: Option<Result<jlong, (NoxError, String)>>,
+    worker: Option<std::thread::JoinHandle<()>>,
+    cancelled: Arc<std::sync::atomic::AtomicBool>,
+}
+
+lazy_static! {
+    // Background mounts by token. Lock order: PENDING_MOUNTS before FILESYSTEMS.
+    static ref PENDING_MOUNTS: Mutex<std::collections::HashMap<i64, PendingMount>> = Mutex::new(std::collections::HashMap::new());
+    static ref NEXT_MOUNT_TOKEN: Mutex<i64> = Mutex::new(1);
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_mountFsAsync.
+// Starts mountFs on a worker thread and returns a token for pollMount/cancelMount,
+// or MOUNT_ERR_GENERIC if the mount could not be started.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_mountFsAsync(
+    mut env: JNIEnv,
+    _class: JClass,
+    volume_handle: jlong,
+    callback_obj: jni::objects::JObject,
+    volume_size: jlong,
+) -> jlong {
+    let result = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        // The worker owns its own CallbackReader (and global reference to the callback).
+        let (volume, reader) = open_callback_reader(&mut env, volume_handle, callback_obj, volume_size)?;
+        let jvm = env
+            .get_java_vm()
+            .map_err(|e| (NoxError::Internal, format!("JNI Error (get_java_vm): {}", e)))?;
+
+        // Hold the map until the worker handle is stored, so pollMount cannot see a half-set entry.
+        let mut pending = PENDING_MOUNTS.lock().unwrap_or_else(|e| e.into_inner());
+        let (token, cancelled) = start_mount_entry(&mut pending);
+
+        let worker = std::thread::Builder::new()
+            .name(format!("mount-{}", token))
+            .spawn(move || {
+                // Stay attached for the whole mount; the callback reads then reuse this
+                // attachment instead of attaching and detaching per read. Dropping the
+                // guard at the end detaches the thread.
+                let _attach = jvm.attach_current_thread();
+                let outcome = match panic::catch_unwind(std::panic::AssertUnwindSafe(|| detect_filesystem(volume, reader))) {
+                    Ok(outcome) => outcome,
+                    Err(_) => Err((NoxError::Internal, "Panic in mount worker".to_string())),
+                };
+                complete_mount(token, &cancelled, outcome, register_filesystem);
+            });
+
+        match worker {
+            Ok(handle) => {
+                if let Some(entry) = pending.get_mut(&token) {
+                    entry.worker = Some(handle);
+                }
+                Ok(token)
+            }
+            Err(e) => {
+                pending.remove(&token);
+                Err((NoxError::Internal, format!("Failed to start mount thread: {}", e)))
+            }
+        }
     }));
 
     match result {
+        Ok(Ok(token)) => token,
+        Ok(Err((code, msg))) => mount_failure(code, &format!("could not start: {}", msg)),
+        Err(_) => mount_failure(NoxError::Internal, "Panic in mountFsAsync"),
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_pollMount.
+// Returns the file system handle once the mount finished, a negative error code if it failed
+// (recorded for getLastError on the calling thread), or MOUNT_PENDING while it is still running.
+// A finished result is returned once; the token is unknown afterwards (InvalidArgument).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_pollMount(
+    _env: JNIEnv,
+    _class: JClass,
+    token: jlong,
+) -> jlong {
+    match panic::catch_unwind(|| poll_mount(token)) {
         Ok(Ok(handle)) => handle,
-        Ok(Err(msg)) => {
-            log::error!("Mount failed: {}", msg);
-            // We do NOT throw exception here to avoid crashing the flow if the app expects -1
-            -1
+        Ok(Err((code, msg))) => mount_failure(code, &msg),
+        Err(_) => mount_failure(NoxError::Internal, "Panic in pollMount"),
+    }
+}
+
+// Add a running mount to `pending` and return its token and cancellation flag.
+fn start_mount_entry(
+    pending: &mut std::collections::HashMap<i64, PendingMount>,
+) -> (i64, Arc<std::sync::atomic::AtomicBool>) {
+    let token = {
+        let mut next = NEXT_MOUNT_TOKEN.lock().unwrap_or_else(|e| e.into_inner());
+        let token = *next;
+        *next += 1;
+        token
+    };
+    let cancelled = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    pending.insert(token, PendingMount { result: None, worker: None, cancelled: cancelled.clone() });
+    (token, cancelled)
+}
+
+// Store the outcome of background mount `token` for pollMount, registering its file system with
+// `register`. The flag is checked under the PENDING_MOUNTS lock that cancel_mount sets it under,
+// so a cancelled mount is never registered; its file system is dropped here instead.
+// Returns whether the outcome was stored.
+fn complete_mount<F>(
+    token: jlong,
+    cancelled: &std::sync::atomic::AtomicBool,
+    outcome: Result<F, (NoxError, String)>,
+    register: impl FnOnce(F) -> jlong,
+) -> bool {
+    let mut pending = PENDING_MOUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = match pending.get_mut(&token) {
+        Some(entry) if !cancelled.load(std::sync::atomic::Ordering::SeqCst) => entry,
+        _ => {
+            log::info!("Mount {} cancelled before completion", token);
+            return false;
         }
-        Err(_) => {
-            log::error!("Panic in mountFs");
-            -1
+    };
+    entry.result = Some(outcome.map(register));
+    true
+}
+
+// Collect the result of a background mount (pollMount without the JNI wrapper).
+fn poll_mount(token: jlong) -> Result<jlong, (NoxError, String)> {
+    let mut pending = PENDING_MOUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    let finished = match pending.get_mut(&token) {
+        Some(entry) => entry.result.take(),
+        None => return Err((NoxError::InvalidArgument, format!("Unknown mount token {}", token))),
+    };
+    match finished {
+        Some(outcome) => {
+            if let Some(worker) = pending.remove(&token).and_then(|entry| entry.worker) {
+                // The worker has stored its result and is only returning.
+                drop(pending);
+                let _ = worker.join();
+            }
+            outcome
         }
+        None => Ok(MOUNT_PENDING),
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_cancelMount.
+// Abandons a background mount. A file system it already mounted is unmounted; one still
+// being parsed is dropped when the worker finishes (the parse itself cannot be interrupted).
+// Returns false if the token is unknown.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_cancelMount(
+    _env: JNIEnv,
+    _class: JClass,
+    token: jlong,
+) -> jni::sys::jboolean {
+    let result = panic::catch_unwind(|| cancel_mount(token) as jni::sys::jboolean);
+    result.unwrap_or(0)
+}
+
+// Abandon background mount `token` (cancelMount without the JNI wrapper). Returns false if
+// the token is unknown.
+fn cancel_mount(token: jlong) -> bool {
+    let mut pending = PENDING_MOUNTS.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = match pending.remove(&token) {
+        Some(entry) => entry,
+        None => return false,
+    };
+    entry.cancelled.store(true, std::sync::atomic::Ordering::SeqCst);
+    if let Some(Ok(handle)) = entry.result {
+        let mut filesystems = FILESYSTEMS.write().unwrap_or_else(|e| e.into_inner());
+        filesystems.remove(&handle);
     }
+    // Dropping the JoinHandle detaches a still running worker.
+    true
 }
 
 
+// Build a com.noxcipher.RustFile[] from `files` (listFiles, listFilesPaged). Returns null on JNI failure.
+fn files_to_java_array(env: &mut JNIEnv, files: &[filesystem::FileInfo]) -> jobjectArray {
+    // Find the com.noxcipher.RustFile class.
+    // Expect success.
+    let file_class = match env.find_class("com/noxcipher/RustFile") {
+         Ok(cls) => cls,
+         Err(e) => {
+             log::error!("Failed to find RustFile class: {}", e);
+             return ptr::null_mut(); // Return null on failure
+         }
+    };
+
+    // Get the constructor ID for RustFile
+    // (String name, boolean isDir, long size, long modifiedTime, long createdTime, int attributes).
+    // Expect success.
+    let init_id = match env.get_method_id(&file_class, "<init>", "(Ljava/lang/String;ZJJJI)V") {
+        Ok(id) => id,
+        Err(e) => {
+             log::error!("Failed to find RustFile constructor: {}", e);
+             return ptr::null_mut();
+        }
+    };
+
+    // Create a new object array of RustFile objects with the size of the files vector.
+    // Initialize with null.
+    // Expect success.
+    let array = match env.new_object_array(
+        files.len() as i32,
+        &file_class,
+        jni::objects::JObject::null(),
+    ) {
+        Ok(arr) => arr,
+        Err(e) => {
+             log::error!("Failed to create RustFile array: {}", e);
+             return ptr::null_mut();
+        }
+    };
+
+    // Iterate over the files and populate the array.
+    for (i, f) in files.iter().enumerate() {
+        // Create a Java string for the file name.
+        let name_jstr = match env.new_string(&f.name) {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        
+        // Create a new RustFile object using the constructor.
+        let obj = unsafe {
+            env.new_object_unchecked(
+                &file_class,
+                init_id,
+                &[
+                    JValue::Object(&name_jstr).as_jni(),   // name
+                    JValue::Bool(f.is_dir as u8).as_jni(), // isDir
+                    JValue::Long(f.size as i64).as_jni(),  // size
+                    // Unknown times are passed as -1.
+                    JValue::Long(f.modified_time.map_or(-1, |t| t as i64)).as_jni(), // modifiedTime
+                    JValue::Long(f.created_time.map_or(-1, |t| t as i64)).as_jni(),  // createdTime
+                    JValue::Int(f.attributes as i32).as_jni(),                         // attributes
+                ],
+            )
+        };
+        
+        if let Ok(obj_ref) = obj {
+             // Set the array element at index i.
+             if let Err(e) = env.set_object_array_element(&array, i as i32, &obj_ref) {
+                 log::warn!("Failed to set array element: {:?}", e);
+             }
+             // Delete local ref to prevent overflow in large loops.
+             env.delete_local_ref(obj_ref).unwrap_or_default();
+        }
+        
+        // Delete the string ref.
+        // new_string returned a JString which is a JObject.
+        // But we actually need to be careful with types.
+        // name_jstr is a JString.
+         env.delete_local_ref(name_jstr).unwrap_or_default();
+    }
+
+    // Return the raw pointer to the array.
+    array.into_raw()
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_listFiles.
 // It lists files in a directory of the mounted file system.
 #[no_mangle]
@@ -1022,93 +1951,108 @@ pub extern "system" fn Java_com_noxcipher_RustNative_listFiles(
 
         };
 
-        // Find the com.noxcipher.RustFile class.
-        // Expect success.
-        let file_class = match env.find_class("com/noxcipher/RustFile") {
-             Ok(cls) => cls,
-             Err(e) => {
-                 log::error!("Failed to find RustFile class: {}", e);
-                 return ptr::null_mut(); // Return null on failure
-             }
-        };
+        files_to_java_array(&mut env, &files)
+    }));
 
-        // Get the constructor ID for RustFile (String name, boolean isDir, long size).
-        // Expect success.
-        let init_id = match env.get_method_id(&file_class, "<init>", "(Ljava/lang/String;ZJ)V") {
-            Ok(id) => id,
-            Err(e) => {
-                 log::error!("Failed to find RustFile constructor: {}", e);
-                 return ptr::null_mut();
+    match result {
+        Ok(ptr) => ptr,
+        Err(_) => {
+            log::error!("Panic in listFiles");
+            record_error(NoxError::Internal, "Panic in listFiles");
+            std::ptr::null_mut()
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_listFilesPaged.
+// Lists at most `limit` entries of a directory, starting at entry `offset`, as a com.noxcipher.RustFilePage
+// that also carries the directory's total entry count. Like mkdir and dirSize, every failure throws
+// (and is recorded for getLastError) and returns null.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_listFilesPaged(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The file system handle.
+    fs_handle: jlong,
+    // The path to list as a Java string.
+    path_obj: JString,
+    // Index of the first entry of the page.
+    offset: jlong,
+    // Maximum number of entries on the page.
+    limit: jni::sys::jint,
+) -> jni::sys::jobject {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let (Ok(offset), Ok(limit)) = (usize::try_from(offset), usize::try_from(limit)) else {
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Offset and limit must not be negative");
+            return ptr::null_mut();
+        };
+        let path: String = match env.get_string(&path_obj) {
+            Ok(s) => s.into(),
+            Err(_) => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid path");
+                return ptr::null_mut();
             }
         };
 
-        // Create a new object array of RustFile objects with the size of the files vector.
-        // Initialize with null.
-        // Expect success.
-        let array = match env.new_object_array(
-            files.len() as i32,
-            &file_class,
-            jni::objects::JObject::null(),
-        ) {
-            Ok(arr) => arr,
+        // Drop the global lock before listing; the listing calls back into Java.
+        let fs_arc_opt = {
+            let lock = FILESYSTEMS.read().unwrap_or_else(|e| e.into_inner());
+            lock.get(&fs_handle).cloned()
+        };
+        let Some(fs_arc) = fs_arc_opt else {
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+            return ptr::null_mut();
+        };
+        let page = fs_arc.lock().unwrap_or_else(|e| e.into_inner()).list_files_paged(&path, offset, limit);
+        let (files, total) = match page {
+            Ok(page) => page,
             Err(e) => {
-                 log::error!("Failed to create RustFile array: {}", e);
-                 return ptr::null_mut();
+                throw_error(&mut env, "java/io/IOException", format!("List files failed: {}", e));
+                return ptr::null_mut();
             }
         };
 
-        // Iterate over the files and populate the array.
-        for (i, f) in files.iter().enumerate() {
-            // Create a Java string for the file name.
-            let name_jstr = match env.new_string(&f.name) {
-                Ok(s) => s,
-                Err(_) => continue,
-            };
-            
-            // Create a new RustFile object using the constructor.
-            let obj = unsafe {
-                env.new_object_unchecked(
-                    &file_class,
-                    init_id,
-                    &[
-                        JValue::Object(&name_jstr).as_jni(),   // name
-                        JValue::Bool(f.is_dir as u8).as_jni(), // isDir
-                        JValue::Long(f.size as i64).as_jni(),  // size
-                    ],
-                )
-            };
-            
-            if let Ok(obj_ref) = obj {
-                 // Set the array element at index i.
-                 if let Err(e) = env.set_object_array_element(&array, i as i32, &obj_ref) {
-                     log::warn!("Failed to set array element: {:?}", e);
-                 }
-                 // Delete local ref to prevent overflow in large loops.
-                 env.delete_local_ref(obj_ref).unwrap_or_default();
+        let files_array = files_to_java_array(&mut env, &files);
+        if files_array.is_null() {
+            throw_unless_pending(&mut env, "java/lang/RuntimeException", "Failed to build RustFile array");
+            return ptr::null_mut();
+        }
+        let files_array = unsafe { JObject::from_raw(files_array) };
+        match env.new_object(
+            "com/noxcipher/RustFilePage",
+            "([Lcom/noxcipher/RustFile;J)V",
+            &[JValue::Object(&files_array), JValue::Long(total as i64)],
+        ) {
+            Ok(page) => page.into_raw(),
+            Err(e) => {
+                log::error!("Failed to create RustFilePage: {}", e);
+                throw_unless_pending(&mut env, "java/lang/RuntimeException", "Failed to create RustFilePage");
+                ptr::null_mut()
             }
-            
-            // Delete the string ref.
-            // new_string returned a JString which is a JObject.
-            // But we actually need to be careful with types.
-            // name_jstr is a JString.
-             env.delete_local_ref(name_jstr).unwrap_or_default();
         }
-
-        // Return the raw pointer to the array.
-        array.into_raw()
     }));
 
     match result {
         Ok(ptr) => ptr,
         Err(_) => {
-            log::error!("Panic in listFiles");
-            std::ptr::null_mut()
+            log::error!("Panic in listFilesPaged");
+            throw_unless_pending(&mut env, "java/lang/RuntimeException", "Panic in listFilesPaged");
+            ptr::null_mut()
         }
     }
 }
 
+// Report a failed file read: -1, with the reason recorded for getLastError.
+// A read at or past the end of a file succeeds with 0 bytes, so EOF is never reported as -1.
+fn read_failed(function: &str, message: &str) -> jlong {
+    log::warn!("{} failed: {}", function, message);
+    set_last_error(&format!("{} failed: {}", function, message));
+    -1
+}
+
 // Define a JNI function named Java_com_noxcipher_RustNative_readFile.
 // It reads content from a file in the mounted file system.
+// Returns the bytes read (0 at or past the end of the file) or -1 on error.
 #[no_mangle]
 #[allow(clippy::not_unsafe_ptr_arg_deref)]
 pub extern "system" fn Java_com_noxcipher_RustNative_readFile(
@@ -1131,6 +2075,9 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFile(
             Ok(s) => s.into(),
             Err(_) => return -1,
         };
+        if offset < 0 {
+            return read_failed("readFile", "Negative offset");
+        }
 
         // Access the file system.
         if let Ok(lock) = FILESYSTEMS.read() {
@@ -1163,7 +2110,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFile(
                             }
                         }
                         // If reading fails, return -1.
-                        Err(_) => -1,
+                        Err(e) => read_failed("readFile", &e.to_string()),
                     };
                     return res;
                 }
@@ -1176,6 +2123,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFile(
         Ok(val) => val,
         Err(_) => {
             log::error!("Panic in readFile");
+            record_error(NoxError::Internal, "Panic in readFile");
             -1
         }
     }
@@ -1200,6 +2148,10 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFileArray(
             Err(_) => return -1,
         };
 
+        if offset < 0 || length < 0 {
+            return read_failed("readFileArray", "Negative offset or length");
+        }
+
         if let Ok(lock) = FILESYSTEMS.read() {
             if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
                 drop(lock);
@@ -1217,7 +2169,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFileArray(
                                 bytes_read as jlong
                             }
                         }
-                        Err(_) => -1,
+                        Err(e) => read_failed("readFileArray", &e.to_string()),
                     };
                     return res;
                 }
@@ -1230,6 +2182,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFileArray(
         Ok(val) => val,
         Err(_) => {
             log::error!("Panic in readFileArray");
+            record_error(NoxError::Internal, "Panic in readFileArray");
             -1
         }
     }
@@ -1272,6 +2225,9 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFileDirect(
         if position < 0 || length < 0 || (position as usize + length as usize) > capacity {
              return -1;
         }
+        if offset < 0 {
+            return read_failed("readFileDirect", "Negative offset");
+        }
 
         // Access filesystem
         if let Ok(lock) = FILESYSTEMS.read() {
@@ -1284,7 +2240,7 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFileDirect(
                     
                     match fs.read_file(&path, offset as u64, target_slice) {
                         Ok(bytes_read) => return bytes_read as jlong,
-                        Err(_) => return -1,
+                        Err(e) => return read_failed("readFileDirect", &e.to_string()),
                     }
                 }
             }
@@ -1298,70 +2254,794 @@ pub extern "system" fn Java_com_noxcipher_RustNative_readFileDirect(
     }
 }
 
-
-
-
-// Define a JNI function named Java_com_noxcipher_RustNative_cleanup.
-// It clears all volume contexts and filesystems, zeroing keys.
+// Define a JNI function named Java_com_noxcipher_RustNative_writeFile.
+// It writes content into a file in the mounted file system (see SupportedFileSystem::write_file for supported layouts).
 #[no_mangle]
-pub extern "system" fn Java_com_noxcipher_RustNative_cleanup(
-    _env: JNIEnv,
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_writeFile(
+    mut env: JNIEnv,
     _class: JClass,
-) {
-    let _ = panic::catch_unwind(|| {
-        log::info!("Executing cleanup");
-        
-        // Clear filesystems
-        if let Ok(mut lock) = FILESYSTEMS.write() {
-            lock.clear();
-        } else if let Ok(mut lock) = FILESYSTEMS.write() {
-             // Retry lock for poisoned? Handled by unwrap_or_else usually but here just try
-             // If heavily poisoned, we might leak, but this is best effort cleanup.
-             // Force clear if possible? 
-             *lock = std::collections::HashMap::new();
-        }
-        
-        // Clear volumes
-        if let Ok(mut lock) = volume::CONTEXTS.lock() {
-            lock.clear();
-        }
-    });
+    // The file system handle.
+    fs_handle: jlong,
+    // The path to the file as a Java string.
+    path_obj: jni::objects::JString,
+    // The offset to start writing at.
+    offset: jlong,
+    // The data to write.
+    data: jbyteArray,
+) -> jlong {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path: String = match env.get_string(&path_obj) {
+            Ok(s) => s.into(),
+            Err(_) => return -1,
+        };
+
+        if offset < 0 {
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative offset");
+            return -1;
+        }
+
+        // Copy the Java data into a zeroizing Rust buffer.
+        let data_obj = unsafe { JByteArray::from_raw(data) };
+        let buf = match env.convert_byte_array(&data_obj) {
+            Ok(b) => Zeroizing::new(b),
+            Err(_) => return -1,
+        };
+
+        if let Ok(lock) = FILESYSTEMS.read() {
+            if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
+                drop(lock);
+                if let Ok(mut fs) = fs_arc.lock() {
+                    return match fs.write_file(&path, offset as u64, &buf) {
+                        Ok(written) => written as jlong,
+                        Err(e) => {
+                            throw_error(&mut env, "java/io/IOException", format!("Write failed: {}", e));
+                            -1
+                        }
+                    };
+                }
+            }
+        }
+        throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+        -1
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in writeFile");
+            record_error(NoxError::Internal, "Panic in writeFile");
+            -1
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_mkdir.
+// It creates a directory (FAT32 only).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_mkdir(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The file system handle.
+    fs_handle: jlong,
+    // The path as a Java string.
+    path_obj: jni::objects::JString,
+) -> jni::sys::jint {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path: String = match env.get_string(&path_obj) {
+            Ok(s) => s.into(),
+            Err(_) => return -1,
+        };
+
+        if let Ok(lock) = FILESYSTEMS.read() {
+            if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
+                drop(lock);
+                if let Ok(mut fs) = fs_arc.lock() {
+                    return match fs.create_dir(&path) {
+                        Ok(()) => 0,
+                        Err(e) => {
+                            throw_error(&mut env, "java/io/IOException", format!("Mkdir failed: {}", e));
+                            -1
+                        }
+                    };
+                }
+            }
+        }
+        throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+        -1
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in mkdir");
+            record_error(NoxError::Internal, "Panic in mkdir");
+            -1
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_dirSize.
+// It returns the total size of all files below a directory (walks the whole subtree).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_dirSize(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The file system handle.
+    fs_handle: jlong,
+    // The directory path as a Java string.
+    path_obj: jni::objects::JString,
+) -> jlong {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path: String = match env.get_string(&path_obj) {
+            Ok(s) => s.into(),
+            Err(_) => return -1,
+        };
+
+        if let Ok(lock) = FILESYSTEMS.read() {
+            if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
+                drop(lock);
+                if let Ok(mut fs) = fs_arc.lock() {
+                    return match fs.dir_size(&path) {
+                        Ok(size) => size.min(i64::MAX as u64) as jlong,
+                        Err(e) => {
+                            throw_error(&mut env, "java/io/IOException", format!("Directory size failed: {}", e));
+                            -1
+                        }
+                    };
+                }
+            }
+        }
+        throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+        -1
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in dirSize");
+            record_error(NoxError::Internal, "Panic in dirSize");
+            -1
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getFreeSpace.
+// Returns [free bytes, total bytes] of a mounted file system, or null on error.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getFreeSpace(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The file system handle.
+    fs_handle: jlong,
+) -> jni::sys::jlongArray {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if let Ok(lock) = FILESYSTEMS.read() {
+            if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
+                drop(lock);
+                if let Ok(mut fs) = fs_arc.lock() {
+                    return match fs.free_space() {
+                        Ok((free, total)) => handles_to_jlong_array(
+                            &mut env,
+                            &[free.min(i64::MAX as u64) as i64, total.min(i64::MAX as u64) as i64],
+                        ),
+                        Err(e) => {
+                            throw_error(&mut env, "java/io/IOException", format!("Free space query failed: {}", e));
+                            ptr::null_mut()
+                        }
+                    };
+                }
+            }
+        }
+        throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+        ptr::null_mut()
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in getFreeSpace");
+            record_error(NoxError::Internal, "Panic in getFreeSpace");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_syncFs.
+// It commits pending file system changes and flushes the Java callback (see SupportedFileSystem::sync).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_syncFs(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The file system handle.
+    fs_handle: jlong,
+) -> jni::sys::jint {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if let Ok(lock) = FILESYSTEMS.read() {
+            if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
+                drop(lock);
+                if let Ok(mut fs) = fs_arc.lock() {
+                    return match fs.sync() {
+                        Ok(()) => 0,
+                        Err(e) => {
+                            throw_error(&mut env, "java/io/IOException", format!("Sync failed: {}", e));
+                            -1
+                        }
+                    };
+                }
+            }
+        }
+        throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+        -1
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in syncFs");
+            record_error(NoxError::Internal, "Panic in syncFs");
+            -1
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_deleteFile.
+// It deletes a file or an empty directory (FAT32), or a simple file (NTFS).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_deleteFile(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The file system handle.
+    fs_handle: jlong,
+    // The path as a Java string.
+    path_obj: jni::objects::JString,
+) -> jni::sys::jint {
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path: String = match env.get_string(&path_obj) {
+            Ok(s) => s.into(),
+            Err(_) => return -1,
+        };
+
+        if let Ok(lock) = FILESYSTEMS.read() {
+            if let Some(fs_arc) = lock.get(&fs_handle).cloned() {
+                drop(lock);
+                if let Ok(mut fs) = fs_arc.lock() {
+                    return match fs.delete(&path) {
+                        Ok(()) => 0,
+                        Err(e) => {
+                            throw_error(&mut env, "java/io/IOException", format!("Delete failed: {}", e));
+                            -1
+                        }
+                    };
+                }
+            }
+        }
+        throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid file system handle");
+        -1
+    }));
+
+    match result {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in deleteFile");
+            record_error(NoxError::Internal, "Panic in deleteFile");
+            -1
+        }
+    }
+}
+
+
+
+
+// Define a JNI function named Java_com_noxcipher_RustNative_cleanup.
+// It clears all volume contexts and filesystems, zeroing keys.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_cleanup(
+    _env: JNIEnv,
+    _class: JClass,
+) {
+    let _ = panic::catch_unwind(|| {
+        log::info!("Executing cleanup");
+
+        // File systems first: their readers hold references to the volumes.
+        unmount_all_filesystems();
+        volume::dismount_all();
+    });
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_closeAllFilesystems.
+// It drops every mounted file system but keeps the volume contexts open for raw decrypt/encrypt.
+// Returns how many file systems were closed.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_closeAllFilesystems(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jint {
+    panic::catch_unwind(|| unmount_all_filesystems() as jni::sys::jint).unwrap_or(-1)
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_closeAllVolumes.
+// It drops every volume context but leaves FILESYSTEMS alone. A mounted file system keeps its
+// own reference to its volume, so that volume's keys are only zeroized once the file system is closed.
+// Returns how many volumes were closed.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_closeAllVolumes(
+    _env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jint {
+    panic::catch_unwind(|| volume::dismount_all() as jni::sys::jint).unwrap_or(-1)
+}
+
+// Drop every mounted file system. Returns how many were unmounted.
+// Only the FILESYSTEMS lock is taken (and released before dropping), never together with the
+// CONTEXTS lock, so this cannot deadlock against volume::dismount_all.
+fn unmount_all_filesystems() -> usize {
+    let drained: Vec<_> = {
+        // Use unwrap_or_else to handle poisoned lock gracefully
+        let mut lock = FILESYSTEMS.write().unwrap_or_else(|e| e.into_inner());
+        lock.drain().collect()
+    };
+    let count = drained.len();
+    drop(drained);
+    log::info!("Unmounted {} file system(s)", count);
+    count
+}
+
+// Helper to build a Java String[] from Rust strings. Returns null on failure.
+fn to_java_string_array(env: &mut JNIEnv, values: &[String]) -> jobjectArray {
+    let array = match env.new_object_array(values.len() as i32, "java/lang/String", JObject::null()) {
+        Ok(arr) => arr,
+        Err(e) => {
+            log::error!("Failed to create String array: {}", e);
+            return ptr::null_mut();
+        }
+    };
+
+    for (i, value) in values.iter().enumerate() {
+        let jstr = match env.new_string(value) {
+            Ok(s) => s,
+            Err(_) => return ptr::null_mut(),
+        };
+        if env.set_object_array_element(&array, i as i32, &jstr).is_err() {
+            return ptr::null_mut();
+        }
+        let _ = env.delete_local_ref(jstr);
+    }
+
+    array.into_raw()
+}
+
+// Helper to read a Java byte[][] of keyfile contents. A null array yields an empty list.
+fn read_keyfiles(env: &mut JNIEnv, keyfiles: jobjectArray) -> Result<Vec<Zeroizing<Vec<u8>>>, String> {
+    if keyfiles.is_null() {
+        return Ok(Vec::new());
+    }
+
+    let array = unsafe { jni::objects::JObjectArray::from_raw(keyfiles) };
+    let len = env.get_array_length(&array).map_err(|e| e.to_string())?;
+    let mut result = Vec::with_capacity(len as usize);
+
+    for i in 0..len {
+        let element = env.get_object_array_element(&array, i).map_err(|e| e.to_string())?;
+        if element.is_null() {
+            return Err(format!("Keyfile {} is null", i));
+        }
+        let bytes_obj = JByteArray::from(element);
+        let bytes = env.convert_byte_array(&bytes_obj).map_err(|e| e.to_string())?;
+        let _ = env.delete_local_ref(bytes_obj);
+        result.push(Zeroizing::new(bytes));
+    }
+
+    Ok(result)
+}
+
+// Helper to map integer to CipherType (ids from volume::CIPHER_IDS).
+fn int_to_cipher_type(val: i32) -> Option<volume::CipherType> {
+    usize::try_from(val).ok().and_then(|id| volume::CIPHER_IDS.get(id).copied())
+}
+
+// Inverse of int_to_cipher_type. Exhaustive so a new cipher cannot ship without an id; the arms
+// must follow volume::CIPHER_IDS.
+fn cipher_type_to_int(cipher: volume::CipherType) -> i32 {
+    match cipher {
+        volume::CipherType::Aes => 0,
+        volume::CipherType::Serpent => 1,
+        volume::CipherType::Twofish => 2,
+        volume::CipherType::AesTwofish => 3,
+        volume::CipherType::AesTwofishSerpent => 4,
+        volume::CipherType::SerpentAes => 5,
+        volume::CipherType::TwofishSerpent => 6,
+        volume::CipherType::SerpentTwofishAes => 7,
+        volume::CipherType::Camellia => 8,
+        volume::CipherType::Kuznyechik => 9,
+        volume::CipherType::CamelliaKuznyechik => 10,
+        volume::CipherType::CamelliaSerpent => 11,
+        volume::CipherType::KuznyechikAes => 12,
+        volume::CipherType::KuznyechikSerpentCamellia => 13,
+        volume::CipherType::KuznyechikTwofish => 14,
+    }
+}
+
+// Helper to map integer to PrfAlgorithm (ids from volume::PRF_IDS).
+fn int_to_prf(val: i32) -> Option<volume::PrfAlgorithm> {
+    usize::try_from(val).ok().and_then(|id| volume::PRF_IDS.get(id).copied())
+}
+
+// Inverse of int_to_prf. Exhaustive like cipher_type_to_int; the arms must follow volume::PRF_IDS.
+fn prf_to_int(prf: volume::PrfAlgorithm) -> i32 {
+    match prf {
+        volume::PrfAlgorithm::Sha512 => 0,
+        volume::PrfAlgorithm::Sha256 => 1,
+        volume::PrfAlgorithm::Whirlpool => 2,
+        volume::PrfAlgorithm::Ripemd160 => 3,
+        volume::PrfAlgorithm::Streebog => 4,
+        volume::PrfAlgorithm::Blake2s => 5,
+        volume::PrfAlgorithm::Sha1 => 6,
+        volume::PrfAlgorithm::Argon2id => 7,
+        volume::PrfAlgorithm::Sha3_256 => 8,
+        volume::PrfAlgorithm::Sha3_512 => 9,
+        volume::PrfAlgorithm::Blake2b => 10,
+    }
+}
+
+// All ciphers in id order (index == id as used by formatVolume).
+fn supported_ciphers() -> Vec<volume::CipherType> {
+    volume::CIPHER_IDS.to_vec()
+}
+
+// All PRFs in id order (index == id as used by formatVolume and changePassword).
+fn supported_prfs() -> Vec<volume::PrfAlgorithm> {
+    volume::PRF_IDS.to_vec()
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getSupportedCiphers.
+// Returns the cipher names; the index of each name is its formatVolume id.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getSupportedCiphers(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jobjectArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let names: Vec<String> = supported_ciphers().iter().map(|c| c.name().to_string()).collect();
+        to_java_string_array(&mut env, &names)
+    }));
+    res.unwrap_or(ptr::null_mut())
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getCipherKeySizes.
+// Returns the master key size in bytes per cipher, indexed like getSupportedCiphers.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getCipherKeySizes(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jni::sys::jintArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let sizes: Vec<i32> = supported_ciphers().iter().map(|c| c.key_size() as i32).collect();
+        let array = match env.new_int_array(sizes.len() as i32) {
+            Ok(a) => a,
+            Err(_) => return ptr::null_mut(),
+        };
+        if env.set_int_array_region(&array, 0, &sizes).is_err() {
+            return ptr::null_mut();
+        }
+        array.into_raw()
+    }));
+    res.unwrap_or(ptr::null_mut())
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_getSupportedPrfs.
+// Returns the PRF names; the index of each name is its formatVolume/changePassword id.
+// Argon2id and BLAKE2b-512 are listed even when the noxcipher-kdf feature is off (formatting then fails).
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_getSupportedPrfs(
+    mut env: JNIEnv,
+    _class: JClass,
+) -> jobjectArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let names: Vec<String> = supported_prfs().iter().map(|p| p.name().to_string()).collect();
+        to_java_string_array(&mut env, &names)
+    }));
+    res.unwrap_or(ptr::null_mut())
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_benchmarkPrf.
+// Returns the estimated PBKDF2 iterations of a PRF (getSupportedPrfs id) taking about targetMs
+// on this device, or -1 for an unknown id, Argon2id or a non-positive target.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_benchmarkPrf(
+    _env: JNIEnv,
+    _class: JClass,
+    prf_id: jni::sys::jint,
+    target_ms: jni::sys::jint,
+) -> jni::sys::jint {
+    let res = panic::catch_unwind(|| {
+        let prf = match int_to_prf(prf_id) {
+            Some(p) => p,
+            None => {
+                set_last_error("benchmarkPrf: unknown PRF id");
+                return -1;
+            }
+        };
+        if target_ms <= 0 {
+            set_last_error("benchmarkPrf: target must be positive");
+            return -1;
+        }
+        match volume::benchmark_prf(prf, target_ms as u32) {
+            // Saturate: counts past i32::MAX are far beyond any usable PIM anyway.
+            Ok(iterations) => iterations.min(i32::MAX as u32) as jni::sys::jint,
+            Err(e) => {
+                set_last_error(&format!("benchmarkPrf failed: {}", e));
+                -1
+            }
+        }
+    });
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in benchmarkPrf");
+            record_error(NoxError::Internal, "Panic in benchmarkPrf");
+            -1
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_pimForIterations.
+// Returns the PIM giving at least `iterations` PBKDF2 rounds (e.g. from benchmarkPrf), to pass
+// to formatVolume and to enter on mount, or -1 below MIN_CUSTOM_ITERATIONS or past MAX_PIM.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_pimForIterations(
+    _env: JNIEnv,
+    _class: JClass,
+    iterations: jni::sys::jint,
+) -> jni::sys::jint {
+    let res = panic::catch_unwind(|| {
+        let Ok(iterations) = u32::try_from(iterations) else {
+            record_error(NoxError::InvalidArgument, "pimForIterations: iterations must not be negative");
+            return -1;
+        };
+        match volume::pim_for_iterations(iterations) {
+            Ok(pim) => pim,
+            Err(e) => {
+                record_error(NoxError::InvalidArgument, &format!("pimForIterations failed: {}", e));
+                -1
+            }
+        }
+    });
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in pimForIterations");
+            record_error(NoxError::Internal, "Panic in pimForIterations");
+            -1
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_probe.
+// It checks a password against a header without mounting and returns [cipher_id, prf_id],
+// using the same ids as formatVolume. Returns null if no header matches.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_probe(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The password as a byte array.
+    password: jbyteArray,
+    // The header data as a byte array.
+    header: jbyteArray,
+    // The PIM value.
+    pim: jni::sys::jint,
+    // The backup header data as a byte array (optional).
+    backup_header: jbyteArray,
+) -> jni::sys::jintArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if password.is_null() || header.is_null() {
+            throw_error(&mut env, "java/lang/IllegalArgumentException", "Password and header cannot be null");
+            return ptr::null_mut();
+        }
+
+        let password_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(password) }) {
+            Ok(b) => Zeroizing::new(b),
+            Err(_) => return ptr::null_mut(),
+        };
+        let header_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(header) }) {
+            Ok(b) => b,
+            Err(_) => return ptr::null_mut(),
+        };
+        let backup_bytes = if backup_header.is_null() {
+            None
+        } else {
+            match env.convert_byte_array(unsafe { &JByteArray::from_raw(backup_header) }) {
+                Ok(b) => Some(b),
+                Err(_) => return ptr::null_mut(),
+            }
+        };
+
+        match volume::probe_header(&password_bytes, &header_bytes, pim, backup_bytes.as_deref()) {
+            Ok((cipher, prf)) => {
+                let ids = [cipher_type_to_int(cipher), prf_to_int(prf)];
+                let array = match env.new_int_array(ids.len() as i32) {
+                    Ok(a) => a,
+                    Err(_) => return ptr::null_mut(),
+                };
+                if env.set_int_array_region(&array, 0, &ids).is_err() {
+                    return ptr::null_mut();
+                }
+                array.into_raw()
+            }
+            Err(e) => {
+                log::info!("Probe did not match: {}", e);
+                ptr::null_mut()
+            }
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in probe");
+            record_error(NoxError::Internal, "Panic in probe");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_looksEncrypted.
+// Heuristic, password-less check that a buffer could be an encrypted volume header
+// (see header::looks_like_veracrypt). Returns false for null or unreadable input.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_looksEncrypted(
+    mut env: JNIEnv,
+    _class: JClass,
+    // The first bytes of the volume (at least 512).
+    header: jbyteArray,
+) -> jni::sys::jboolean {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        if header.is_null() {
+            return 0;
+        }
+        match env.convert_byte_array(unsafe { &JByteArray::from_raw(header) }) {
+            Ok(bytes) => header::looks_like_veracrypt(&bytes) as jni::sys::jboolean,
+            Err(_) => 0,
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in looksEncrypted");
+            record_error(NoxError::Internal, "Panic in looksEncrypted");
+            0
+        }
+    }
 }
 
-// Helper to map integer to CipherType
-fn int_to_cipher_type(val: i32) -> Option<volume::CipherType> {
-    match val {
-        0 => Some(volume::CipherType::Aes),
-        1 => Some(volume::CipherType::Serpent),
-        2 => Some(volume::CipherType::Twofish),
-        3 => Some(volume::CipherType::AesTwofish),
-        4 => Some(volume::CipherType::AesTwofishSerpent),
-        5 => Some(volume::CipherType::SerpentAes),
-        6 => Some(volume::CipherType::TwofishSerpent),
-        7 => Some(volume::CipherType::SerpentTwofishAes),
-        8 => Some(volume::CipherType::Camellia),
-        9 => Some(volume::CipherType::Kuznyechik),
-        10 => Some(volume::CipherType::CamelliaKuznyechik),
-        11 => Some(volume::CipherType::CamelliaSerpent),
-        12 => Some(volume::CipherType::KuznyechikAes),
-        13 => Some(volume::CipherType::KuznyechikSerpentCamellia),
-        14 => Some(volume::CipherType::KuznyechikTwofish),
-        _ => None,
+// verifyVolume results other than a bad offset (which is always >= 0).
+const VERIFY_CLEAN: jlong = -1;
+// The calling Java thread was interrupted.
+const VERIFY_CANCELLED: jlong = -2;
+// Invalid handle, I/O or JNI error; an exception is thrown as well.
+const VERIFY_ERROR: jlong = -3;
+
+// Define a JNI function named Java_com_noxcipher_RustNative_verifyVolume.
+// Reads every sector of the volume through the decrypt path and checks the file system boot region.
+// Returns the first bad offset (relative to the data area) or one of the VERIFY_* codes.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_verifyVolume(
+    mut env: JNIEnv,
+    _class: JClass,
+    volume_handle: jlong,
+    callback_obj: JObject,
+    volume_size: jlong,
+) -> jlong {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let jvm = match env.get_java_vm() {
+            Ok(v) => v,
+            Err(e) => {
+                throw_error(&mut env, "java/io/IOException", format!("JNI Error (get_java_vm): {}", e));
+                return VERIFY_ERROR;
+            }
+        };
+        let callback_global = match env.new_global_ref(callback_obj) {
+            Ok(g) => g,
+            Err(e) => {
+                throw_error(&mut env, "java/io/IOException", format!("JNI Error (new_global_ref): {}", e));
+                return VERIFY_ERROR;
+            }
+        };
+        let mut reader = match CallbackReader::new(&mut env, jvm, callback_global, volume_size.max(0) as u64) {
+            Ok(r) => r,
+            Err(e) => {
+                let _ = env.exception_clear();
+                throw_error(&mut env, "java/io/IOException", format!("JNI Error (callback method lookup): {}", e));
+                return VERIFY_ERROR;
+            }
+        };
+
+        // Cancel when the calling thread is interrupted.
+        let env_cell = std::cell::RefCell::new(&mut env);
+        let should_continue = || {
+            let mut env = env_cell.borrow_mut();
+            let interrupted = env
+                .call_static_method("java/lang/Thread", "currentThread", "()Ljava/lang/Thread;", &[])
+                .and_then(|t| t.l())
+                .and_then(|thread| env.call_method(&thread, "isInterrupted", "()Z", &[]))
+                .and_then(|v| v.z());
+            !matches!(interrupted, Ok(true))
+        };
+
+        let res = volume::verify_volume(volume_handle, &mut reader, should_continue);
+        match res {
+            Ok(report) if report.cancelled => VERIFY_CANCELLED,
+            Ok(report) => match report.first_bad_offset {
+                Some(offset) => {
+                    log::warn!("Verify found bad sector at offset {}", offset);
+                    offset as jlong
+                }
+                None => VERIFY_CLEAN,
+            },
+            Err(e) => {
+                log::error!("Verify failed: {}", e);
+                throw_error(&mut env, "java/io/IOException", format!("Verify failed: {}", e));
+                VERIFY_ERROR
+            }
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in verifyVolume");
+            record_error(NoxError::Internal, "Panic in verifyVolume");
+            VERIFY_ERROR
+        }
     }
 }
 
-// Helper to map integer to PrfAlgorithm
-fn int_to_prf(val: i32) -> Option<volume::PrfAlgorithm> {
-    match val {
-        0 => Some(volume::PrfAlgorithm::Sha512),
-        1 => Some(volume::PrfAlgorithm::Sha256),
-        2 => Some(volume::PrfAlgorithm::Whirlpool),
-        3 => Some(volume::PrfAlgorithm::Ripemd160),
-        4 => Some(volume::PrfAlgorithm::Streebog),
-        5 => Some(volume::PrfAlgorithm::Blake2s),
-        6 => Some(volume::PrfAlgorithm::Sha1),
-        7 => Some(volume::PrfAlgorithm::Argon2id),
-        _ => None,
+// Largest buffer the benchmark allocates, in MiB.
+const BENCHMARK_MAX_MB: jni::sys::jint = 256;
+
+// Define a JNI function named Java_com_noxcipher_RustNative_benchmark.
+// Encrypts and decrypts a bufferSizeMb MiB buffer with a throwaway key of the given cipher id
+// (as in formatVolume) and returns the throughput in MiB/s, or -1.0 on error.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_benchmark(
+    mut env: JNIEnv,
+    _class: JClass,
+    cipher_id: jni::sys::jint,
+    buffer_size_mb: jni::sys::jint,
+) -> jni::sys::jdouble {
+    let cipher = match int_to_cipher_type(cipher_id) {
+        Some(c) => c,
+        None => {
+            throw_error(&mut env, "java/lang/IllegalArgumentException", format!("Invalid cipher id: {}", cipher_id));
+            return -1.0;
+        }
+    };
+    if !(1..=BENCHMARK_MAX_MB).contains(&buffer_size_mb) {
+        throw_error(&mut env, 
+            "java/lang/IllegalArgumentException",
+            format!("Buffer size must be 1..={} MiB, got {}", BENCHMARK_MAX_MB, buffer_size_mb),
+        );
+        return -1.0;
+    }
+
+    let res = panic::catch_unwind(|| volume::benchmark_cipher(cipher, buffer_size_mb as usize * 1024 * 1024));
+
+    match res {
+        Ok(Ok(mib_per_sec)) => {
+            log::info!("Benchmark {}: {:.1} MiB/s", cipher.name(), mib_per_sec);
+            mib_per_sec
+        }
+        Ok(Err(e)) => {
+            throw_error(&mut env, "java/lang/RuntimeException", format!("Benchmark failed: {}", e));
+            -1.0
+        }
+        Err(_) => {
+            throw_error(&mut env, "java/lang/RuntimeException", "Panic in benchmark");
+            -1.0
+        }
     }
 }
 
@@ -1378,46 +3058,69 @@ pub extern "system" fn Java_com_noxcipher_RustNative_formatVolume(
     cipher_type_int: jni::sys::jint,
     prf_int: jni::sys::jint,
     filesystem_type_int: jni::sys::jint,
+    progress_callback: JObject,
+    store_pim_hint: jni::sys::jboolean,
+    resume: jni::sys::jboolean,
+    full_format: jni::sys::jboolean,
 ) -> jni::sys::jint {
     let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         // Convert path
         let path_str: String = match env.get_string(&path) {
              Ok(s) => s.into(),
-             Err(_) => return -1,
+             Err(_) => return fail(NoxError::InvalidArgument, "Format failed: invalid path"),
         };
         
         // Convert byte arrays
         let password_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(password) }) {
              Ok(b) => Zeroizing::new(b),
-             Err(_) => return -2,
+             Err(_) => return fail(NoxError::InvalidArgument, "Format failed: invalid byte array"),
         };
         let salt_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(salt) }) {
              Ok(b) => Zeroizing::new(b),
-             Err(_) => return -2,
+             Err(_) => return fail(NoxError::InvalidArgument, "Format failed: invalid byte array"),
         };
         let master_key_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(master_key) }) {
              Ok(b) => Zeroizing::new(b),
-             Err(_) => return -2,
+             Err(_) => return fail(NoxError::InvalidArgument, "Format failed: invalid byte array"),
         };
         
         let cipher_type = match int_to_cipher_type(cipher_type_int) {
              Some(c) => c,
-             None => return -3, // Invalid cipher
+             None => return fail(NoxError::InvalidArgument, &format!("Format failed: invalid cipher {}", cipher_type_int)),
         };
         
         let prf = match int_to_prf(prf_int) {
              Some(p) => p,
-             None => return -4, // Invalid PRF
+             None => return fail(NoxError::InvalidArgument, &format!("Format failed: invalid PRF {}", prf_int)),
         };
 
         let filesystem_type = match filesystem_type_int {
             0 => volume::FilesystemType::Fat32,
             1 => volume::FilesystemType::ExFat,
-            _ => return -6, // Invalid filesystem type
+            2 => volume::FilesystemType::Auto,
+            _ => return fail(NoxError::InvalidArgument, &format!("Format failed: invalid file system {}", filesystem_type_int)),
         };
         
+        // Forward formatting progress to onProgress(long, long) when a callback was given.
+        let env_cell = std::cell::RefCell::new(&mut env);
+        let report = |done: u64, total: u64| {
+            let mut env = env_cell.borrow_mut();
+            let call = env.call_method(
+                &progress_callback,
+                "onProgress",
+                "(JJ)V",
+                &[JValue::Long(done as i64), JValue::Long(total as i64)],
+            );
+            if call.is_err() {
+                // A throwing callback must not abort the format.
+                let _ = env.exception_clear();
+                log::warn!("Progress callback failed");
+            }
+        };
+        let progress: Option<&dyn Fn(u64, u64)> = if progress_callback.is_null() { None } else { Some(&report) };
+
         // Call create_volume
-        match volume::create_volume(
+        match volume::create_volume_resumable(
             &path_str,
             &password_bytes,
             pim,
@@ -1428,21 +3131,123 @@ pub extern "system" fn Java_com_noxcipher_RustNative_formatVolume(
             prf,
             None, // default sector size
             filesystem_type,
+            progress,
+            store_pim_hint != 0,
+            resume != 0,
+            full_format != 0,
         ) {
             Ok(_) => 0, // Success
-            Err(e) => {
-                 log::error!("Format failed: {}", e);
-                 -5 // Error
-            }
+            Err(e) => fail_volume(&e, &format!("Format failed: {}", e)),
         }
     }));
     
     match res {
          Ok(val) => val,
-         Err(_) => -99 // Panic
+         Err(_) => fail(NoxError::Internal, "Panic in formatVolume"),
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_encryptInPlace.
+// Converts the plain file at `path` into a volume (see volume::encrypt_in_place).
+// A negative resume_offset starts a new conversion; otherwise it continues from that offset.
+// Returns 0 on success, or a negative error code (see fail) on failure.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_encryptInPlace(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+    password: jbyteArray,
+    pim: jni::sys::jint,
+    salt: jbyteArray,
+    master_key: jbyteArray,
+    cipher_type_int: jni::sys::jint,
+    prf_int: jni::sys::jint,
+    resume_offset: jlong,
+    progress_callback: JObject,
+) -> jni::sys::jint {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+             Ok(s) => s.into(),
+             Err(_) => return fail(NoxError::InvalidArgument, "In-place encryption failed: invalid path"),
+        };
+
+        let password_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(password) }) {
+             Ok(b) => Zeroizing::new(b),
+             Err(_) => return fail(NoxError::InvalidArgument, "In-place encryption failed: invalid byte array"),
+        };
+        let salt_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(salt) }) {
+             Ok(b) => Zeroizing::new(b),
+             Err(_) => return fail(NoxError::InvalidArgument, "In-place encryption failed: invalid byte array"),
+        };
+        let master_key_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(master_key) }) {
+             Ok(b) => Zeroizing::new(b),
+             Err(_) => return fail(NoxError::InvalidArgument, "In-place encryption failed: invalid byte array"),
+        };
+
+        let cipher_type = match int_to_cipher_type(cipher_type_int) {
+             Some(c) => c,
+             None => return fail(NoxError::InvalidArgument, &format!("In-place encryption failed: invalid cipher {}", cipher_type_int)),
+        };
+        let prf = match int_to_prf(prf_int) {
+             Some(p) => p,
+             None => return fail(NoxError::InvalidArgument, &format!("In-place encryption failed: invalid PRF {}", prf_int)),
+        };
+
+        // Forward progress to onProgress(long, long) when a callback was given.
+        let env_cell = std::cell::RefCell::new(&mut env);
+        let report = |done: u64, total: u64| {
+            let mut env = env_cell.borrow_mut();
+            let call = env.call_method(
+                &progress_callback,
+                "onProgress",
+                "(JJ)V",
+                &[JValue::Long(done as i64), JValue::Long(total as i64)],
+            );
+            if call.is_err() {
+                let _ = env.exception_clear();
+                log::warn!("Progress callback failed");
+            }
+        };
+        let progress: Option<&dyn Fn(u64, u64)> = if progress_callback.is_null() { None } else { Some(&report) };
+        let resume = if resume_offset < 0 { None } else { Some(resume_offset as u64) };
+
+        match volume::encrypt_in_place(
+            &path_str,
+            &password_bytes,
+            pim,
+            &salt_bytes,
+            &master_key_bytes,
+            cipher_type,
+            prf,
+            resume,
+            progress,
+        ) {
+            Ok(_) => 0,
+            Err(e) => fail_volume(&e, &format!("In-place encryption failed: {}", e)),
+        }
+    }));
+
+    match res {
+         Ok(val) => val,
+         Err(_) => fail(NoxError::Internal, "Panic in encryptInPlace"),
     }
 }
 
+// Define a JNI function named Java_com_noxcipher_RustNative_inPlaceProgress.
+// Returns the resume offset recorded by an interrupted encryptInPlace on `path`, or -1 if none.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_inPlaceProgress(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+) -> jlong {
+    let path_str: String = match env.get_string(&path) {
+        Ok(s) => s.into(),
+        Err(_) => return -1,
+    };
+    panic::catch_unwind(|| volume::in_place_progress(&path_str).map_or(-1, |offset| offset as jlong)).unwrap_or(-1)
+}
+
 #[no_mangle]
 pub extern "system" fn Java_com_noxcipher_RustNative_changePassword(
     mut env: JNIEnv,
@@ -1458,20 +3263,20 @@ pub extern "system" fn Java_com_noxcipher_RustNative_changePassword(
     let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
         let path_str: String = match env.get_string(&path) {
              Ok(s) => s.into(),
-             Err(_) => return -1,
+             Err(_) => return fail(NoxError::InvalidArgument, "Change Password failed: invalid path"),
         };
         
         let old_pass_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(old_password) }) {
              Ok(b) => Zeroizing::new(b),
-             Err(_) => return -2,
+             Err(_) => return fail(NoxError::InvalidArgument, "Change Password failed: invalid byte array"),
         };
         let new_pass_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(new_password) }) {
              Ok(b) => Zeroizing::new(b),
-             Err(_) => return -2,
+             Err(_) => return fail(NoxError::InvalidArgument, "Change Password failed: invalid byte array"),
         };
          let new_salt_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(new_salt) }) {
              Ok(b) => Zeroizing::new(b),
-             Err(_) => return -2,
+             Err(_) => return fail(NoxError::InvalidArgument, "Change Password failed: invalid byte array"),
         };
         
         let new_prf = int_to_prf(new_prf_int); // Option
@@ -1486,19 +3291,231 @@ pub extern "system" fn Java_com_noxcipher_RustNative_changePassword(
              new_prf
         ) {
              Ok(_) => 0,
-             Err(e) => {
-                  log::error!("Change Password failed: {}", e);
-                  -5
-             }
+             Err(e) => fail_volume(&e, &format!("Change Password failed: {}", e)),
         }
     }));
     
     match res {
         Ok(val) => val,
-        Err(_) => -99
+        Err(_) => fail(NoxError::Internal, "Panic in changePassword"),
+    }
+}
+
+
+// Define a JNI function named Java_com_noxcipher_RustNative_changeKeyfiles.
+// It swaps the keyfile set of a volume, keeping its password and PIM. Returns 0 on success,
+// or a negative error code (see fail) on failure.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_changeKeyfiles(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+    password: jbyteArray,
+    pim: jni::sys::jint,
+    old_keyfiles: jobjectArray,
+    new_keyfiles: jobjectArray,
+    new_salt: jbyteArray,
+    new_prf_int: jni::sys::jint,
+) -> jni::sys::jint {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+             Ok(s) => s.into(),
+             Err(_) => return fail(NoxError::InvalidArgument, "Change keyfiles failed: invalid path"),
+        };
+
+        let pass_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(password) }) {
+             Ok(b) => Zeroizing::new(b),
+             Err(_) => return fail(NoxError::InvalidArgument, "Change keyfiles failed: invalid byte array"),
+        };
+        let new_salt_bytes = match env.convert_byte_array(unsafe { &JByteArray::from_raw(new_salt) }) {
+             Ok(b) => Zeroizing::new(b),
+             Err(_) => return fail(NoxError::InvalidArgument, "Change keyfiles failed: invalid byte array"),
+        };
+        let (old_keyfile_bytes, new_keyfile_bytes) =
+            match (read_keyfiles(&mut env, old_keyfiles), read_keyfiles(&mut env, new_keyfiles)) {
+                (Ok(old), Ok(new)) => (old, new),
+                (Err(e), _) | (_, Err(e)) => {
+                    return fail(NoxError::InvalidArgument, &format!("Invalid keyfiles array: {}", e));
+                }
+            };
+        let old_slices: Vec<&[u8]> = old_keyfile_bytes.iter().map(|k| k.as_slice()).collect();
+        let new_slices: Vec<&[u8]> = new_keyfile_bytes.iter().map(|k| k.as_slice()).collect();
+
+        match volume::change_keyfiles(
+             &path_str,
+             &pass_bytes,
+             pim,
+             &old_slices,
+             &new_slices,
+             &new_salt_bytes,
+             int_to_prf(new_prf_int),
+        ) {
+             Ok(_) => 0,
+             Err(e) => fail_volume(&e, &format!("Change keyfiles failed: {}", e)),
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => fail(NoxError::Internal, "Panic in changeKeyfiles"),
+    }
+}
+
+
+// Define a JNI function named Java_com_noxcipher_RustNative_exportHeader.
+// It returns the raw encrypted 128 KiB header region (primary or backup) for safekeeping.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_exportHeader(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+    use_backup: jni::sys::jboolean,
+) -> jbyteArray {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(_) => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Invalid path");
+                return ptr::null_mut();
+            }
+        };
+
+        match volume::export_header(&path_str, use_backup != 0) {
+            Ok(header) => match env.byte_array_from_slice(&header) {
+                Ok(arr) => arr.into_raw(),
+                Err(_) => ptr::null_mut(),
+            },
+            Err(e) => {
+                throw_error(&mut env, "java/io/IOException", format!("Header export failed: {}", e));
+                ptr::null_mut()
+            }
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => {
+            log::error!("Panic in exportHeader");
+            record_error(NoxError::Internal, "Panic in exportHeader");
+            ptr::null_mut()
+        }
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_restoreHeader.
+// It writes a previously exported 128 KiB header region back to the primary or backup location.
+#[no_mangle]
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+pub extern "system" fn Java_com_noxcipher_RustNative_restoreHeader(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+    header_blob: jbyteArray,
+    to_backup_location: jni::sys::jboolean,
+) -> jni::sys::jint {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(_) => return fail(NoxError::InvalidArgument, "Header restore failed: invalid path"),
+        };
+
+        let blob = match env.convert_byte_array(unsafe { &JByteArray::from_raw(header_blob) }) {
+            Ok(b) => b,
+            Err(_) => return fail(NoxError::InvalidArgument, "Header restore failed: invalid byte array"),
+        };
+
+        match volume::restore_header(&path_str, &blob, to_backup_location != 0) {
+            Ok(_) => 0,
+            Err(e) => {
+                throw_volume_error(&mut env, "java/io/IOException", &e, format!("Header restore failed: {}", e));
+                e.code()
+            }
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => fail(NoxError::Internal, "Panic in restoreHeader"),
+    }
+}
+
+// Define a JNI function named Java_com_noxcipher_RustNative_wipeVolume.
+// It overwrites a volume file with `passes` rounds of random data followed by zeros.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_wipeVolume(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+    passes: jni::sys::jint,
+) -> jni::sys::jint {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(_) => return fail(NoxError::InvalidArgument, "Wipe failed: invalid path"),
+        };
+
+        let passes = match u32::try_from(passes) {
+            Ok(p) => p,
+            Err(_) => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative pass count");
+                return NoxError::InvalidArgument.return_code();
+            }
+        };
+
+        match volume::wipe_volume(&path_str, passes) {
+            Ok(_) => 0,
+            Err(e) => {
+                throw_volume_error(&mut env, "java/io/IOException", &e, format!("Wipe failed: {}", e));
+                e.code()
+            }
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => fail(NoxError::Internal, "Panic in wipeVolume"),
     }
 }
 
+// Define a JNI function named Java_com_noxcipher_RustNative_freeSpaceEntropy.
+// Returns the sampled entropy in bits per byte (0.0..=8.0) of a file region,
+// or -1.0 with an IOException on error.
+#[no_mangle]
+pub extern "system" fn Java_com_noxcipher_RustNative_freeSpaceEntropy(
+    mut env: JNIEnv,
+    _class: JClass,
+    path: jni::objects::JString,
+    region_start: jlong,
+    region_len: jlong,
+) -> jni::sys::jdouble {
+    let res = panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let path_str: String = match env.get_string(&path) {
+            Ok(s) => s.into(),
+            Err(_) => return -1.0,
+        };
+
+        let (start, len) = match (u64::try_from(region_start), u64::try_from(region_len)) {
+            (Ok(start), Ok(len)) => (start, len),
+            _ => {
+                throw_error(&mut env, "java/lang/IllegalArgumentException", "Negative region offset or length");
+                return -1.0;
+            }
+        };
+
+        match volume::analyze_freespace_entropy(&path_str, start, len) {
+            Ok(entropy) => entropy,
+            Err(e) => {
+                throw_error(&mut env, "java/io/IOException", format!("Entropy analysis failed: {}", e));
+                -1.0
+            }
+        }
+    }));
+
+    match res {
+        Ok(val) => val,
+        Err(_) => -1.0,
+    }
+}
 
 #[cfg(test)]
 #[path = "tests_change_password.rs"]
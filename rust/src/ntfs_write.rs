@@ -0,0 +1,667 @@
+use byteorder::{ByteOrder, LittleEndian};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use ntfs::Ntfs;
+
+// Minimal NTFS metadata editing
+// The ntfs crate is read-only, so modifications work on raw MFT and index records read through
+// the decrypted device. The crate is only used to locate file records.
+
+// Update sequence fixups protect the last two bytes of every 512-byte stride of a record.
+const FIXUP_STRIDE: usize = 512;
+const ATTR_ATTRIBUTE_LIST: u32 = 0x20;
+const ATTR_FILE_NAME: u32 = 0x30;
+const ATTR_DATA: u32 = 0x80;
+const ATTR_INDEX_ROOT: u32 = 0x90;
+const ATTR_INDEX_ALLOCATION: u32 = 0xA0;
+const ATTR_BITMAP: u32 = 0xB0;
+const ATTR_END: u32 = 0xFFFF_FFFF;
+const RECORD_FLAG_IN_USE: u16 = 0x0001;
+const RECORD_FLAG_DIRECTORY: u16 = 0x0002;
+const INDEX_ENTRY_SUBNODE: u16 = 0x0001;
+const INDEX_ENTRY_LAST: u16 = 0x0002;
+const INDEX_NODE_HAS_CHILDREN: u32 = 0x0001;
+const FILE_NAME_NAMESPACE_DOS: u8 = 2;
+// Offset of the index node header inside an INDX record.
+const INDX_NODE_OFFSET: usize = 0x18;
+// File references carry a sequence number in the top 16 bits.
+const FILE_REFERENCE_MASK: u64 = 0x0000_FFFF_FFFF_FFFF;
+const MFT_RECORD_NUMBER: u64 = 0;
+const BITMAP_RECORD_NUMBER: u64 = 6;
+const I30: &str = "$I30";
+
+fn invalid_data(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unsupported(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, message)
+}
+
+// A run of clusters: starting cluster (None for a sparse run) and length in clusters.
+type DataRun = (Option<u64>, u64);
+
+// Attribute header fields of a record attribute.
+#[derive(Clone)]
+struct RawAttribute {
+    offset: usize,
+    ty: u32,
+    length: usize,
+    non_resident: bool,
+    name: String,
+}
+
+// Location of the update sequence array: (offset, entry count including the sequence number).
+fn fixup_array(record: &[u8]) -> io::Result<(usize, usize)> {
+    if record.len() < 8 {
+        return Err(invalid_data("NTFS record too small"));
+    }
+    let offset = LittleEndian::read_u16(&record[4..6]) as usize;
+    let count = LittleEndian::read_u16(&record[6..8]) as usize;
+    if count == 0 || (count - 1) * FIXUP_STRIDE > record.len() || offset + 2 * count > record.len() {
+        return Err(invalid_data("Invalid NTFS update sequence array"));
+    }
+    Ok((offset, count))
+}
+
+// Undo the update sequence fixups of a record read from disk.
+pub(crate) fn remove_fixup(record: &mut [u8]) -> io::Result<()> {
+    let (offset, count) = fixup_array(record)?;
+    for i in 1..count {
+        let end = i * FIXUP_STRIDE;
+        if record[end - 2..end] != record[offset..offset + 2] {
+            return Err(invalid_data("NTFS update sequence mismatch"));
+        }
+        record.copy_within(offset + 2 * i..offset + 2 * i + 2, end - 2);
+    }
+    Ok(())
+}
+
+// Re-apply the fixups before a record is written back, with a new update sequence number.
+pub(crate) fn apply_fixup(record: &mut [u8]) -> io::Result<()> {
+    let (offset, count) = fixup_array(record)?;
+    let mut usn = LittleEndian::read_u16(&record[offset..offset + 2]).wrapping_add(1);
+    if usn == 0 || usn == 0xFFFF {
+        usn = 1;
+    }
+    LittleEndian::write_u16(&mut record[offset..offset + 2], usn);
+    for i in 1..count {
+        let end = i * FIXUP_STRIDE;
+        record.copy_within(end - 2..end, offset + 2 * i);
+        LittleEndian::write_u16(&mut record[end - 2..end], usn);
+    }
+    Ok(())
+}
+
+fn read_record<D: Read + Seek>(dev: &mut D, position: u64, size: usize, magic: &[u8; 4]) -> io::Result<Vec<u8>> {
+    let mut record = vec![0u8; size];
+    dev.seek(SeekFrom::Start(position))?;
+    dev.read_exact(&mut record)?;
+    if &record[0..4] != magic {
+        return Err(invalid_data("Bad NTFS record signature"));
+    }
+    remove_fixup(&mut record)?;
+    Ok(record)
+}
+
+fn write_record<D: Write + Seek>(dev: &mut D, position: u64, record: &mut [u8]) -> io::Result<()> {
+    apply_fixup(record)?;
+    dev.seek(SeekFrom::Start(position))?;
+    dev.write_all(record)?;
+    dev.flush()
+}
+
+// Absolute position of a file record, located through the $MFT data runs by the ntfs crate.
+fn record_position<D: Read + Seek>(fs: &Ntfs, dev: &mut D, record_number: u64) -> io::Result<u64> {
+    let file = fs
+        .file(dev, record_number)
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
+    file.position()
+        .value()
+        .map(|p| p.get())
+        .ok_or_else(|| invalid_data("NTFS file record has no position"))
+}
+
+// Parse the attribute headers of a record (fixups already removed).
+fn record_attributes(record: &[u8]) -> io::Result<Vec<RawAttribute>> {
+    let used = std::cmp::min(LittleEndian::read_u32(&record[0x18..0x1C]) as usize, record.len());
+    let mut offset = LittleEndian::read_u16(&record[0x14..0x16]) as usize;
+    let mut attributes = Vec::new();
+    loop {
+        if offset + 4 > used {
+            return Err(invalid_data("NTFS attribute list runs past the record"));
+        }
+        let ty = LittleEndian::read_u32(&record[offset..offset + 4]);
+        if ty == ATTR_END {
+            return Ok(attributes);
+        }
+        if offset + 0x18 > used {
+            return Err(invalid_data("Truncated NTFS attribute header"));
+        }
+        let length = LittleEndian::read_u32(&record[offset + 4..offset + 8]) as usize;
+        if length < 0x18 || offset + length > used {
+            return Err(invalid_data("Invalid NTFS attribute length"));
+        }
+        let name_length = record[offset + 9] as usize;
+        let name_offset = LittleEndian::read_u16(&record[offset + 10..offset + 12]) as usize;
+        if name_offset + 2 * name_length > length {
+            return Err(invalid_data("Invalid NTFS attribute name"));
+        }
+        let units: Vec<u16> = record[offset + name_offset..offset + name_offset + 2 * name_length]
+            .chunks_exact(2)
+            .map(LittleEndian::read_u16)
+            .collect();
+        attributes.push(RawAttribute {
+            offset,
+            ty,
+            length,
+            non_resident: record[offset + 8] != 0,
+            name: String::from_utf16_lossy(&units),
+        });
+        offset += length;
+    }
+}
+
+fn find_attribute<'a>(attributes: &'a [RawAttribute], ty: u32, name: &str) -> Option<&'a RawAttribute> {
+    attributes.iter().find(|a| a.ty == ty && a.name == name)
+}
+
+// Absolute range of a resident attribute value inside the record.
+fn resident_value(record: &[u8], attr: &RawAttribute) -> io::Result<std::ops::Range<usize>> {
+    if attr.non_resident {
+        return Err(invalid_data("Expected a resident NTFS attribute"));
+    }
+    let length = LittleEndian::read_u32(&record[attr.offset + 0x10..attr.offset + 0x14]) as usize;
+    let offset = LittleEndian::read_u16(&record[attr.offset + 0x14..attr.offset + 0x16]) as usize;
+    if offset + length > attr.length {
+        return Err(invalid_data("NTFS attribute value runs past the attribute"));
+    }
+    Ok(attr.offset + offset..attr.offset + offset + length)
+}
+
+// Decode the mapping pairs of a non-resident attribute.
+fn data_runs(record: &[u8], attr: &RawAttribute) -> io::Result<Vec<DataRun>> {
+    if !attr.non_resident || attr.length < 0x40 {
+        return Err(invalid_data("Expected a non-resident NTFS attribute"));
+    }
+    let end = attr.offset + attr.length;
+    let mut pos = attr.offset + LittleEndian::read_u16(&record[attr.offset + 0x20..attr.offset + 0x22]) as usize;
+    let mut lcn = 0i64;
+    let mut runs = Vec::new();
+    while pos < end && record[pos] != 0 {
+        let length_size = (record[pos] & 0x0F) as usize;
+        let offset_size = (record[pos] >> 4) as usize;
+        if length_size == 0 || length_size > 8 || offset_size > 8 || pos + 1 + length_size + offset_size > end {
+            return Err(invalid_data("Invalid NTFS data run"));
+        }
+        let length = LittleEndian::read_uint(&record[pos + 1..pos + 1 + length_size], length_size);
+        let start = if offset_size == 0 {
+            None
+        } else {
+            let delta = LittleEndian::read_int(&record[pos + 1 + length_size..pos + 1 + length_size + offset_size], offset_size);
+            lcn = lcn
+                .checked_add(delta)
+                .filter(|l| *l >= 0)
+                .ok_or_else(|| invalid_data("Invalid NTFS data run offset"))?;
+            Some(lcn as u64)
+        };
+        runs.push((start, length));
+        pos += 1 + length_size + offset_size;
+    }
+    Ok(runs)
+}
+
+// Absolute position of `offset` within a non-resident stream, and the bytes left in that run.
+fn run_position(runs: &[DataRun], cluster_size: u64, offset: u64) -> io::Result<(u64, u64)> {
+    let mut run_start = 0u64;
+    for &(lcn, length) in runs {
+        let run_bytes = length
+            .checked_mul(cluster_size)
+            .ok_or_else(|| invalid_data("NTFS data run too long"))?;
+        if offset - run_start < run_bytes {
+            let lcn = lcn.ok_or_else(|| invalid_data("NTFS metadata stream is sparse"))?;
+            let within = offset - run_start;
+            return Ok((lcn * cluster_size + within, run_bytes - within));
+        }
+        run_start += run_bytes;
+    }
+    Err(invalid_data("Offset beyond the end of the NTFS stream"))
+}
+
+// Read `buf.len()` bytes of a non-resident stream starting at `offset`.
+fn read_stream<D: Read + Seek>(dev: &mut D, runs: &[DataRun], cluster_size: u64, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    let mut done = 0usize;
+    while done < buf.len() {
+        let (position, left) = run_position(runs, cluster_size, offset + done as u64)?;
+        let chunk = std::cmp::min(left, (buf.len() - done) as u64) as usize;
+        dev.seek(SeekFrom::Start(position))?;
+        dev.read_exact(&mut buf[done..done + chunk])?;
+        done += chunk;
+    }
+    Ok(())
+}
+
+// Write `buf` into a non-resident stream starting at `offset`.
+fn write_stream<D: Write + Seek>(dev: &mut D, runs: &[DataRun], cluster_size: u64, offset: u64, buf: &[u8]) -> io::Result<()> {
+    let mut done = 0usize;
+    while done < buf.len() {
+        let (position, left) = run_position(runs, cluster_size, offset + done as u64)?;
+        let chunk = std::cmp::min(left, (buf.len() - done) as u64) as usize;
+        dev.seek(SeekFrom::Start(position))?;
+        dev.write_all(&buf[done..done + chunk])?;
+        done += chunk;
+    }
+    dev.flush()
+}
+
+// Clear bits [first, first + count) in `bitmap`, whose first byte holds bit `base`.
+fn clear_bits(bitmap: &mut [u8], base: u64, first: u64, count: u64) {
+    for (i, byte) in bitmap.iter_mut().enumerate() {
+        for bit in 0..8 {
+            let n = base + i as u64 * 8 + bit;
+            if n >= first && n - first < count {
+                *byte &= !(1u8 << bit);
+            }
+        }
+    }
+}
+
+// Clear bits [first, first + count) of a bitmap attribute, resident or not.
+// A resident bitmap is changed in `record` only; the caller writes the record back.
+fn clear_attribute_bits<D: Read + Write + Seek>(
+    dev: &mut D,
+    record: &mut [u8],
+    attr: &RawAttribute,
+    cluster_size: u64,
+    first: u64,
+    count: u64,
+) -> io::Result<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    check_attribute_bits(record, attr, first, count)?;
+    let first_byte = first / 8;
+    let end_byte = (first + count - 1) / 8 + 1;
+    if !attr.non_resident {
+        let value = resident_value(record, attr)?;
+        let bytes = &mut record[value.start + first_byte as usize..value.start + end_byte as usize];
+        clear_bits(bytes, first_byte * 8, first, count);
+        return Ok(());
+    }
+
+    let runs = data_runs(record, attr)?;
+    let mut byte = first_byte;
+    while byte < end_byte {
+        let chunk = std::cmp::min(end_byte - byte, 64 * 1024) as usize;
+        let mut buf = vec![0u8; chunk];
+        read_stream(dev, &runs, cluster_size, byte, &mut buf)?;
+        clear_bits(&mut buf, byte * 8, first, count);
+        write_stream(dev, &runs, cluster_size, byte, &buf)?;
+        byte += chunk as u64;
+    }
+    Ok(())
+}
+
+// Check that bits `first..first + count` exist in the bitmap attribute `attr`, so that
+// clear_attribute_bits cannot fail on the layout after earlier writes went through.
+fn check_attribute_bits(record: &[u8], attr: &RawAttribute, first: u64, count: u64) -> io::Result<()> {
+    if count == 0 {
+        return Ok(());
+    }
+    let end_byte = (first + count - 1) / 8 + 1;
+    let size = if attr.non_resident {
+        data_runs(record, attr)?;
+        LittleEndian::read_u64(&record[attr.offset + 0x30..attr.offset + 0x38])
+    } else {
+        resident_value(record, attr)?.len() as u64
+    };
+    if end_byte > size {
+        return Err(invalid_data("NTFS bitmap too small"));
+    }
+    Ok(())
+}
+
+// Remove the entries referring to `record_number` from the index node whose header starts at
+// `node`. The bytes after a removed entry up to `shift_end` move down and the tail is zeroed.
+// Returns the number of bytes removed.
+fn remove_node_entries(buf: &mut [u8], node: usize, mut shift_end: usize, record_number: u64) -> io::Result<usize> {
+    let entries_start = node + LittleEndian::read_u32(&buf[node..node + 4]) as usize;
+    let mut entries_end = node + LittleEndian::read_u32(&buf[node + 4..node + 8]) as usize;
+    if entries_start > entries_end || entries_end > shift_end || shift_end > buf.len() {
+        return Err(invalid_data("Invalid NTFS index node header"));
+    }
+
+    let mut removed = 0usize;
+    let mut pos = entries_start;
+    loop {
+        if pos + 0x10 > entries_end {
+            return Err(invalid_data("NTFS index node has no end entry"));
+        }
+        let length = LittleEndian::read_u16(&buf[pos + 8..pos + 10]) as usize;
+        let flags = LittleEndian::read_u16(&buf[pos + 12..pos + 14]);
+        if length < 0x10 || pos + length > entries_end {
+            return Err(invalid_data("Invalid NTFS index entry length"));
+        }
+        if flags & INDEX_ENTRY_LAST != 0 {
+            break;
+        }
+        if LittleEndian::read_u64(&buf[pos..pos + 8]) & FILE_REFERENCE_MASK != record_number {
+            pos += length;
+            continue;
+        }
+        if flags & INDEX_ENTRY_SUBNODE != 0 {
+            return Err(unsupported("Deleting an entry of an internal NTFS index node is not supported"));
+        }
+        buf.copy_within(pos + length..shift_end, pos);
+        buf[shift_end - length..shift_end].fill(0);
+        shift_end -= length;
+        entries_end -= length;
+        removed += length;
+    }
+
+    if removed > 0 {
+        LittleEndian::write_u32(&mut buf[node + 4..node + 8], (entries_end - node) as u32);
+    }
+    Ok(removed)
+}
+
+// Whether an index node holds only its end entry.
+fn node_is_empty(buf: &[u8], node: usize) -> bool {
+    let first = node + LittleEndian::read_u32(&buf[node..node + 4]) as usize;
+    LittleEndian::read_u16(&buf[first + 12..first + 14]) & INDEX_ENTRY_LAST != 0
+}
+
+// Delete a file given its MFT record number and that of its parent directory.
+// Every record and bitmap involved is read and validated before the first write. The directory
+// index entries are then removed, the record is marked unused and its clusters are released in
+// $Bitmap (and the record in the $MFT bitmap).
+// Only simple files are handled: a single name, no attribute list, and data that is resident
+// or a single run. Index entries must sit in the root or a leaf node that keeps other entries.
+pub(crate) fn delete_file<D: Read + Write + Seek>(
+    fs: &Ntfs,
+    dev: &mut D,
+    parent_number: u64,
+    record_number: u64,
+) -> io::Result<()> {
+    let record_size = fs.file_record_size() as usize;
+    let cluster_size = fs.cluster_size() as u64;
+
+    // Validate the file record before changing anything.
+    let file_position = record_position(fs, dev, record_number)?;
+    let mut file_record = read_record(dev, file_position, record_size, b"FILE")?;
+    let flags = LittleEndian::read_u16(&file_record[0x16..0x18]);
+    if flags & RECORD_FLAG_IN_USE == 0 {
+        return Err(invalid_data("NTFS file record is not in use"));
+    }
+    if flags & RECORD_FLAG_DIRECTORY != 0 {
+        return Err(unsupported("Deleting directories is not supported on NTFS"));
+    }
+    let file_attributes = record_attributes(&file_record)?;
+    if file_attributes.iter().any(|a| a.ty == ATTR_ATTRIBUTE_LIST) {
+        return Err(unsupported("NTFS files with an attribute list are not supported"));
+    }
+
+    let mut names = 0;
+    for attr in file_attributes.iter().filter(|a| a.ty == ATTR_FILE_NAME) {
+        let value = resident_value(&file_record, attr)?;
+        if value.len() < 0x42 {
+            return Err(invalid_data("NTFS file name attribute too small"));
+        }
+        let parent = LittleEndian::read_u64(&file_record[value.start..value.start + 8]) & FILE_REFERENCE_MASK;
+        if parent != parent_number {
+            return Err(unsupported("Deleting hard-linked NTFS files is not supported"));
+        }
+        if file_record[value.start + 0x41] != FILE_NAME_NAMESPACE_DOS {
+            names += 1;
+        }
+    }
+    if names > 1 {
+        return Err(unsupported("Deleting hard-linked NTFS files is not supported"));
+    }
+
+    let mut freed_run: Option<(u64, u64)> = None;
+    for attr in file_attributes.iter().filter(|a| a.non_resident) {
+        if attr.ty != ATTR_DATA || !attr.name.is_empty() {
+            return Err(unsupported("NTFS files with non-resident streams other than the data are not supported"));
+        }
+        let runs = data_runs(&file_record, attr)?;
+        if runs.len() > 1 {
+            return Err(unsupported("Fragmented NTFS files are not supported"));
+        }
+        if let Some(&(Some(lcn), length)) = runs.first() {
+            freed_run = Some((lcn, length));
+        }
+    }
+
+    // Remove the directory entries in memory; nothing is written until every lookup succeeded.
+    let parent_position = record_position(fs, dev, parent_number)?;
+    let mut parent_record = read_record(dev, parent_position, record_size, b"FILE")?;
+    let parent_attributes = record_attributes(&parent_record)?;
+    if parent_attributes.iter().any(|a| a.ty == ATTR_ATTRIBUTE_LIST) {
+        return Err(unsupported("NTFS directories with an attribute list are not supported"));
+    }
+    let root = find_attribute(&parent_attributes, ATTR_INDEX_ROOT, I30)
+        .ok_or_else(|| invalid_data("NTFS directory has no index root"))?;
+    let root_value = resident_value(&parent_record, root)?;
+    if root_value.len() < 0x20 {
+        return Err(invalid_data("NTFS index root too small"));
+    }
+    let index_record_size = LittleEndian::read_u32(&parent_record[root_value.start + 8..root_value.start + 12]) as usize;
+
+    let mut removed_any = false;
+    let used = LittleEndian::read_u32(&parent_record[0x18..0x1C]) as usize;
+    let root_node = root_value.start + 0x10;
+    let removed = remove_node_entries(&mut parent_record, root_node, used, record_number)?;
+    if removed > 0 {
+        // The resident index root shrinks, moving the following attributes down.
+        removed_any = true;
+        let node_allocated = LittleEndian::read_u32(&parent_record[root_node + 8..root_node + 12]) as usize;
+        LittleEndian::write_u32(&mut parent_record[root_node + 8..root_node + 12], node_allocated.saturating_sub(removed) as u32);
+        LittleEndian::write_u32(&mut parent_record[root.offset + 0x10..root.offset + 0x14], (root_value.len() - removed) as u32);
+        LittleEndian::write_u32(&mut parent_record[root.offset + 4..root.offset + 8], (root.length - removed) as u32);
+        LittleEndian::write_u32(&mut parent_record[0x18..0x1C], (used - removed) as u32);
+    }
+
+    let mut index_blocks: Vec<(u64, Vec<u8>)> = Vec::new();
+    let mut index_runs = Vec::new();
+    if let Some(allocation) = find_attribute(&parent_attributes, ATTR_INDEX_ALLOCATION, I30) {
+        if index_record_size == 0 {
+            return Err(invalid_data("Invalid NTFS index record size"));
+        }
+        let runs = data_runs(&parent_record, allocation)?;
+        let data_size = LittleEndian::read_u64(&parent_record[allocation.offset + 0x30..allocation.offset + 0x38]);
+        let block_count = data_size / index_record_size as u64;
+
+        // Blocks not marked in the $I30 bitmap are free and may hold stale entries.
+        let mut in_use = vec![0xFFu8; block_count.div_ceil(8) as usize];
+        if let Some(bitmap) = find_attribute(&parent_attributes, ATTR_BITMAP, I30) {
+            if bitmap.non_resident {
+                let bitmap_runs = data_runs(&parent_record, bitmap)?;
+                read_stream(dev, &bitmap_runs, cluster_size, 0, &mut in_use)?;
+            } else {
+                let value = resident_value(&parent_record, bitmap)?;
+                let len = std::cmp::min(value.len(), in_use.len());
+                in_use[..len].copy_from_slice(&parent_record[value.start..value.start + len]);
+            }
+        }
+
+        for block in 0..block_count {
+            if in_use[(block / 8) as usize] & (1 << (block % 8)) == 0 {
+                continue;
+            }
+            let offset = block * index_record_size as u64;
+            let mut buf = vec![0u8; index_record_size];
+            read_stream(dev, &runs, cluster_size, offset, &mut buf)?;
+            if &buf[0..4] != b"INDX" {
+                return Err(invalid_data("Bad NTFS index record signature"));
+            }
+            remove_fixup(&mut buf)?;
+            let node = INDX_NODE_OFFSET;
+            let node_used = node + LittleEndian::read_u32(&buf[node + 4..node + 8]) as usize;
+            if remove_node_entries(&mut buf, node, node_used, record_number)? > 0 {
+                let node_flags = LittleEndian::read_u32(&buf[node + 12..node + 16]);
+                if node_flags & INDEX_NODE_HAS_CHILDREN == 0 && node_is_empty(&buf, node) {
+                    return Err(unsupported("Deleting the last entry of an NTFS index node is not supported"));
+                }
+                index_blocks.push((offset, buf));
+            }
+        }
+
+        removed_any |= !index_blocks.is_empty();
+        index_runs = runs;
+    }
+    if !removed_any {
+        return Err(invalid_data("NTFS directory entry not found"));
+    }
+
+    // Look up the $MFT bitmap bit of the record.
+    let mft_position = record_position(fs, dev, MFT_RECORD_NUMBER)?;
+    let mut mft_record = read_record(dev, mft_position, record_size, b"FILE")?;
+    let mft_attributes = record_attributes(&mft_record)?;
+    let mft_bitmap = find_attribute(&mft_attributes, ATTR_BITMAP, "")
+        .ok_or_else(|| invalid_data("NTFS $MFT has no bitmap"))?;
+    check_attribute_bits(&mft_record, mft_bitmap, record_number, 1)?;
+
+    // Look up the $Bitmap bits of the data clusters.
+    let mut cluster_bitmap = None;
+    if let Some((lcn, length)) = freed_run {
+        let bitmap_position = record_position(fs, dev, BITMAP_RECORD_NUMBER)?;
+        let bitmap_record = read_record(dev, bitmap_position, record_size, b"FILE")?;
+        let bitmap_attributes = record_attributes(&bitmap_record)?;
+        let bitmap = find_attribute(&bitmap_attributes, ATTR_DATA, "")
+            .ok_or_else(|| invalid_data("NTFS $Bitmap has no data"))?
+            .clone();
+        if !bitmap.non_resident {
+            return Err(unsupported("Resident NTFS $Bitmap is not supported"));
+        }
+        check_attribute_bits(&bitmap_record, &bitmap, lcn, length)?;
+        cluster_bitmap = Some((bitmap_record, bitmap, lcn, length));
+    }
+
+    // Everything checked out: write the index blocks and the parent directory record.
+    for (offset, buf) in index_blocks.iter_mut() {
+        apply_fixup(buf)?;
+        write_stream(dev, &index_runs, cluster_size, *offset, buf)?;
+    }
+    if removed > 0 {
+        write_record(dev, parent_position, &mut parent_record)?;
+    }
+
+    // Mark the file record unused; the new sequence number invalidates stale references.
+    let mut sequence = LittleEndian::read_u16(&file_record[0x10..0x12]).wrapping_add(1);
+    if sequence == 0 {
+        sequence = 1;
+    }
+    LittleEndian::write_u16(&mut file_record[0x10..0x12], sequence);
+    LittleEndian::write_u16(&mut file_record[0x16..0x18], flags & !RECORD_FLAG_IN_USE);
+    write_record(dev, file_position, &mut file_record)?;
+
+    // Release the record in the $MFT bitmap.
+    clear_attribute_bits(dev, &mut mft_record, mft_bitmap, cluster_size, record_number, 1)?;
+    if !mft_bitmap.non_resident {
+        write_record(dev, mft_position, &mut mft_record)?;
+    }
+
+    // Release the data clusters in $Bitmap.
+    if let Some((mut bitmap_record, bitmap, lcn, length)) = cluster_bitmap {
+        clear_attribute_bits(dev, &mut bitmap_record, &bitmap, cluster_size, lcn, length)?;
+    }
+
+    Ok(())
+}
+
+// Read a file record and locate the header of its unnamed non-resident $DATA attribute.
+fn data_attribute<D: Read + Seek>(fs: &Ntfs, dev: &mut D, record_number: u64) -> io::Result<(u64, Vec<u8>, usize)> {
+    let position = record_position(fs, dev, record_number)?;
+    let record = read_record(dev, position, fs.file_record_size() as usize, b"FILE")?;
+    let attributes = record_attributes(&record)?;
+    let data = find_attribute(&attributes, ATTR_DATA, "").ok_or_else(|| invalid_data("NTFS file has no data attribute"))?;
+    if !data.non_resident || data.length < 0x40 {
+        return Err(unsupported("Expected a non-resident NTFS data attribute"));
+    }
+    Ok((position, record, data.offset))
+}
+
+// Initialized size (valid data length) of the unnamed $DATA stream of a file. Bytes past it read
+// as zero whatever the clusters hold.
+pub(crate) fn data_initialized_size<D: Read + Seek>(fs: &Ntfs, dev: &mut D, record_number: u64) -> io::Result<u64> {
+    let (_, record, offset) = data_attribute(fs, dev, record_number)?;
+    Ok(LittleEndian::read_u64(&record[offset + 0x38..offset + 0x40]))
+}
+
+// Raise the initialized size of the unnamed $DATA stream of a file to `size`, which must not
+// exceed its data size. The caller zeroes the clusters that become part of the initialized range.
+pub(crate) fn set_data_initialized_size<D: Read + Write + Seek>(
+    fs: &Ntfs,
+    dev: &mut D,
+    record_number: u64,
+    size: u64,
+) -> io::Result<()> {
+    let (position, mut record, offset) = data_attribute(fs, dev, record_number)?;
+    let data_size = LittleEndian::read_u64(&record[offset + 0x30..offset + 0x38]);
+    if size > data_size {
+        return Err(invalid_data("NTFS initialized size beyond the data size"));
+    }
+    LittleEndian::write_u64(&mut record[offset + 0x38..offset + 0x40], size);
+    write_record(dev, position, &mut record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixup_round_trip() {
+        let mut record = vec![0u8; 1024];
+        record[0..4].copy_from_slice(b"FILE");
+        LittleEndian::write_u16(&mut record[4..6], 0x30);
+        LittleEndian::write_u16(&mut record[6..8], 3);
+        record[510] = 0xAB;
+        record[1023] = 0xCD;
+        let original = record.clone();
+
+        apply_fixup(&mut record).unwrap();
+        assert_eq!(&record[510..512], &record[0x30..0x32]);
+        assert_eq!(&record[1022..1024], &record[0x30..0x32]);
+        remove_fixup(&mut record).unwrap();
+        assert_eq!(&record[..0x30], &original[..0x30]);
+        assert_eq!(&record[0x36..], &original[0x36..]);
+
+        // A torn write (one stride without the sequence number) is detected.
+        apply_fixup(&mut record).unwrap();
+        record[1023] ^= 0xFF;
+        assert!(remove_fixup(&mut record).is_err());
+    }
+
+    #[test]
+    fn test_remove_node_entries_skips_subnode_and_keeps_end_entry() {
+        // Node header at 0 with entries at 0x10: entry for record 7, entry for record 9, end entry.
+        let mut node = vec![0u8; 0x40];
+        LittleEndian::write_u32(&mut node[0..4], 0x10);
+        LittleEndian::write_u32(&mut node[4..8], 0x40);
+        LittleEndian::write_u64(&mut node[0x10..0x18], 7 | (3 << 48));
+        LittleEndian::write_u16(&mut node[0x18..0x1A], 0x10);
+        LittleEndian::write_u64(&mut node[0x20..0x28], 9);
+        LittleEndian::write_u16(&mut node[0x28..0x2A], 0x10);
+        LittleEndian::write_u16(&mut node[0x38..0x3A], 0x10);
+        LittleEndian::write_u16(&mut node[0x3C..0x3E], INDEX_ENTRY_LAST);
+
+        assert_eq!(remove_node_entries(&mut node, 0, 0x40, 7).unwrap(), 0x10);
+        assert_eq!(LittleEndian::read_u32(&node[4..8]), 0x30);
+        assert_eq!(LittleEndian::read_u64(&node[0x10..0x18]), 9);
+        assert_eq!(remove_node_entries(&mut node, 0, 0x30, 9).unwrap(), 0x10);
+        assert!(node_is_empty(&node, 0));
+
+        // Entries with a subnode need B-tree rebalancing and are refused.
+        let mut node = vec![0u8; 0x30];
+        LittleEndian::write_u32(&mut node[0..4], 0x10);
+        LittleEndian::write_u32(&mut node[4..8], 0x30);
+        LittleEndian::write_u64(&mut node[0x10..0x18], 7);
+        LittleEndian::write_u16(&mut node[0x18..0x1A], 0x10);
+        LittleEndian::write_u16(&mut node[0x1C..0x1E], INDEX_ENTRY_SUBNODE);
+        LittleEndian::write_u16(&mut node[0x28..0x2A], 0x10);
+        LittleEndian::write_u16(&mut node[0x2C..0x2E], INDEX_ENTRY_LAST);
+        let err = remove_node_entries(&mut node, 0, 0x30, 7).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    }
+}
@@ -103,5 +103,1244 @@ mod tests {
         assert!(!path.exists());
     }
 
+    // Master key whose XTS halves differ, so create_volume does not reject it.
+    fn distinct_master_key(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i as u8).wrapping_add(1)).collect()
+    }
+
+    // Path of the temp file `name`, with any file left there by an earlier run removed.
+    fn test_path(name: &str) -> String {
+        let mut path = std::env::temp_dir();
+        path.push(name);
+        let path = path.to_str().unwrap().to_string();
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    // Create a volume at `path` with create_volume and 512-byte sectors. Salt and master key are
+    // fixed (the key is long enough for any cascade).
+    fn create_test_volume(
+        path: &str,
+        password: &[u8],
+        pim: i32,
+        size: u64,
+        cipher: CipherType,
+        prf: PrfAlgorithm,
+        filesystem: FilesystemType,
+    ) -> Result<(), VolumeError> {
+        volume::create_volume(path, password, pim, size, &[1u8; 64], &distinct_master_key(192), cipher, prf, None, filesystem)
+    }
+
+    // Create a volume with create_test_volume in the temp file `name` and return its image; the
+    // file is removed again.
+    fn create_test_image(
+        name: &str,
+        password: &[u8],
+        pim: i32,
+        size: u64,
+        cipher: CipherType,
+        prf: PrfAlgorithm,
+        filesystem: FilesystemType,
+    ) -> Vec<u8> {
+        let path = test_path(name);
+        create_test_volume(&path, password, pim, size, cipher, prf, filesystem)
+            .unwrap_or_else(|e| panic!("Failed to create {} volume {}: {}", cipher.name(), name, e));
+        let content = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        content
+    }
+
+    // Mount the primary header of an image from create_test_image, without backup header or
+    // hidden volume protection.
+    fn mount_test_image(content: &[u8], password: &[u8], pim: i32, read_only: bool) -> Result<i64, VolumeError> {
+        volume::create_context(password, &content[..131072], pim, 0, None, 0, None, 0, content.len() as u64, None, read_only)
+    }
+
+    // Mount an image from create_test_image and return its volume; the context is closed again.
+    fn test_image_volume(content: &[u8], password: &[u8], pim: i32) -> std::sync::Arc<Volume> {
+        let handle = mount_test_image(content, password, pim, false).expect("Failed to mount volume");
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+        volume::close_context(handle);
+        vol
+    }
+
+    #[test]
+    fn test_create_volume_reports_bounded_progress() {
+        let path = test_path("test_create_volume_progress.hc");
+
+        let calls = std::cell::RefCell::new(Vec::new());
+        let record = |done: u64, total: u64| calls.borrow_mut().push((done, total));
+        volume::create_volume_with_progress(
+            &path,
+            b"password",
+            1,
+            4 * 1024 * 1024,
+            &[1u8; 64],
+            &distinct_master_key(64),
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            None,
+            FilesystemType::Fat32,
+            Some(&record),
+            false,
+        ).expect("Failed to create volume");
+
+        let calls = calls.into_inner();
+        assert!(!calls.is_empty());
+        assert!(calls.len() as u64 <= crate::format::PROGRESS_REPORT_STEPS + 1, "{} reports", calls.len());
+        // Monotonic, against a fixed total, ending at completion.
+        let total = calls[0].1;
+        assert!(calls.windows(2).all(|w| w[0].0 < w[1].0 && w[1].1 == total));
+        assert_eq!(*calls.last().unwrap(), (total, total));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_pim_hint_mounts_without_pim() {
+        let path = test_path("test_pim_hint.hc");
+
+        let size = 1024 * 1024;
+        volume::create_volume_with_progress(
+            &path,
+            b"password",
+            3,
+            size,
+            &[1u8; 64],
+            &distinct_master_key(64),
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            None,
+            FilesystemType::Fat32,
+            None,
+            true,
+        ).expect("Failed to create volume");
+
+        let content = std::fs::read(&path).unwrap();
+        assert_eq!(crate::header::decode_pim_hint(&content[..131072]), Some(3));
+        // PIM 0 picks up the hint instead of sweeping the default iteration counts.
+        let handle = mount_test_image(&content, b"password", 0, false)
+            .expect("Failed to mount with PIM hint");
+        volume::close_context(handle);
+
+        let _ = std::fs::remove_file(&path);
+
+        // Without the opt-in the area after the header stays empty.
+        let content = create_test_image(
+            "test_pim_hint.hc",
+            b"password",
+            3,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        assert_eq!(crate::header::decode_pim_hint(&content[..131072]), None);
+    }
+
+    #[test]
+    fn test_every_cipher_type_round_trips_through_create_volume() {
+        let size = 1024 * 1024;
+
+        // Every cipher the app can pick by id must mount back with that cipher.
+        for cipher in volume::CIPHER_IDS {
+            let content = create_test_image(
+                &format!("test_round_trip_{}.hc", cipher.name()),
+                b"password",
+                1,
+                size,
+                cipher,
+                PrfAlgorithm::Sha512,
+                FilesystemType::Fat32,
+            );
+            let handle = mount_test_image(&content, b"password", 1, false)
+                .unwrap_or_else(|e| panic!("Failed to mount {} volume: {}", cipher.name(), e));
+            let info = volume::get_volume_info(handle).unwrap();
+            volume::close_context(handle);
+            assert_eq!(info.cipher_name, cipher.name());
+        }
+    }
+
+    #[test]
+    fn test_mount_with_keyfiles_round_trip() {
+        let keyfile: &[u8] = b"keyfile contents for mounting";
+        let mixed = crate::keyfile::apply_keyfiles(b"password", &[keyfile]).unwrap();
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_keyfile_mount.hc",
+            &mixed,
+            0,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let header = &content[0..131072];
+
+        let handle = volume::create_context_with_keyfiles(
+            b"password", &[keyfile], header, 0, 0, None, 0, None, 0, size, None, false,
+        ).expect("Failed to mount with keyfile");
+        volume::close_context(handle);
+
+        // Without the keyfile the same password must not mount.
+        let res = volume::create_context_with_keyfiles(
+            b"password", &[], header, 0, 0, None, 0, None, 0, size, None, false,
+        );
+        assert!(matches!(res, Err(VolumeError::InvalidPassword(_))));
+    }
+
+    #[test]
+    fn test_get_volume_info_reports_cipher_and_prf() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_volume_info.hc",
+            b"password",
+            0,
+            size,
+            CipherType::AesTwofish,
+            PrfAlgorithm::Sha256,
+            FilesystemType::Fat32,
+        );
+        let handle = mount_test_image(&content, b"password", 0, false).expect("Failed to mount");
+
+        let info = volume::get_volume_info(handle).expect("Missing volume info");
+        assert_eq!(info.cipher_name, "AES-Twofish");
+        assert_eq!(info.prf_name, "SHA-256");
+        assert_eq!(info.sector_size, 512);
+        assert!(info.data_size > 0 && info.data_size <= size);
+
+        volume::close_context(handle);
+        assert!(volume::get_volume_info(handle).is_err());
+    }
+
+    #[test]
+    fn test_get_volume_times_reports_creation_time() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_volume_times.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let handle = mount_test_image(&content, b"password", 1, false).expect("Failed to mount");
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let (volume_time, header_time) = volume::get_volume_times(handle).expect("Missing volume times");
+        assert!(now.abs_diff(volume_time) <= 5, "volume time {} vs now {}", volume_time, now);
+        assert!(now.abs_diff(header_time) <= 5, "header time {} vs now {}", header_time, now);
+
+        volume::close_context(handle);
+        assert!(volume::get_volume_times(handle).is_err());
+    }
+
+    #[test]
+    fn test_read_only_mount_rejects_encrypt() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_read_only_mount.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+
+        let handle = mount_test_image(&content, b"password", 1, true).expect("Failed to mount read-only");
+        assert!(volume::is_read_only(handle).unwrap());
+        let mut data = [0u8; 512];
+        let err = volume::encrypt(handle, 0, &mut data).unwrap_err();
+        assert_eq!(err.to_string(), "Crypto Error: Volume is Read-Only");
+        // Unaligned writes are refused the same way, after their covering sector is decrypted.
+        let mut covering = [0u8; 512];
+        let err = volume::encrypt_range(handle, 10, b"partial", &mut covering).unwrap_err();
+        assert_eq!(err.to_string(), "Crypto Error: Volume is Read-Only");
+        // Reads still work.
+        volume::decrypt(handle, 0, &mut data).unwrap();
+        volume::close_context(handle);
+
+        let handle = mount_test_image(&content, b"password", 1, false).expect("Failed to mount writable");
+        assert!(!volume::is_read_only(handle).unwrap());
+        volume::encrypt(handle, 0, &mut data).unwrap();
+        volume::close_context(handle);
+    }
+
+    #[test]
+    fn test_in_memory_logger_stores_formatted_records() {
+        let _ = log::set_logger(&crate::LOGGER);
+        log::set_max_level(crate::effective_max_level(log::LevelFilter::Info));
+
+        log::info!("logger-test-info-marker");
+        // Lowering the max level must not suppress warnings.
+        log::set_max_level(crate::effective_max_level(log::LevelFilter::Off));
+        log::warn!("logger-test-warn-marker");
+        log::set_max_level(crate::effective_max_level(log::LevelFilter::Info));
+
+        let buffer = crate::LOG_BUFFER.lock().unwrap_or_else(|e| e.into_inner());
+        assert!(buffer.iter().any(|line| line == "[INFO] logger-test-info-marker"));
+        assert!(buffer.iter().any(|line| line == "[WARN] logger-test-warn-marker"));
+    }
+
+    #[test]
+    fn test_created_volume_mounts_as_fat32() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_fat32_mount.hc",
+            b"password",
+            0,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let handle = mount_test_image(&content, b"password", 0, false).expect("Failed to mount volume");
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+
+        // Decrypt the data area the same way DecryptedReader does.
+        let start = vol.data_offset() as usize;
+        let mut plain = content[start..start + vol.size() as usize].to_vec();
+        vol.decrypt_sector(0, &mut plain).expect("Failed to decrypt data area");
+        volume::close_context(handle);
+
+        let mut fs = crate::fat32::Fat32::open(std::io::Cursor::new(plain)).expect("Failed to open FAT32");
+        assert!(fs.list_files(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_decrypted_reader_lists_fat32_root_in_memory() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_memory_reader_mount.hc",
+            b"password",
+            0,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let vol = test_image_volume(&content, b"password", 0);
+
+        // Whole stack in-process: MemoryReader -> DecryptedReader -> FAT32.
+        let reader = crate::filesystem::DecryptedReader::new(crate::io_memory::MemoryReader::new(content), vol);
+        let mut fs = crate::fat32::Fat32::open(reader).expect("Failed to open FAT32");
+        assert!(fs.list_files(&[]).unwrap().is_empty());
+
+        fs.write_file(&["notes.txt"], 0, b"in memory").unwrap();
+        let files = fs.list_files(&[]).unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].name, "notes.txt");
+        let mut buf = [0u8; 16];
+        let n = fs.read_file(&["notes.txt"], 0, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"in memory");
+    }
+
+    #[test]
+    fn test_created_16mb_volume_detected_as_fat32_with_empty_root() {
+        use std::io::Read;
+
+        let size = 16 * 1024 * 1024;
+        let content = create_test_image(
+            "test_fat32_detect_16mb.hc",
+            b"password",
+            0,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let vol = test_image_volume(&content, b"password", 0);
+
+        // Same detection path as mountFs: decrypted boot sector, then a full FAT32 open.
+        let mut reader = crate::filesystem::DecryptedReader::new(crate::io_memory::MemoryReader::new(content), vol);
+        let mut boot_sector = [0u8; 512];
+        reader.read_exact(&mut boot_sector).unwrap();
+        assert!(crate::fat32::is_fat32_boot_sector(&boot_sector));
+
+        let mut fs = crate::fat32::Fat32::open(reader).expect("Failed to open FAT32");
+        assert!(fs.list_files(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_mount_fs_from_reader_detects_file_system_without_jni() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_mount_fs_from_reader.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let vol = test_image_volume(&content, b"password", 1);
+
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol.clone(), std::io::Cursor::new(content.clone()))
+            .expect("Failed to detect file system");
+        assert!(matches!(fs, crate::filesystem::SupportedFileSystem::Fat32(_)));
+        assert!(fs.list_files("/").unwrap().is_empty());
+
+        // Wiping the data area leaves nothing recognizable.
+        let mut wiped = content;
+        wiped[131072..].fill(0);
+        let err = crate::filesystem::mount_fs_from_reader(vol, std::io::Cursor::new(wiped)).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn test_mount_on_bad_handle_sets_last_error() {
+        // Same failure path as mountFs with a handle that was never registered.
+        let (code, msg) = crate::mount_volume_context(-424_242).err().expect("Bad handle accepted");
+        assert_eq!(crate::mount_failure(code, &msg), VolumeError::NotInitialized.code() as i64);
+
+        let (code, last) = crate::last_error().expect("No last error recorded");
+        assert_eq!(code, crate::error::NoxError::NotInitialized);
+        assert!(last.contains("Volume handle invalid"), "{}", last);
+    }
+
+    #[test]
+    fn test_last_error_is_per_thread() {
+        assert_eq!(crate::mount_failure(crate::error::NoxError::IoError, "disk gone"), crate::MOUNT_ERR_IO);
+        let (code, message) = crate::last_error().unwrap();
+        assert_eq!(code, crate::error::NoxError::IoError);
+        assert!(message.contains("disk gone"), "{}", message);
+
+        // Another thread has its own code and message.
+        let other = std::thread::spawn(crate::last_error).join().unwrap();
+        assert!(other.is_none());
+
+        let err = VolumeError::InvalidPassword(String::new());
+        crate::record_error((&err).into(), "Init failed");
+        assert_eq!(crate::last_error().unwrap(), (crate::error::NoxError::InvalidPassword, "Init failed".to_string()));
+    }
+
+    #[test]
+    fn test_int_failures_return_the_recorded_code() {
+        let err = VolumeError::InvalidPassword(String::new());
+        assert_eq!(crate::fail_volume(&err, "Change Password failed"), -2);
+        assert_eq!(crate::last_error().unwrap().0.return_code(), -2);
+
+        assert_eq!(crate::fail(crate::error::NoxError::InvalidArgument, "Format failed: invalid PRF 99"), -12);
+        assert_eq!(crate::last_error().unwrap().0, crate::error::NoxError::InvalidArgument);
+    }
+
+    #[test]
+    fn test_async_mount_failure_is_reported_to_poller() {
+        let (token, cancelled) = crate::start_mount_entry(&mut crate::PENDING_MOUNTS.lock().unwrap());
+        assert_eq!(crate::poll_mount(token).unwrap(), crate::MOUNT_PENDING);
+        let outcome: Result<(), _> = Err((crate::error::NoxError::MountFailed, "no file system".to_string()));
+        assert!(crate::complete_mount(token, &cancelled, outcome, |_| unreachable!()));
+
+        // The worker thread's failure reaches getLastError on the thread that polls.
+        let (code, msg) = crate::poll_mount(token).unwrap_err();
+        assert_eq!(crate::mount_failure(code, &msg), crate::MOUNT_ERR_NO_FILESYSTEM);
+        assert_eq!(crate::last_error().unwrap().0, crate::error::NoxError::MountFailed);
+        assert_eq!(crate::poll_mount(token).unwrap_err().0, crate::error::NoxError::InvalidArgument);
+    }
+
+    #[test]
+    fn test_cancelled_mount_is_never_registered() {
+        // Cancel while the worker is still parsing, then let it finish.
+        let (token, cancelled) = crate::start_mount_entry(&mut crate::PENDING_MOUNTS.lock().unwrap());
+        assert!(crate::cancel_mount(token));
+        assert!(cancelled.load(std::sync::atomic::Ordering::SeqCst));
+        let registered = std::cell::Cell::new(false);
+        assert!(!crate::complete_mount(token, &cancelled, Ok(()), |_| {
+            registered.set(true);
+            1
+        }));
+        assert!(!registered.get());
+        assert_eq!(crate::poll_mount(token).unwrap_err().0, crate::error::NoxError::InvalidArgument);
+        assert!(!crate::cancel_mount(token));
+
+        // Finish first: the handle is stored for pollMount and a later cancel discards it.
+        let (token, cancelled) = crate::start_mount_entry(&mut crate::PENDING_MOUNTS.lock().unwrap());
+        assert!(crate::complete_mount(token, &cancelled, Ok(()), |_| -77));
+        assert!(crate::cancel_mount(token));
+        assert_eq!(crate::poll_mount(token).unwrap_err().0, crate::error::NoxError::InvalidArgument);
+
+        // Without a cancel pollMount collects the handle exactly once.
+        let (token, cancelled) = crate::start_mount_entry(&mut crate::PENDING_MOUNTS.lock().unwrap());
+        assert!(crate::complete_mount(token, &cancelled, Ok(()), |_| -78));
+        assert_eq!(crate::poll_mount(token).unwrap(), -78);
+        assert!(!crate::cancel_mount(token));
+    }
+
+    // Create a FAT32 volume of `size` bytes and open its file system over an in-memory image.
+    fn mount_test_fat32(name: &str, size: u64) -> crate::filesystem::SupportedFileSystem<std::io::Cursor<Vec<u8>>> {
+        let content = create_test_image(name, b"password", 1, size, CipherType::Aes, PrfAlgorithm::Sha512, FilesystemType::Fat32);
+        let vol = test_image_volume(&content, b"password", 1);
+        crate::filesystem::mount_fs_from_reader(vol, std::io::Cursor::new(content))
+            .expect("Failed to detect file system")
+    }
+
+    #[test]
+    fn test_dir_size_sums_subtree() {
+        let mut fs = mount_test_fat32("test_dir_size.hc", 4 * 1024 * 1024);
+
+        fs.create_dir("a").unwrap();
+        fs.create_dir("a/b").unwrap();
+        fs.create_dir("empty").unwrap();
+        fs.write_file("top.bin", 0, &[1u8; 100]).unwrap();
+        fs.write_file("a/one.bin", 0, &[2u8; 1000]).unwrap();
+        fs.write_file("a/b/two.bin", 0, &[3u8; 5000]).unwrap();
+
+        assert_eq!(fs.dir_size("/").unwrap(), 6100);
+        assert_eq!(fs.dir_size("/a").unwrap(), 6000);
+        assert_eq!(fs.dir_size("a/b/").unwrap(), 5000);
+        assert_eq!(fs.dir_size("empty").unwrap(), 0);
+        assert_eq!(fs.dir_size("missing").unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert_eq!(fs.dir_size("a/..").unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+    }
+
+    #[test]
+    fn test_dir_size_rejects_excessive_depth() {
+        let mut fs = mount_test_fat32("test_dir_size_depth.hc", 4 * 1024 * 1024);
+
+        // 257 nested directories: one more level than dir_size follows.
+        let mut path = String::new();
+        for _ in 0..257 {
+            path.push_str("/d");
+            fs.create_dir(&path).unwrap();
+        }
+
+        assert_eq!(fs.dir_size("/").unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+        // The same tree is fine when started one level down.
+        assert_eq!(fs.dir_size("/d").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_create_hidden_volume_mounts_both() {
+        let path = test_path("test_hidden_volume.hc");
+
+        let size = 4 * 1024 * 1024;
+        let hidden_size = 1024 * 1024;
+        let hidden_key: Vec<u8> = distinct_master_key(64).iter().map(|b| b.wrapping_mul(3)).collect();
+        create_test_volume(
+            &path,
+            b"outer",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        ).expect("Failed to create outer volume");
+
+        // A hidden volume reaching into the outer FATs is rejected.
+        assert!(volume::create_hidden_volume(
+            &path, size, b"outer", 1, size - 2 * 131072, b"hidden", 1, &[2u8; 64], &hidden_key, CipherType::Aes, PrfAlgorithm::Sha512,
+        ).is_err());
+        volume::create_hidden_volume(
+            &path, size, b"outer", 1, hidden_size, b"hidden", 1, &[2u8; 64], &hidden_key, CipherType::Aes, PrfAlgorithm::Sha512,
+        ).expect("Failed to create hidden volume");
+
+        let content = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let outer = mount_test_image(&content, b"outer", 1, false)
+            .expect("Failed to mount outer volume");
+        assert_eq!(volume::get_volume_info(outer).unwrap().data_size, size - 2 * 131072);
+        volume::close_context(outer);
+
+        let hidden = mount_test_image(&content, b"hidden", 1, false)
+            .expect("Failed to mount hidden volume");
+        assert_eq!(volume::get_volume_info(hidden).unwrap().data_size, hidden_size);
+        let vol = volume::CONTEXTS.lock().unwrap().get(&hidden).cloned().unwrap();
+        volume::close_context(hidden);
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol, std::io::Cursor::new(content.clone()))
+            .expect("Failed to detect hidden file system");
+        assert!(fs.list_files("").unwrap().is_empty());
+
+        // The outer volume can also be mounted with the hidden volume protected.
+        let protected = volume::create_context(b"outer", &content[..131072], 1, 0, None, 0, Some(b"hidden"), 1, size, None, false)
+            .expect("Failed to mount outer volume with protection");
+        volume::close_context(protected);
+    }
+
+    #[test]
+    fn test_create_hidden_volume_checks_outer_file_system() {
+        let path = test_path("test_hidden_volume_outer_checks.hc");
+        let size = 4 * 1024 * 1024;
+        let hidden_size = 1024 * 1024;
+        let hidden_key: Vec<u8> = distinct_master_key(64).iter().map(|b| b.wrapping_mul(3)).collect();
+        let create_hidden = |outer_password: &[u8]| {
+            volume::create_hidden_volume(
+                &path, size, outer_password, 1, hidden_size, b"hidden", 1, &[2u8; 64], &hidden_key, CipherType::Aes, PrfAlgorithm::Sha512,
+            )
+        };
+
+        // The outer volume has to be unlocked, and has to hold FAT32.
+        create_test_volume(&path, b"outer", 1, size, CipherType::Aes, PrfAlgorithm::Sha512, FilesystemType::ExFat)
+            .expect("Failed to create outer volume");
+        assert!(matches!(create_hidden(b"wrong"), Err(VolumeError::InvalidPassword(_))));
+        assert!(matches!(create_hidden(b"outer"), Err(VolumeError::CryptoError(msg)) if msg.contains("FAT32")));
+
+        // A FAT32 outer volume whose files reach into the hidden range is left alone.
+        let _ = std::fs::remove_file(&path);
+        create_test_volume(&path, b"outer", 1, size, CipherType::Aes, PrfAlgorithm::Sha512, FilesystemType::Fat32)
+            .expect("Failed to create outer volume");
+        let buffer = std::sync::Arc::new(std::sync::Mutex::new(std::fs::read(&path).unwrap()));
+        let (handle, reader) = volume::create_context_in_memory(buffer.clone(), b"outer", 1, None, 0, false).unwrap();
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol, reader).unwrap();
+        fs.write_file("/big.bin", 0, &vec![7u8; 3 * 1024 * 1024]).unwrap();
+        fs.sync().unwrap();
+        drop(fs);
+        volume::close_context(handle);
+        let outer_image = buffer.lock().unwrap().clone();
+        std::fs::write(&path, &outer_image).unwrap();
+
+        assert!(matches!(create_hidden(b"outer"), Err(VolumeError::CryptoError(msg)) if msg.contains("has data")));
+        assert_eq!(std::fs::read(&path).unwrap(), outer_image);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_free_space_after_create_volume() {
+        let size = 4 * 1024 * 1024;
+        let mut fs = mount_test_fat32("test_free_space.hc", size);
+
+        let (free, total) = fs.free_space().unwrap();
+        // The data area excludes the two 128 KiB header areas, reserved sectors and FATs.
+        assert!(total > 0 && total < size - 2 * 131072);
+        assert!(free < total);
+
+        fs.write_file("big.bin", 0, &vec![7u8; 64 * 1024]).unwrap();
+        let (after, total_after) = fs.free_space().unwrap();
+        assert_eq!(total_after, total);
+        assert!(after <= free - 64 * 1024, "{} free after writing, {} before", after, free);
+
+        fs.delete("big.bin").unwrap();
+        assert_eq!(fs.free_space().unwrap(), (free, total));
+    }
+
+    #[test]
+    fn test_created_exfat_volume_opens_via_exfat_path() {
+        use std::io::{Read, Seek};
+
+        let size = 16 * 1024 * 1024;
+        let content = create_test_image(
+            "test_exfat_create_16mb.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::ExFat,
+        );
+        let vol = test_image_volume(&content, b"password", 1);
+
+        let mut reader = crate::filesystem::DecryptedReader::new(crate::io_memory::MemoryReader::new(content), vol);
+        let mut boot_sector = [0u8; 512];
+        reader.read_exact(&mut boot_sector).unwrap();
+        assert_eq!(&boot_sector[3..11], b"EXFAT   ");
+        assert!(!crate::fat32::is_fat32_boot_sector(&boot_sector));
+        reader.seek(std::io::SeekFrom::Start(0)).unwrap();
+        if let Err(e) = exfat::ExFat::open(reader) {
+            panic!("exFAT open failed: {:?}", e);
+        }
+    }
+
+    #[test]
+    fn test_auto_filesystem_type_switches_to_exfat_above_threshold() {
+        let threshold = volume::EXFAT_AUTO_THRESHOLD;
+        assert_eq!(FilesystemType::Auto.resolve(threshold), FilesystemType::Fat32);
+        assert_eq!(FilesystemType::Auto.resolve(threshold + 1), FilesystemType::ExFat);
+        assert_eq!(FilesystemType::Fat32.resolve(threshold + 1), FilesystemType::Fat32);
+        assert_eq!(FilesystemType::ExFat.resolve(1024), FilesystemType::ExFat);
+    }
+
+    // Kuznyechik-Serpent-Camellia with SHA3-256: the last cipher and PRF the full search reaches.
+    fn create_hint_test_volume(name: &str) -> Vec<u8> {
+        create_test_image(
+            name,
+            b"password",
+            1,
+            1024 * 1024,
+            CipherType::KuznyechikSerpentCamellia,
+            PrfAlgorithm::Sha3_256,
+            FilesystemType::Fat32,
+        )
+    }
+
+    #[test]
+    fn test_hinted_mount_uses_cipher_and_prf_hints() {
+        let content = create_hint_test_volume("test_hinted_mount.hc");
+        let header = &content[0..131072];
+        let size = content.len() as u64;
+
+        let handle = volume::create_context_hinted(
+            b"password", header, 1, 0, None, 0, None, 0, size, None,
+            Some(CipherType::KuznyechikSerpentCamellia), Some(PrfAlgorithm::Sha3_256), false,
+        ).expect("Hinted mount failed");
+        let info = volume::get_volume_info(handle).unwrap();
+        assert_eq!(info.cipher_name, CipherType::KuznyechikSerpentCamellia.name());
+        assert_eq!(info.prf_name, PrfAlgorithm::Sha3_256.name());
+        volume::close_context(handle);
+
+        // Only one half of the hint known.
+        let handle = volume::create_context_hinted(
+            b"password", header, 1, 0, None, 0, None, 0, size, None,
+            None, Some(PrfAlgorithm::Sha3_256), false,
+        ).expect("PRF-only hinted mount failed");
+        volume::close_context(handle);
+        let handle = volume::create_context_hinted(
+            b"password", header, 1, 0, None, 0, None, 0, size, None,
+            Some(CipherType::KuznyechikSerpentCamellia), None, false,
+        ).expect("Cipher-only hinted mount failed");
+        assert_eq!(volume::get_volume_info(handle).unwrap().prf_name, PrfAlgorithm::Sha3_256.name());
+        volume::close_context(handle);
+    }
+
+    #[test]
+    fn test_wrong_hint_falls_back_to_full_search() {
+        // AES / SHA-512 is the first combination the full search tries, keeping the fallback cheap.
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_hinted_fallback.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+
+        let handle = volume::create_context_hinted(
+            b"password", &content[0..131072], 1, 0, None, 0, None, 0, size, None,
+            Some(CipherType::KuznyechikSerpentCamellia), Some(PrfAlgorithm::Sha3_256), false,
+        ).expect("Fallback mount failed");
+        let info = volume::get_volume_info(handle).unwrap();
+        assert_eq!(info.cipher_name, CipherType::Aes.name());
+        volume::close_context(handle);
+    }
+
+    // Hinted vs full-search mount time. Run with
+    // `cargo test --release -- --ignored`.
+    #[test]
+    #[ignore]
+    fn bench_hinted_vs_full_search_mount() {
+        let content = create_hint_test_volume("bench_hinted_mount.hc");
+        let header = &content[0..131072];
+        let size = content.len() as u64;
+
+        let start = std::time::Instant::now();
+        let handle = volume::create_context(b"password", header, 1, 0, None, 0, None, 0, size, None, false)
+            .expect("Full search mount failed");
+        let full = start.elapsed();
+        volume::close_context(handle);
+
+        let start = std::time::Instant::now();
+        let handle = volume::create_context_hinted(
+            b"password", header, 1, 0, None, 0, None, 0, size, None,
+            Some(CipherType::KuznyechikSerpentCamellia), Some(PrfAlgorithm::Sha3_256), false,
+        ).expect("Hinted mount failed");
+        let hinted = start.elapsed();
+        volume::close_context(handle);
+
+        assert!(
+            hinted < full,
+            "Kuznyechik-Serpent-Camellia / SHA3-256 mount: hinted {:?} not faster than full search {:?}",
+            hinted, full,
+        );
+    }
+
+    fn create_verify_test_volume(name: &str, filesystem_type: FilesystemType) -> (i64, Vec<u8>) {
+        let size = 4 * 1024 * 1024;
+        let content = create_test_image(
+            name,
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            filesystem_type,
+        );
+        let handle = mount_test_image(&content, b"password", 1, false).expect("Failed to mount volume");
+        (handle, content)
+    }
+
+    #[test]
+    fn test_verify_volume_reports_clean_and_corrupted_sectors() {
+        use crate::io_memory::MemoryReader;
+
+        for (name, fs) in [("test_verify_fat32.hc", FilesystemType::Fat32), ("test_verify_exfat.hc", FilesystemType::ExFat)] {
+            let (handle, content) = create_verify_test_volume(name, fs);
+            let report = volume::verify_volume(handle, &mut MemoryReader::new(content), || true).unwrap();
+            assert_eq!(report.first_bad_offset, None, "{:?}", fs);
+            assert!(!report.cancelled);
+            assert_eq!(report.bytes_checked, report.total_bytes);
+            assert_eq!(report.total_bytes, 4 * 1024 * 1024 - 262144);
+            volume::close_context(handle);
+        }
+
+        let (handle, content) = create_verify_test_volume("test_verify_corrupt.hc", FilesystemType::Fat32);
+        let data_start = 131072;
+
+        // A flipped bit in the FAT32 backup boot sector (sector 6) no longer matches sector 0.
+        let mut corrupted = content.clone();
+        corrupted[data_start + 6 * 512 + 100] ^= 0x01;
+        let report = volume::verify_volume(handle, &mut MemoryReader::new(corrupted), || true).unwrap();
+        assert_eq!(report.first_bad_offset, Some(6 * 512));
+
+        // A truncated container fails at the first missing sector.
+        let truncated = content[..data_start + 3 * 1024 * 1024].to_vec();
+        let report = volume::verify_volume(handle, &mut MemoryReader::new(truncated), || true).unwrap();
+        assert_eq!(report.first_bad_offset, Some(3 * 1024 * 1024));
+
+        // Cancelled before the first chunk.
+        let report = volume::verify_volume(handle, &mut MemoryReader::new(content), || false).unwrap();
+        assert!(report.cancelled);
+        assert_eq!(report.bytes_checked, 0);
+        volume::close_context(handle);
+    }
+
+    #[test]
+    fn test_decrypt_direct_region_matches_array_decrypt() {
+        let (handle, content) = create_verify_test_volume("test_decrypt_direct.hc", FilesystemType::Fat32);
+        let data_start = 131072;
+
+        let mut expected = content[data_start..data_start + 4096].to_vec();
+        volume::decrypt(handle, 0, &mut expected).unwrap();
+
+        // Decrypt at a non-zero position of a larger buffer; the surrounding bytes stay untouched.
+        let mut direct = vec![0xeeu8; 512 + 4096 + 512];
+        direct[512..512 + 4096].copy_from_slice(&content[data_start..data_start + 4096]);
+        crate::decrypt_direct_region(handle, 0, &mut direct, 512, 4096).unwrap();
+        assert_eq!(&direct[512..512 + 4096], &expected[..]);
+        assert!(direct[..512].iter().chain(&direct[512 + 4096..]).all(|&b| b == 0xee));
+
+        // 1536 + 4096 runs past the end of the 5120 byte buffer.
+        let err = crate::decrypt_direct_region(handle, 0, &mut direct, 1536, 4096).unwrap_err();
+        assert_eq!(err.0, "java/lang/IndexOutOfBoundsException");
+        let err = crate::decrypt_direct_region(handle, 0, &mut direct, -1, 512).unwrap_err();
+        assert_eq!(err.0, "java/lang/IndexOutOfBoundsException");
+        let err = crate::decrypt_direct_region(handle, -512, &mut direct, 0, 512).unwrap_err();
+        assert_eq!(err.0, "java/lang/IllegalArgumentException");
+        // Unaligned lengths are rejected by the volume.
+        let err = crate::decrypt_direct_region(handle, 0, &mut direct, 0, 100).unwrap_err();
+        assert_eq!(err.0, "java/io/IOException");
+
+        volume::close_context(handle);
+    }
+
+    #[test]
+    fn test_sha3_prf_volumes_mount() {
+        for (prf, name) in [(PrfAlgorithm::Sha3_256, "sha3_256"), (PrfAlgorithm::Sha3_512, "sha3_512")] {
+            // PIM 1 keeps the sweep over the other PRFs cheap.
+            let size = 1024 * 1024;
+            let content = create_test_image(
+                &format!("test_prf_{}.hc", name),
+                b"password",
+                1,
+                size,
+                CipherType::Aes,
+                prf,
+                FilesystemType::Fat32,
+            );
+            let vol = test_image_volume(&content, b"password", 1);
+            assert_eq!(vol.prf, Some(prf));
+        }
+    }
+
+    #[test]
+    fn test_probe_header_reports_cipher_and_prf() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_probe_header.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Serpent,
+            PrfAlgorithm::Sha256,
+            FilesystemType::Fat32,
+        );
+
+        let (cipher, prf) = volume::probe_header(b"password", &content[0..131072], 1, None)
+            .expect("Probe failed");
+        assert_eq!(cipher, CipherType::Serpent);
+        assert_eq!(prf, PrfAlgorithm::Sha256);
+        assert_eq!(crate::int_to_cipher_type(crate::cipher_type_to_int(cipher)), Some(cipher));
+        assert_eq!(crate::int_to_prf(crate::prf_to_int(prf)), Some(prf));
+
+        // A damaged primary header is still probed through the backup header.
+        let backup = &content[(size - 131072) as usize..];
+        let (cipher, _) = volume::probe_header(b"password", &[0u8; 512], 1, Some(backup))
+            .expect("Backup probe failed");
+        assert_eq!(cipher, CipherType::Serpent);
+    }
+
+    #[test]
+    fn test_supported_cipher_and_prf_lists_match_ids() {
+        let ciphers = crate::supported_ciphers();
+        assert_eq!(ciphers.len(), 15);
+        for (id, cipher) in ciphers.iter().enumerate() {
+            assert_eq!(crate::cipher_type_to_int(*cipher), id as i32);
+            assert_eq!(cipher.key_size() % 64, 0);
+        }
+        assert_eq!(ciphers[0].key_size(), 64);
+
+        let prfs = crate::supported_prfs();
+        assert_eq!(prfs.len(), 11);
+        for (id, prf) in prfs.iter().enumerate() {
+            assert_eq!(crate::prf_to_int(*prf), id as i32);
+        }
+
+        // Ids past either table are rejected.
+        assert_eq!(crate::int_to_cipher_type(-1), None);
+        assert_eq!(crate::int_to_cipher_type(15), None);
+        assert_eq!(crate::int_to_prf(-1), None);
+        assert_eq!(crate::int_to_prf(11), None);
+    }
+
+    #[cfg(feature = "noxcipher-kdf")]
+    #[test]
+    fn test_argon2id_volume_round_trip() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_argon2id_volume.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Argon2id,
+            FilesystemType::Fat32,
+        );
+        let vol = test_image_volume(&content, b"password", 1);
+
+        assert_eq!(vol.prf, Some(PrfAlgorithm::Argon2id));
+        assert_ne!(vol.header_flags() & volume::HEADER_FLAG_ARGON2ID, 0);
+    }
+
+    #[cfg(feature = "noxcipher-kdf")]
+    #[test]
+    fn test_blake2b_volume_round_trip() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_blake2b_volume.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Blake2b,
+            FilesystemType::Fat32,
+        );
+        // The tagged salt lets the plain sweep find it without a PRF hint.
+        let vol = test_image_volume(&content, b"password", 1);
+        assert_eq!(vol.prf, Some(PrfAlgorithm::Blake2b));
+        assert_ne!(vol.header_flags() & volume::HEADER_FLAG_BLAKE2B, 0);
+        assert_eq!(vol.header_flags() & volume::HEADER_FLAG_ARGON2ID, 0);
+
+        // The wrong password still fails instead of matching the BLAKE2b attempt.
+        assert!(mount_test_image(&content, b"wrong", 1, false).is_err());
+        assert!(volume::create_context_with_prf_order(
+            b"wrong", &content[0..131072], 1, 0, None, 0, None, 0, size, None, false, &[PrfAlgorithm::Blake2b],
+        ).is_err());
+    }
+
+    #[cfg(feature = "noxcipher-kdf")]
+    #[test]
+    fn test_blake2b_volume_change_password_round_trip() {
+        let path = test_path("test_blake2b_change_password.hc");
+        create_test_volume(&path, b"password", 1, 1024 * 1024, CipherType::Aes, PrfAlgorithm::Blake2b, FilesystemType::Fat32)
+            .expect("Create failed");
+
+        volume::change_password(&path, b"password", 1, b"new password", 1, &[2u8; 64], None).expect("Change password failed");
+        let content = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let vol = test_image_volume(&content, b"new password", 1);
+        assert_eq!(vol.prf, Some(PrfAlgorithm::Blake2b));
+        assert_ne!(vol.header_flags() & volume::HEADER_FLAG_BLAKE2B, 0);
+        assert!(mount_test_image(&content, b"password", 1, false).is_err());
+    }
+
+    #[cfg(not(feature = "noxcipher-kdf"))]
+    #[test]
+    fn test_argon2id_requires_kdf_feature() {
+        let path = test_path("test_argon2id_disabled.hc");
+
+        let result = create_test_volume(
+            &path,
+            b"password",
+            1,
+            1024 * 1024,
+            CipherType::Aes,
+            PrfAlgorithm::Argon2id,
+            FilesystemType::Fat32,
+        );
+        let _ = std::fs::remove_file(&path);
+        assert!(matches!(result, Err(VolumeError::CryptoError(_))));
+    }
+
+    #[test]
+    fn test_inspect_header_returns_non_secret_fields() {
+        let path = test_path("test_inspect_header.hc");
+
+        let size = 1024 * 1024;
+        volume::create_volume(
+            &path,
+            b"password",
+            1,
+            size,
+            &[7u8; 64],
+            &distinct_master_key(64),
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            Some(4096),
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+
+        let content = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let summary = volume::inspect_header(b"password", &content[0..131072], 1).expect("Inspect failed");
+        assert_eq!(summary.version, 5);
+        assert_eq!(summary.sector_size, 4096);
+        assert_eq!(summary.encrypted_area_start, 131072);
+        assert_eq!(summary.volume_data_size, size - 2 * 131072);
+        assert_eq!(summary.hidden_volume_size, 0);
+        assert!(!format!("{:?}", summary).contains("master_key"));
+    }
+
+    #[test]
+    fn test_restore_header_recovers_corrupted_primary() {
+        let path = test_path("test_restore_header.hc");
+
+        let size = 1024 * 1024;
+        create_test_volume(
+            &path,
+            b"password",
+            0,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+
+        let exported = volume::export_header(&path, false).expect("Export failed");
+
+        // Corrupt the primary header.
+        let mut content = std::fs::read(&path).unwrap();
+        content[64..512].fill(0xAA);
+        std::fs::write(&path, &content).unwrap();
+
+        // Wrong-sized blobs are rejected without touching the file.
+        assert!(volume::restore_header(&path, &exported[..1024], false).is_err());
+
+        volume::restore_header(&path, &exported, false).expect("Restore failed");
+
+        let content = std::fs::read(&path).unwrap();
+        let handle = mount_test_image(&content, b"password", 0, false).expect("Restored header should mount");
+        volume::close_context(handle);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_create_context_from_file_mounts_without_callback() {
+        let path = test_path("test_context_from_file.hc");
+
+        let size = 2 * 1024 * 1024;
+        create_test_volume(
+            &path,
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+
+        assert!(volume::create_context_from_file(&path, b"wrong", 1, None, 0, false).is_err());
+
+        // Writable: create a file through the file-backed reader.
+        let handle = volume::create_context_from_file(&path, b"password", 1, None, 0, false)
+            .expect("Failed to mount from file");
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+        let reader = volume::open_context_file(handle).expect("Context should be file-backed");
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol, reader).expect("Mount failed");
+        fs.write_file("/hello.txt", 0, b"from the host").expect("Write failed");
+        fs.sync().expect("Sync failed");
+        drop(fs);
+        volume::close_context(handle);
+        assert!(matches!(volume::open_context_file(handle), Err(volume::VolumeError::NotInitialized)));
+
+        // A context mounted from header bytes has no file behind it.
+        let content = std::fs::read(&path).unwrap();
+        let handle = mount_test_image(&content, b"password", 1, false)
+            .expect("Failed to mount from bytes");
+        match volume::open_context_file(handle) {
+            Err(volume::VolumeError::IoError(e)) => assert_eq!(e.kind(), std::io::ErrorKind::Unsupported),
+            _ => panic!("Expected an Unsupported I/O error"),
+        }
+        volume::close_context(handle);
+
+        // Read-only: the file is listed, but writes are rejected.
+        let handle = volume::create_context_from_file(&path, b"password", 1, None, 0, true)
+            .expect("Failed to mount read-only from file");
+        assert!(volume::is_read_only(handle).unwrap());
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol, volume::open_context_file(handle).unwrap()).unwrap();
+        let files = fs.list_files("/").unwrap();
+        assert!(files.iter().any(|f| f.name == "hello.txt" && f.size == 13));
+        let mut buf = [0u8; 13];
+        assert_eq!(fs.read_file("/hello.txt", 0, &mut buf).unwrap(), 13);
+        assert_eq!(&buf, b"from the host");
+        assert!(fs.write_file("/other.txt", 0, b"x").is_err());
+        drop(fs);
+        volume::close_context(handle);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_custom_iteration_volume_mounts_with_matching_pim() {
+        assert!(volume::pim_for_iterations(volume::MIN_CUSTOM_ITERATIONS - 1).is_err());
+        assert_eq!(volume::pim_for_iterations(100_000).unwrap(), 85);
+        assert_eq!(volume::pim_for_iterations(100_001).unwrap(), 86);
+        assert!(volume::pim_for_iterations(u32::MAX).is_err());
+
+        let path = test_path("test_custom_iterations.hc");
+
+        let size = 1024 * 1024;
+        let pim = volume::create_volume_with_iterations(
+            &path,
+            b"password",
+            150_000,
+            size,
+            &[7u8; 64],
+            &distinct_master_key(64),
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            None,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+        assert_eq!(pim, 135);
+        let content = std::fs::read(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        // The header does not carry the count: a neighbouring PIM does not open it.
+        assert!(mount_test_image(&content, b"password", pim + 1, false).is_err());
+        let handle = mount_test_image(&content, b"password", pim, false)
+            .expect("Failed to mount with the returned PIM");
+        volume::close_context(handle);
+    }
+
+    #[test]
+    fn test_in_memory_volume_end_to_end() {
+        let size = 2 * 1024 * 1024;
+        let image = volume::create_volume_in_memory(
+            b"password",
+            1,
+            size,
+            &[6u8; 64],
+            &distinct_master_key(64),
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume in memory");
+        assert_eq!(image.len() as u64, size);
+        let buffer = std::sync::Arc::new(std::sync::Mutex::new(image));
+
+        assert!(volume::create_context_in_memory(buffer.clone(), b"wrong", 1, None, 0, false).is_err());
+        let (handle, reader) = volume::create_context_in_memory(buffer.clone(), b"password", 1, None, 0, false)
+            .expect("Failed to mount in-memory volume");
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol, reader).expect("Mount failed");
+        assert!(matches!(fs, crate::filesystem::SupportedFileSystem::Fat32(_)));
+        assert!(fs.list_files("/").unwrap().is_empty());
+        fs.write_file("/ram.txt", 0, b"no jni here").unwrap();
+        fs.sync().unwrap();
+        drop(fs);
+        volume::close_context(handle);
+
+        // The write reached the shared buffer: a fresh mount over it sees the file.
+        let (handle, reader) = volume::create_context_in_memory(buffer, b"password", 1, None, 0, true).unwrap();
+        let vol = volume::CONTEXTS.lock().unwrap().get(&handle).cloned().unwrap();
+        let mut fs = crate::filesystem::mount_fs_from_reader(vol, reader).unwrap();
+        let files = fs.list_files("/").unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].name.as_str(), files[0].size), ("ram.txt", 11));
+        let mut buf = [0u8; 11];
+        assert_eq!(fs.read_file("/ram.txt", 0, &mut buf).unwrap(), 11);
+        assert_eq!(&buf, b"no jni here");
+        drop(fs);
+        volume::close_context(handle);
+    }
+
+    #[test]
+    fn test_create_context_from_file_finds_hidden_and_backup_headers() {
+        let path = test_path("test_context_from_file_headers.hc");
+
+        let size = 4 * 1024 * 1024;
+        let hidden_size = 1024 * 1024;
+        let hidden_key: Vec<u8> = distinct_master_key(64).iter().map(|b| b.wrapping_mul(3)).collect();
+        create_test_volume(
+            &path,
+            b"outer",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        ).expect("Failed to create outer volume");
+        volume::create_hidden_volume(
+            &path, size, b"outer", 1, hidden_size, b"hidden", 1, &[2u8; 64], &hidden_key, CipherType::Aes, PrfAlgorithm::Sha512,
+        ).expect("Failed to create hidden volume");
+
+        let hidden = volume::create_context_from_file(&path, b"hidden", 1, None, 0, true)
+            .expect("Hidden volume not found by path");
+        assert_eq!(volume::get_volume_info(hidden).unwrap().data_size, hidden_size);
+        volume::close_context(hidden);
+
+        // With the primary header destroyed, the outer volume mounts from its backup header.
+        {
+            use std::io::{Seek, SeekFrom, Write};
+            let mut file = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+            file.seek(SeekFrom::Start(0)).unwrap();
+            file.write_all(&[0u8; 512]).unwrap();
+        }
+        let outer = volume::create_context_from_file(&path, b"outer", 1, None, 0, true)
+            .expect("Outer volume not found through its backup header");
+        let vol = volume::CONTEXTS.lock().unwrap().get(&outer).cloned().unwrap();
+        assert!(vol.used_backup_header);
+        volume::close_context(outer);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_freshly_mounted_volume_passes_header_integrity_check() {
+        let size = 1024 * 1024;
+        let content = create_test_image(
+            "test_verify_header.hc",
+            b"password",
+            1,
+            size,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            FilesystemType::Fat32,
+        );
+        let handle = mount_test_image(&content, b"password", 1, false).expect("Failed to mount volume");
+        assert!(volume::verify_header(handle).unwrap());
+        volume::close_context(handle);
+        assert!(volume::verify_header(handle).is_err());
+    }
+
+    #[test]
+    fn test_benchmark_prf_scales_with_target() {
+        let short = volume::benchmark_prf(PrfAlgorithm::Sha256, 10).expect("Benchmark failed");
+        let long = volume::benchmark_prf(PrfAlgorithm::Sha256, 10_000).expect("Benchmark failed");
+        assert!(short >= 1);
+        // Timing noise between runs is far below the 1000x difference in targets.
+        assert!(long > short);
+
+        assert!(volume::benchmark_prf(PrfAlgorithm::Sha256, 0).is_err());
+        assert!(volume::benchmark_prf(PrfAlgorithm::Argon2id, 100).is_err());
+    }
+
     // test_encrypted_writer_partial_flush moved to volume.rs due to visibility
 }
@@ -1,7 +1,7 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::volume::{create_volume, change_password, create_context, CipherType, PrfAlgorithm, FilesystemType, close_context};
+    use crate::volume::{create_volume, change_password, change_keyfiles, rekey_header, create_context, create_context_with_keyfiles, probe_header, get_volume_times, CipherType, PrfAlgorithm, FilesystemType, close_context};
     use std::fs;
     use std::path::Path;
     use std::io::{Write, Seek, SeekFrom};
@@ -9,6 +9,11 @@ mod tests {
     const TEST_VOL: &str = "test_vol_inner.hc";
     const TEST_VOL_TWOFISH: &str = "test_vol_twofish.hc";
     const TEST_VOL_AES_TWOFISH: &str = "test_vol_aes_twofish.hc";
+    const TEST_VOL_CAMELLIA: &str = "test_vol_camellia.hc";
+    const TEST_VOL_KUZNYECHIK: &str = "test_vol_kuznyechik.hc";
+    const TEST_VOL_REKEY: &str = "test_vol_rekey.hc";
+    const TEST_VOL_KEYFILES: &str = "test_vol_keyfiles.hc";
+    const TEST_VOL_PRF_MATRIX: &str = "test_vol_prf_matrix.hc";
     const PASS_OLD: &[u8] = b"password123";
     const PASS_NEW: &[u8] = b"newsecret456";
     const SALT_OLD: [u8; 64] = [1u8; 64];
@@ -27,6 +32,11 @@ mod tests {
         }
     }
 
+    // A master key whose XTS key halves differ (MASTER_KEY is rejected as weak by create_volume).
+    fn distinct_master_key(len: usize) -> Vec<u8> {
+        (0..len).map(|index| (index as u8).wrapping_mul(11).wrapping_add(3)).collect()
+    }
+
     #[test]
     fn test_change_password_flow() {
         cleanup();
@@ -76,7 +86,8 @@ mod tests {
             None,
             0,
             size,
-            None
+            None,
+            false,
         ).expect("Failed to open with new password");
         close_context(handle_new);
 
@@ -105,7 +116,8 @@ mod tests {
             None,
             0,
             size,
-            Some(backup_slice)
+            Some(backup_slice),
+            false,
         ).expect("Failed to open with backup header and new password");
         close_context(handle_backup);
 
@@ -149,6 +161,7 @@ mod tests {
             0,
             size,
             None,
+            false,
         ).expect("Failed to mount standard Twofish volume");
         close_context(handle);
 
@@ -205,6 +218,7 @@ mod tests {
             0,
             size,
             None,
+            false,
         ).expect("Failed to mount AES-Twofish volume with new password");
         close_context(handle);
 
@@ -229,9 +243,224 @@ mod tests {
             0,
             size,
             Some(backup_slice),
+            false,
         ).expect("Failed to mount AES-Twofish backup header with new password");
         close_context(handle);
 
         cleanup_path(TEST_VOL_AES_TWOFISH);
     }
+
+    fn single_cipher_change_password_flow(path: &str, cipher: CipherType) {
+        cleanup_path(path);
+
+        let size = 1024 * 1024;
+        let mut master_key = [0u8; 64];
+        for (index, byte) in master_key.iter_mut().enumerate() {
+            *byte = (index as u8).wrapping_mul(3).wrapping_add(7);
+        }
+
+        create_volume(
+            path,
+            PASS_OLD,
+            0,
+            size,
+            &SALT_OLD,
+            &master_key,
+            cipher,
+            PrfAlgorithm::Sha512,
+            None,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+        let original = fs::read(path).expect("Failed to read created volume");
+        let handle = create_context(PASS_OLD, &original[..131072], 0, 0, None, 0, None, 0, size, None, false)
+            .expect("Failed to mount created volume");
+        let times_before = get_volume_times(handle).unwrap();
+        close_context(handle);
+
+        change_password(path, PASS_OLD, 0, PASS_NEW, 0, &SALT_NEW, Some(PrfAlgorithm::Sha512))
+            .expect("Failed to change password");
+
+        let file_content = fs::read(path).expect("Failed to read volume after password change");
+        let handle = create_context(
+            PASS_NEW,
+            &file_content[..131072],
+            0,
+            0,
+            None,
+            0,
+            None,
+            0,
+            size,
+            None,
+            false,
+        ).expect("Failed to mount volume with new password");
+        // Only the key material changes; the header keeps its creation times.
+        assert_eq!(get_volume_times(handle).unwrap(), times_before);
+        close_context(handle);
+
+        // Both headers were rewritten, so the old password opens neither.
+        let backup = &file_content[file_content.len() - 131072..];
+        assert!(create_context(PASS_OLD, &file_content[..131072], 0, 0, None, 0, None, 0, size, Some(backup), false).is_err());
+        let handle = create_context(PASS_NEW, &[0u8; 512], 0, 0, None, 0, None, 0, size, Some(backup), false)
+            .expect("Failed to mount backup header with new password");
+        close_context(handle);
+
+        cleanup_path(path);
+    }
+
+    // Every PBKDF2 PRF, on the cascade that uses the full 192 bytes of derived header key.
+    #[test]
+    fn test_change_password_prf_matrix() {
+        const PRFS: [PrfAlgorithm; 9] = [
+            PrfAlgorithm::Sha512,
+            PrfAlgorithm::Sha256,
+            PrfAlgorithm::Whirlpool,
+            PrfAlgorithm::Ripemd160,
+            PrfAlgorithm::Streebog,
+            PrfAlgorithm::Blake2s,
+            PrfAlgorithm::Sha1,
+            PrfAlgorithm::Sha3_256,
+            PrfAlgorithm::Sha3_512,
+        ];
+        let size = 1024 * 1024;
+        let pim = 1;
+        let master_key = distinct_master_key(192);
+
+        for (index, &prf) in PRFS.iter().enumerate() {
+            let other = PRFS[(index + 1) % PRFS.len()];
+            cleanup_path(TEST_VOL_PRF_MATRIX);
+            create_volume(
+                TEST_VOL_PRF_MATRIX, PASS_OLD, pim, size, &SALT_OLD, &master_key,
+                CipherType::AesTwofishSerpent, prf, None, FilesystemType::Fat32,
+            ).unwrap_or_else(|e| panic!("Failed to create {} volume: {}", prf.name(), e));
+
+            // Same PRF, then a different one; each header must reopen with the PRF it was written with.
+            for (password, next_password, target) in [(PASS_OLD, PASS_NEW, prf), (PASS_NEW, PASS_OLD, other)] {
+                change_password(TEST_VOL_PRF_MATRIX, password, pim, next_password, pim, &SALT_NEW, Some(target))
+                    .unwrap_or_else(|e| panic!("{} -> {}: change failed: {}", prf.name(), target.name(), e));
+
+                let file_content = fs::read(TEST_VOL_PRF_MATRIX).unwrap();
+                let backup = &file_content[file_content.len() - 131072..];
+                let (cipher, found) = probe_header(next_password, &file_content[..131072], pim, None)
+                    .unwrap_or_else(|e| panic!("{} -> {}: probe failed: {}", prf.name(), target.name(), e));
+                assert_eq!(cipher, CipherType::AesTwofishSerpent);
+                assert_eq!(found, target, "{} -> {}", prf.name(), target.name());
+                let (_, found) = probe_header(next_password, &[0u8; 512], pim, Some(backup))
+                    .unwrap_or_else(|e| panic!("{} -> {}: backup probe failed: {}", prf.name(), target.name(), e));
+                assert_eq!(found, target);
+
+                let handle = create_context(next_password, &file_content[..131072], pim, 0, None, 0, None, 0, size, None, false)
+                    .unwrap_or_else(|e| panic!("{} -> {}: mount failed: {}", prf.name(), target.name(), e));
+                close_context(handle);
+            }
+        }
+
+        cleanup_path(TEST_VOL_PRF_MATRIX);
+    }
+
+    #[test]
+    fn test_camellia_change_password_flow() {
+        single_cipher_change_password_flow(TEST_VOL_CAMELLIA, CipherType::Camellia);
+    }
+
+    #[test]
+    fn test_kuznyechik_change_password_flow() {
+        single_cipher_change_password_flow(TEST_VOL_KUZNYECHIK, CipherType::Kuznyechik);
+    }
+
+    #[test]
+    fn test_rekey_header_rotates_salt_and_keeps_password() {
+        cleanup_path(TEST_VOL_REKEY);
+
+        let size = 1024 * 1024;
+        let master_key = distinct_master_key(64);
+
+        create_volume(
+            TEST_VOL_REKEY,
+            PASS_OLD,
+            0,
+            size,
+            &SALT_OLD,
+            &master_key,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            None,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+        let before = fs::read(TEST_VOL_REKEY).unwrap();
+
+        rekey_header(TEST_VOL_REKEY, PASS_OLD, 0, &SALT_NEW).expect("Failed to rekey header");
+
+        // The salt is stored in clear at the start of both headers.
+        let after = fs::read(TEST_VOL_REKEY).unwrap();
+        let backup_offset = (size - 131072) as usize;
+        assert_eq!(&after[0..64], &SALT_NEW[..]);
+        assert_eq!(&after[backup_offset..backup_offset + 64], &SALT_NEW[..]);
+        assert_ne!(&after[64..512], &before[64..512]);
+        // The data area is untouched because the master keys did not change.
+        assert_eq!(&after[131072..backup_offset], &before[131072..backup_offset]);
+
+        let handle = create_context(
+            PASS_OLD,
+            &after[..131072],
+            0,
+            0,
+            None,
+            0,
+            None,
+            0,
+            size,
+            None,
+            false,
+        ).expect("Failed to mount volume after header rotation");
+        close_context(handle);
+
+        cleanup_path(TEST_VOL_REKEY);
+    }
+
+    #[test]
+    fn test_change_keyfiles_requires_new_keyfile_set() {
+        cleanup_path(TEST_VOL_KEYFILES);
+        let size = 1024 * 1024;
+        let old_keyfile: &[u8] = b"old keyfile contents";
+        let new_keyfile: &[u8] = b"new keyfile contents";
+        let mixed = crate::keyfile::apply_keyfiles(PASS_OLD, &[old_keyfile]).unwrap();
+        let master_key = distinct_master_key(64);
+        create_volume(
+            TEST_VOL_KEYFILES,
+            &mixed,
+            1,
+            size,
+            &SALT_OLD,
+            &master_key,
+            CipherType::Aes,
+            PrfAlgorithm::Sha512,
+            None,
+            FilesystemType::Fat32,
+        ).expect("Failed to create volume");
+
+        // The old keyfile set must unlock the volume.
+        let wrong = change_keyfiles(TEST_VOL_KEYFILES, PASS_OLD, 1, &[], &[new_keyfile], &SALT_NEW, None);
+        assert!(wrong.is_err());
+
+        change_keyfiles(TEST_VOL_KEYFILES, PASS_OLD, 1, &[old_keyfile], &[new_keyfile], &SALT_NEW, None)
+            .expect("Failed to change keyfiles");
+
+        let content = fs::read(TEST_VOL_KEYFILES).unwrap();
+        let header = &content[..131072];
+        let backup = &content[(size - 131072) as usize..];
+        for (keyfiles, should_mount) in [(&[old_keyfile][..], false), (&[][..], false), (&[new_keyfile][..], true)] {
+            let res = create_context_with_keyfiles(PASS_OLD, keyfiles, header, 1, 0, None, 0, None, 0, size, None, false);
+            assert_eq!(res.is_ok(), should_mount);
+            if let Ok(handle) = res {
+                close_context(handle);
+            }
+        }
+        // The backup header carries the new keyfile set too.
+        let handle = create_context_with_keyfiles(PASS_OLD, &[new_keyfile], backup, 1, 0, None, 0, None, 0, 0, None, false)
+            .expect("Backup header should mount with the new keyfile");
+        close_context(handle);
+
+        cleanup_path(TEST_VOL_KEYFILES);
+    }
 }